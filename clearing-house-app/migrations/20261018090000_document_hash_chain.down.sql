@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_documents_process_id_previous_hash;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS hash,
+    DROP COLUMN IF EXISTS previous_hash;
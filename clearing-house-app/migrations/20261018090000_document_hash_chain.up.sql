@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE documents
+    ADD COLUMN previous_hash VARCHAR,
+    ADD COLUMN hash          VARCHAR;
+
+-- Every document can only be succeeded by exactly one other document of the same process
+CREATE UNIQUE INDEX idx_documents_process_id_previous_hash ON documents (process_id, previous_hash);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_merkle_roots_process_id;
+DROP TABLE IF EXISTS merkle_roots;
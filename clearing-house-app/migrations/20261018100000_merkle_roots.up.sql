@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE merkle_roots
+(
+    id           SERIAL PRIMARY KEY,
+    process_id   INTEGER REFERENCES processes (id), -- NULL for global roots
+    tree_size    BIGINT    NOT NULL,
+    root         VARCHAR   NOT NULL,
+    covers_up_to INTEGER,
+    signature    VARCHAR   NOT NULL,
+    created_at   TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_merkle_roots_process_id ON merkle_roots (process_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS tombstones;
+DROP TABLE IF EXISTS deletion_consents;
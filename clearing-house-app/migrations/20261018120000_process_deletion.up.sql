@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TABLE deletion_consents
+(
+    process_id INTEGER   NOT NULL REFERENCES processes (id),
+    client_id  INTEGER   NOT NULL REFERENCES clients (id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (process_id, client_id)
+);
+
+-- Erased processes keep their row in 'processes', so Merkle roots stay verifiable
+CREATE TABLE tombstones
+(
+    process_id   INTEGER PRIMARY KEY REFERENCES processes (id),
+    owners       JSONB     NOT NULL,
+    consents     JSONB     NOT NULL,
+    chain_length BIGINT    NOT NULL,
+    chain_head   VARCHAR,
+    deleted_at   TIMESTAMP NOT NULL,
+    signature    VARCHAR   NOT NULL
+);
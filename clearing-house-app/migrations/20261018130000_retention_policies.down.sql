@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS retention_policies;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS expired_at;
+ALTER TABLE processes
+    DROP COLUMN IF EXISTS closed_at;
@@ -0,0 +1,16 @@
+-- Add up migration script here
+ALTER TABLE processes
+    ADD COLUMN closed_at TIMESTAMP;
+
+-- Expired documents keep their hashes, so the hash chain and Merkle roots stay verifiable
+ALTER TABLE documents
+    ADD COLUMN expired_at TIMESTAMP;
+
+-- The policy without process is the global default for all processes without own policy
+CREATE TABLE retention_policies
+(
+    id              SERIAL PRIMARY KEY,
+    process_id      INTEGER UNIQUE REFERENCES processes (id),
+    retention_days  INTEGER NOT NULL,
+    retention_start VARCHAR NOT NULL
+);
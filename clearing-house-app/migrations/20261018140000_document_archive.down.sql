@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS archived_at;
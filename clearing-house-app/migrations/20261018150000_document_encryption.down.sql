@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS key_version;
+DROP TABLE IF EXISTS data_keys;
@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- Data keys of the processes, only stored wrapped by a master key
+CREATE TABLE data_keys
+(
+    process_id    INTEGER   NOT NULL REFERENCES processes (id),
+    key_version   INTEGER   NOT NULL,
+    master_key_id VARCHAR   NOT NULL,
+    wrapped_key   BYTEA     NOT NULL,
+    created_at    TIMESTAMP NOT NULL,
+    PRIMARY KEY (process_id, key_version)
+);
+
+CREATE INDEX idx_data_keys_master_key_id ON data_keys (master_key_id);
+
+-- Version of the data key the document is encrypted with, NULL for unencrypted documents
+ALTER TABLE documents
+    ADD COLUMN key_version INTEGER;
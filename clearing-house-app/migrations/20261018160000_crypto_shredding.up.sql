@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Shredded processes keep their documents, but their data keys are destroyed
+CREATE TABLE shred_certificates
+(
+    process_id   INTEGER PRIMARY KEY REFERENCES processes (id),
+    owners       JSONB     NOT NULL,
+    consents     JSONB     NOT NULL,
+    key_versions JSONB     NOT NULL,
+    chain_length BIGINT    NOT NULL,
+    chain_head   VARCHAR,
+    shredded_at  TIMESTAMP NOT NULL,
+    signature    VARCHAR   NOT NULL
+);
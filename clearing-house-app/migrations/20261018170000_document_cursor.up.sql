@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Keyset pagination of documents orders by creation time and id
+CREATE INDEX idx_documents_process_id_created_at_id ON documents (process_id, created_at, id);
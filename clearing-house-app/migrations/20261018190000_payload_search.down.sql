@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_documents_payload_tsv;
+
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS payload_tsv;
+
+DROP FUNCTION IF EXISTS payload_text(BYTEA);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Payloads are stored as UTF-8, which is only immutable in a UTF-8 database
+CREATE FUNCTION payload_text(payload BYTEA) RETURNS TEXT
+    LANGUAGE SQL IMMUTABLE STRICT
+    AS $$ SELECT convert_from(payload, 'UTF8') $$;
+
+-- Full-text index over the payloads, encrypted payloads are not indexed
+ALTER TABLE documents
+    ADD COLUMN payload_tsv TSVECTOR GENERATED ALWAYS AS (
+        CASE WHEN key_version IS NULL THEN to_tsvector('simple'::regconfig, COALESCE(payload_text(payload), '')) END
+    ) STORED;
+
+CREATE INDEX idx_documents_payload_tsv ON documents USING GIN (payload_tsv);
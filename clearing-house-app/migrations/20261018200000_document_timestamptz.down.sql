@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE documents
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
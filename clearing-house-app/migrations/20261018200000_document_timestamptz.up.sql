@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Creation times were stored as naive timestamps in UTC
+ALTER TABLE documents
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC';
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- IDS message context (@context) of the logged message as JSON, NULL for documents logged before
+ALTER TABLE documents
+    ADD COLUMN message_context JSONB;
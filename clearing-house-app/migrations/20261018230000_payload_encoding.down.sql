@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_documents_payload_tsv;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS payload_tsv;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS payload_encoding;
+ALTER TABLE documents
+    ADD COLUMN payload_tsv TSVECTOR GENERATED ALWAYS AS (
+        CASE WHEN key_version IS NULL THEN to_tsvector('simple'::regconfig, COALESCE(payload_text(payload), '')) END
+    ) STORED;
+
+CREATE INDEX idx_documents_payload_tsv ON documents USING GIN (payload_tsv);
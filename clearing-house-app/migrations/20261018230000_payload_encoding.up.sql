@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Encoding of binary payloads, which are stored as they were received, NULL for text payloads
+ALTER TABLE documents
+    ADD COLUMN payload_encoding TEXT;
+
+-- Binary payloads are not valid UTF-8, so they are excluded from the full-text index
+DROP INDEX idx_documents_payload_tsv;
+ALTER TABLE documents
+    DROP COLUMN payload_tsv;
+ALTER TABLE documents
+    ADD COLUMN payload_tsv TSVECTOR GENERATED ALWAYS AS (
+        CASE WHEN key_version IS NULL AND payload_encoding IS NULL
+            THEN to_tsvector('simple'::regconfig, COALESCE(payload_text(payload), '')) END
+    ) STORED;
+
+CREATE INDEX idx_documents_payload_tsv ON documents USING GIN (payload_tsv);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS merkle_roots;
+DROP TABLE IF EXISTS documents;
+DROP TABLE IF EXISTS process_owners;
+DROP TABLE IF EXISTS clients;
+DROP TABLE IF EXISTS processes;
@@ -0,0 +1,60 @@
+-- Add up migration script here
+CREATE TABLE processes
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    process_id TEXT UNIQUE NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE clients
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id  TEXT UNIQUE NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE process_owners
+(
+    process_id INTEGER NOT NULL REFERENCES processes (id),
+    client_id  INTEGER NOT NULL REFERENCES clients (id),
+    PRIMARY KEY (process_id, client_id)
+);
+
+CREATE TABLE documents
+(
+    id                  BLOB PRIMARY KEY,
+    process_id          INTEGER NOT NULL REFERENCES processes (id),
+    created_at          TEXT    NOT NULL,
+    model_version       TEXT    NOT NULL,
+    correlation_message TEXT,
+    transfer_contract   TEXT,
+    issued              TEXT,
+    issuer_connector    TEXT    NOT NULL,
+    content_version     TEXT,
+    recipient_connector TEXT,
+    sender_agent        TEXT,
+    recipient_agent     TEXT,
+    payload             BLOB,
+    payload_type        TEXT,
+    message_id          TEXT,
+    previous_hash       TEXT,
+    hash                TEXT
+);
+
+CREATE INDEX idx_documents_process_id_created_at ON documents (process_id, created_at);
+
+-- Every document can only be succeeded by exactly one other document of the same process
+CREATE UNIQUE INDEX idx_documents_process_id_previous_hash ON documents (process_id, previous_hash);
+
+CREATE TABLE merkle_roots
+(
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    process_id   INTEGER REFERENCES processes (id), -- NULL for global roots
+    tree_size    INTEGER NOT NULL,
+    root         TEXT    NOT NULL,
+    covers_up_to INTEGER,
+    signature    TEXT    NOT NULL,
+    created_at   TEXT    NOT NULL
+);
+
+CREATE INDEX idx_merkle_roots_process_id ON merkle_roots (process_id);
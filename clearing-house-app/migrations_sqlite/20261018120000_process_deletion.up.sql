@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TABLE deletion_consents
+(
+    process_id INTEGER NOT NULL REFERENCES processes (id),
+    client_id  INTEGER NOT NULL REFERENCES clients (id),
+    created_at TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (process_id, client_id)
+);
+
+-- Erased processes keep their row in 'processes', so Merkle roots stay verifiable
+CREATE TABLE tombstones
+(
+    process_id   INTEGER PRIMARY KEY REFERENCES processes (id),
+    owners       TEXT    NOT NULL,
+    consents     TEXT    NOT NULL,
+    chain_length INTEGER NOT NULL,
+    chain_head   TEXT,
+    deleted_at   TEXT    NOT NULL,
+    signature    TEXT    NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS retention_policies;
+ALTER TABLE documents DROP COLUMN expired_at;
+ALTER TABLE processes DROP COLUMN closed_at;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE documents DROP COLUMN archived_at;
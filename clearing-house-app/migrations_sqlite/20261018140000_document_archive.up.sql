@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Archived documents keep their hashes, their payload is moved to the archive
+ALTER TABLE documents ADD COLUMN archived_at TEXT;
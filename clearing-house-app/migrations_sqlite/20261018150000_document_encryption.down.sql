@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE documents DROP COLUMN key_version;
+DROP TABLE IF EXISTS data_keys;
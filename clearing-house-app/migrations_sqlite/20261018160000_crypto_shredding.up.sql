@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Shredded processes keep their documents, but their data keys are destroyed
+CREATE TABLE shred_certificates
+(
+    process_id   INTEGER PRIMARY KEY REFERENCES processes (id),
+    owners       TEXT    NOT NULL,
+    consents     TEXT    NOT NULL,
+    key_versions TEXT    NOT NULL,
+    chain_length INTEGER NOT NULL,
+    chain_head   TEXT,
+    shredded_at  TEXT    NOT NULL,
+    signature    TEXT    NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_documents_process_id_created_at_id;
+CREATE INDEX idx_documents_process_id_created_at ON documents (process_id, created_at);
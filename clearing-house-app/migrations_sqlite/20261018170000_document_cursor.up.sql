@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Keyset pagination of documents orders by creation time and id
+DROP INDEX IF EXISTS idx_documents_process_id_created_at;
+CREATE INDEX idx_documents_process_id_created_at_id ON documents (process_id, created_at, id);
@@ -0,0 +1,13 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_documents_message_id;
+DROP INDEX IF EXISTS idx_documents_payload_type;
+DROP INDEX IF EXISTS idx_documents_transfer_contract_index;
+DROP INDEX IF EXISTS idx_documents_transfer_contract;
+DROP INDEX IF EXISTS idx_documents_correlation_message;
+DROP INDEX IF EXISTS idx_documents_sender_agent;
+DROP INDEX IF EXISTS idx_documents_issuer_connector;
+DROP INDEX IF EXISTS idx_documents_message_type;
+ALTER TABLE documents
+    DROP COLUMN transfer_contract_index;
+ALTER TABLE documents
+    DROP COLUMN message_type;
@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- IDS message type (@type) of the logged message, NULL for documents logged before
+ALTER TABLE documents
+    ADD COLUMN message_type TEXT;
+-- Blind index of the transfer contract of encrypted documents
+ALTER TABLE documents
+    ADD COLUMN transfer_contract_index TEXT;
+
+CREATE INDEX idx_documents_message_type ON documents (process_id, message_type);
+CREATE INDEX idx_documents_issuer_connector ON documents
+    (process_id, COALESCE(json_extract(issuer_connector, '$."@id"'), json_extract(issuer_connector, '$')));
+CREATE INDEX idx_documents_sender_agent ON documents (process_id, sender_agent);
+CREATE INDEX idx_documents_correlation_message ON documents (process_id, correlation_message);
+CREATE INDEX idx_documents_transfer_contract ON documents (process_id, transfer_contract);
+CREATE INDEX idx_documents_transfer_contract_index ON documents (process_id, transfer_contract_index);
+CREATE INDEX idx_documents_payload_type ON documents (process_id, payload_type);
+CREATE INDEX idx_documents_message_id ON documents (process_id, message_id);
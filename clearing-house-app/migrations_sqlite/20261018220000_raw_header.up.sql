@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Multipart header of the logged message exactly as it was received, NULL for documents logged before
+ALTER TABLE documents
+    ADD COLUMN raw_header TEXT;
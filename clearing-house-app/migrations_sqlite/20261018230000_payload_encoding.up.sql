@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Encoding of binary payloads, which are stored as they were received, NULL for text payloads
+ALTER TABLE documents
+    ADD COLUMN payload_encoding TEXT;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Digest of payload, transfer contract and raw header, which the hash commits to instead of the
+-- content, so the hash can be recomputed after the payload was purged or encrypted
+ALTER TABLE documents
+    ADD COLUMN content_sha256 TEXT;
@@ -4,6 +4,8 @@ use std::fmt::Display;
 #[derive(Debug, serde::Deserialize)]
 pub(crate) struct CHConfig {
     pub(crate) database_url: String,
+    /// Clears the database on startup, the in-memory backend always starts empty
+    #[cfg_attr(not(any(feature = "postgres", feature = "sqlite")), allow(dead_code))]
     pub(crate) clear_db: bool,
     pub(crate) issuer: String,
     #[serde(default)]
@@ -16,9 +18,65 @@ pub(crate) struct CHConfig {
     pub(crate) token_scope: String,
     #[serde(default)]
     pub(crate) static_process_owner: Option<String>,
+    /// Interval in seconds for publishing Merkle roots
+    #[serde(default)]
+    pub(crate) merkle_root_interval: Option<u64>,
+    /// Content of the receipts: the full payload (legacy) or digests of header and payload
+    #[serde(default)]
+    pub(crate) receipt_mode: crate::model::process::ReceiptMode,
+    /// Number of owners, which must consent to erase a process (default: all owners). The static
+    /// process owner does not count as owner.
+    #[serde(default)]
+    pub(crate) deletion_quorum: Option<usize>,
+    /// Number of days documents of processes without own retention policy are kept (default: forever)
+    #[serde(default)]
+    pub(crate) retention_days: Option<u32>,
+    /// Start of the retention period of the global retention policy
+    #[serde(default)]
+    pub(crate) retention_start: crate::model::process::RetentionStart,
+    /// Interval in seconds for expiring documents
+    #[serde(default)]
+    pub(crate) retention_interval: Option<u64>,
+    /// Directory of the cold archive, documents are not archived if not set
+    #[serde(default)]
+    pub(crate) archive_path: Option<String>,
+    /// Number of days after the end of a month, before its documents are archived
+    #[serde(default)]
+    pub(crate) archive_after_days: Option<u32>,
+    /// Interval in seconds for archiving documents
+    #[serde(default)]
+    pub(crate) archive_interval: Option<u64>,
+    /// Base64 encoded master keys for encryption at rest, separated by commas, starting with the
+    /// current key. Documents are stored unencrypted if neither this nor `encryption_key_file` is set.
+    #[serde(default)]
+    pub(crate) encryption_key: Option<String>,
+    /// File containing the master keys, one per line, starting with the current key
+    #[serde(default)]
+    pub(crate) encryption_key_file: Option<String>,
+    /// Maximum size in bytes of requests logging a message
+    #[serde(default)]
+    pub(crate) max_log_size: Option<usize>,
+    /// Maximum size in bytes of all other requests
+    #[serde(default)]
+    pub(crate) max_request_size: Option<usize>,
+    /// Time in seconds before its expiry, from which the DAT of the Clearing House is refreshed
+    #[serde(default)]
+    pub(crate) dat_refresh_margin: Option<u64>,
     performance_tracing: Option<bool>,
 }
 
+impl CHConfig {
+    /// Body limits of the routes, falling back to the defaults
+    pub(crate) fn body_limits(&self) -> crate::ports::BodyLimits {
+        use crate::model::constants::{DEFAULT_MAX_LOG_SIZE, DEFAULT_MAX_REQUEST_SIZE};
+
+        crate::ports::BodyLimits {
+            log: self.max_log_size.unwrap_or(DEFAULT_MAX_LOG_SIZE),
+            other: self.max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE),
+        }
+    }
+}
+
 /// Contains the log level for the application
 #[derive(Debug, PartialEq, serde::Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -80,6 +138,19 @@ pub(crate) fn read_config(config_file_override: Option<&std::path::Path>) -> CHC
         .expect("Failure to parse configuration! Exiting...")
 }
 
+impl CHConfig {
+    /// Master keys for encryption at rest from `encryption_key` or `encryption_key_file`
+    pub(crate) fn key_ring(&self) -> anyhow::Result<Option<crate::model::crypto::KeyRing>> {
+        let keys = match (&self.encryption_key, &self.encryption_key_file) {
+            (Some(keys), _) => keys.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)?,
+            (None, None) => return Ok(None),
+        };
+
+        crate::model::crypto::KeyRing::parse(&keys).map(Some)
+    }
+}
+
 /// Configure logging based on environment variable `RUST_LOG`
 pub(crate) fn configure_logging(config: &CHConfig) {
     if std::env::var("RUST_LOG").is_err() {
@@ -163,6 +234,13 @@ p12_password = "Password1"  # Optional
 daps_token_url = "http://localhost:4567/jwks.json"
 daps_certs_url = "http://localhost:4567/token"
 token_scope = "idsc:IDS_CONNECTORS_ALL"
+receipt_mode = "sha512"
+deletion_quorum = 2
+retention_days = 3650
+retention_start = "closed"
+archive_path = "data/archive"
+archive_after_days = 365
+encryption_key_file = "keys/master.key"
 "#;
 
         // Write to file
@@ -180,5 +258,22 @@ token_scope = "idsc:IDS_CONNECTORS_ALL"
         assert_eq!(conf.log_level, Some(super::LogLevel::Error));
         assert_eq!(conf.static_process_owner, Some("ABC".to_string()));
         assert_eq!(conf.issuer, "https://example.com");
+        assert_eq!(
+            conf.receipt_mode,
+            crate::model::process::ReceiptMode::Sha512
+        );
+        assert_eq!(conf.deletion_quorum, Some(2));
+        assert_eq!(conf.retention_days, Some(3650));
+        assert_eq!(
+            conf.retention_start,
+            crate::model::process::RetentionStart::Closed
+        );
+        assert_eq!(conf.archive_path, Some("data/archive".to_string()));
+        assert_eq!(conf.archive_after_days, Some(365));
+        assert_eq!(
+            conf.encryption_key_file,
+            Some("keys/master.key".to_string())
+        );
+        assert!(conf.key_ring().is_err());
     }
 }
@@ -0,0 +1,1049 @@
+//! In-memory implementations of the `ProcessStore` and `DocumentStore`, mainly for tests and
+//! development setups without a database. All data is lost when the application stops.
+
+use crate::model::crypto::WrappedDataKey;
+use crate::model::document::{message_type_name, Document, DocumentCursor, DocumentFilter};
+use crate::model::ids::InfoModelId;
+use crate::model::merkle::MerkleRoot;
+use crate::model::process::{
+    Process, ProcessInfo, Retention, RetentionPolicy, ShredCertificate, Tombstone,
+};
+use crate::model::search::{self, SearchHit};
+use crate::model::SortingOrder;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Tables of the in-memory database
+#[derive(Default)]
+struct Tables {
+    processes: BTreeMap<String, Process>,
+    /// Creation time of the processes
+    created_at: BTreeMap<String, chrono::DateTime<chrono::Utc>>,
+    /// Clients, which consented to the erasure of a process
+    deletion_consents: BTreeMap<String, Vec<String>>,
+    tombstones: BTreeMap<String, Tombstone>,
+    shred_certificates: BTreeMap<String, ShredCertificate>,
+    /// Retention policies, the policy without process is the global policy
+    retention_policies: BTreeMap<Option<String>, RetentionPolicy>,
+    /// Time the processes were closed
+    closed_at: BTreeMap<String, chrono::DateTime<chrono::Utc>>,
+    /// Documents in insertion order
+    documents: Vec<Document<String>>,
+    /// Data keys by process and version
+    data_keys: BTreeMap<(String, i32), WrappedDataKey>,
+    /// Merkle roots, the id of a root is its position + 1
+    merkle_roots: Vec<MerkleRoot>,
+}
+
+impl Tables {
+    /// Retention of the process `pid`, falling back to the global policy
+    fn retention(&self, pid: &str) -> Retention {
+        Retention {
+            policy: self
+                .retention_policies
+                .get(&Some(pid.to_string()))
+                .or_else(|| self.retention_policies.get(&None))
+                .cloned(),
+            closed_at: self.closed_at.get(pid).copied(),
+        }
+    }
+}
+
+/// In-memory database shared by the stores, the equivalent of a connection pool
+#[derive(Default)]
+pub(crate) struct MemoryDb(RwLock<Tables>);
+
+impl MemoryDb {
+    fn read(&self) -> anyhow::Result<RwLockReadGuard<'_, Tables>> {
+        self.0
+            .read()
+            .map_err(|_| anyhow::anyhow!("In-memory database is poisoned"))
+    }
+
+    fn write(&self) -> anyhow::Result<RwLockWriteGuard<'_, Tables>> {
+        self.0
+            .write()
+            .map_err(|_| anyhow::anyhow!("In-memory database is poisoned"))
+    }
+}
+
+pub(crate) struct MemoryProcessStore {
+    db: Arc<MemoryDb>,
+}
+
+impl MemoryProcessStore {
+    pub(crate) fn new(db: Arc<MemoryDb>) -> Self {
+        Self { db }
+    }
+}
+
+impl super::ProcessStore for MemoryProcessStore {
+    async fn get_processes(&self) -> anyhow::Result<Vec<Process>> {
+        Ok(self.db.read()?.processes.values().cloned().collect())
+    }
+
+    async fn delete_process(&self, pid: &str, tombstone: &Tombstone) -> anyhow::Result<bool> {
+        let mut tables = self.db.write()?;
+        if !tables.processes.contains_key(pid) {
+            return Ok(false);
+        }
+        if tables.tombstones.contains_key(pid) {
+            anyhow::bail!("Process '{pid}' is already erased");
+        }
+
+        // The process itself is kept, as it is referenced by the tombstone and Merkle roots
+        tables.tombstones.insert(pid.to_string(), tombstone.clone());
+        tables.documents.retain(|d| d.pid != pid);
+        tables.data_keys.retain(|(p, _), _| p != pid);
+        tables.deletion_consents.remove(pid);
+        if let Some(process) = tables.processes.get_mut(pid) {
+            process.owners.clear();
+        }
+        Ok(true)
+    }
+
+    async fn add_deletion_consent(&self, pid: &str, owner: &str) -> anyhow::Result<Vec<String>> {
+        let mut tables = self.db.write()?;
+        if !tables.processes.contains_key(pid) {
+            anyhow::bail!("Process '{pid}' does not exist");
+        }
+
+        let consents = tables.deletion_consents.entry(pid.to_string()).or_default();
+        if !consents.iter().any(|c| c == owner) {
+            consents.push(owner.to_string());
+            consents.sort();
+        }
+        Ok(consents.clone())
+    }
+
+    async fn get_deletion_consents(&self, pid: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .db
+            .read()?
+            .deletion_consents
+            .get(pid)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_tombstone(&self, pid: &str) -> anyhow::Result<Option<Tombstone>> {
+        Ok(self.db.read()?.tombstones.get(pid).cloned())
+    }
+
+    async fn shred_process(
+        &self,
+        pid: &str,
+        certificate: &ShredCertificate,
+    ) -> anyhow::Result<bool> {
+        let mut tables = self.db.write()?;
+        if !tables.processes.contains_key(pid) {
+            return Ok(false);
+        }
+        if tables.shred_certificates.contains_key(pid) {
+            anyhow::bail!("Process '{pid}' is already shredded");
+        }
+
+        // The documents are kept, without their data keys they are only ciphertext
+        tables
+            .shred_certificates
+            .insert(pid.to_string(), certificate.clone());
+        tables.data_keys.retain(|(p, _), _| p != pid);
+        tables.deletion_consents.remove(pid);
+        Ok(true)
+    }
+
+    async fn get_shred_certificate(&self, pid: &str) -> anyhow::Result<Option<ShredCertificate>> {
+        Ok(self.db.read()?.shred_certificates.get(pid).cloned())
+    }
+
+    async fn exists_process(&self, pid: &str) -> anyhow::Result<bool> {
+        Ok(self.db.read()?.processes.contains_key(pid))
+    }
+
+    async fn get_process(&self, pid: &str) -> anyhow::Result<Option<Process>> {
+        Ok(self.db.read()?.processes.get(pid).cloned())
+    }
+
+    async fn store_process(&self, process: Process) -> anyhow::Result<()> {
+        let mut tables = self.db.write()?;
+        if tables.processes.contains_key(&process.id) {
+            anyhow::bail!("Process '{}' already exists", process.id);
+        }
+
+        tables
+            .created_at
+            .insert(process.id.clone(), chrono::Utc::now());
+        tables.processes.insert(process.id.clone(), process);
+        Ok(())
+    }
+
+    async fn get_processes_for_owner(
+        &self,
+        owner: &str,
+        page: u64,
+        size: u64,
+    ) -> anyhow::Result<Vec<ProcessInfo>> {
+        let offset = usize::try_from((page - 1) * size)?;
+        let limit = usize::try_from(size)?;
+        let tables = self.db.read()?;
+
+        let mut processes: Vec<_> = tables
+            .processes
+            .values()
+            .filter(|p| p.is_authorized(owner))
+            .map(|p| ProcessInfo {
+                pid: p.id.clone(),
+                created_at: tables.created_at.get(&p.id).copied(),
+                owners: p.owners.clone(),
+                document_count: tables.documents.iter().filter(|d| d.pid == p.id).count() as u64,
+            })
+            .collect();
+        processes.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.pid.cmp(&b.pid)));
+
+        Ok(processes.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn update_owners(
+        &self,
+        pid: &str,
+        added: &[String],
+        removed: &[String],
+    ) -> anyhow::Result<()> {
+        let mut tables = self.db.write()?;
+        let process = tables
+            .processes
+            .get_mut(pid)
+            .ok_or_else(|| anyhow::anyhow!("Process '{pid}' does not exist"))?;
+
+        process.owners.retain(|o| !removed.contains(o));
+        for o in added {
+            if !process.owners.contains(o) {
+                process.owners.push(o.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_retention_policy(
+        &self,
+        pid: Option<&str>,
+        policy: Option<&RetentionPolicy>,
+    ) -> anyhow::Result<()> {
+        let mut tables = self.db.write()?;
+        if let Some(pid) = pid {
+            if !tables.processes.contains_key(pid) {
+                anyhow::bail!("Process '{pid}' does not exist");
+            }
+        }
+
+        let pid = pid.map(ToString::to_string);
+        match policy {
+            Some(policy) => tables.retention_policies.insert(pid, policy.clone()),
+            None => tables.retention_policies.remove(&pid),
+        };
+        Ok(())
+    }
+
+    async fn get_retention(&self, pid: &str) -> anyhow::Result<Retention> {
+        let tables = self.db.read()?;
+        if !tables.processes.contains_key(pid) {
+            anyhow::bail!("Process '{pid}' does not exist");
+        }
+
+        Ok(tables.retention(pid))
+    }
+
+    async fn close_process(
+        &self,
+        pid: &str,
+        closed_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let mut tables = self.db.write()?;
+        if !tables.processes.contains_key(pid) {
+            anyhow::bail!("Process '{pid}' does not exist");
+        }
+
+        tables.closed_at.entry(pid.to_string()).or_insert(closed_at);
+        Ok(())
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.db.read().map(|_| ())
+    }
+}
+
+pub(crate) struct MemoryDocumentStore {
+    db: Arc<MemoryDb>,
+}
+
+impl MemoryDocumentStore {
+    pub(crate) fn new(db: Arc<MemoryDb>) -> Self {
+        Self { db }
+    }
+}
+
+impl super::DocumentStore for MemoryDocumentStore {
+    async fn add_document(&self, doc: Document<String>) -> anyhow::Result<bool> {
+        let mut tables = self.db.write()?;
+
+        check_document(&tables, &doc)?;
+        tables.documents.push(doc);
+        Ok(true)
+    }
+
+    async fn add_documents(&self, docs: Vec<Document<String>>) -> anyhow::Result<()> {
+        let mut tables = self.db.write()?;
+
+        // Either all or none of the documents are stored, like in a transaction
+        let stored = tables.documents.len();
+        for doc in docs {
+            if let Err(e) = check_document(&tables, &doc) {
+                tables.documents.truncate(stored);
+                return Err(e);
+            }
+            tables.documents.push(doc);
+        }
+        Ok(())
+    }
+
+    async fn exists_document(&self, id: &uuid::Uuid) -> anyhow::Result<bool> {
+        Ok(self.db.read()?.documents.iter().any(|d| d.id == *id))
+    }
+
+    async fn get_document(&self, id: &str, pid: &str) -> anyhow::Result<Option<Document<String>>> {
+        Ok(self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .find(|d| d.id.to_string() == id && d.pid == pid)
+            .cloned())
+    }
+
+    async fn get_document_by_message_id(
+        &self,
+        pid: &str,
+        message_id: &str,
+    ) -> anyhow::Result<Option<Document<String>>> {
+        Ok(self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid && d.content.header.id.as_deref() == Some(message_id))
+            .min_by_key(|d| (d.ts, d.id))
+            .cloned())
+    }
+
+    async fn get_documents_for_pid(
+        &self,
+        pid: &str,
+        filter: &DocumentFilter,
+        (page, size): (u64, u64),
+        sort: &SortingOrder,
+        (date_from, date_to): (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        cursor: Option<&DocumentCursor>,
+    ) -> anyhow::Result<Vec<Document<String>>> {
+        // A cursor replaces the offset, the id orders documents with the same timestamp
+        let offset = match cursor {
+            Some(_) => 0,
+            None => usize::try_from((page - 1) * size)?,
+        };
+        let limit = usize::try_from(size)?;
+        let position = |d: &Document<String>| (d.ts.to_utc(), d.id);
+        let after_cursor = |d: &Document<String>| {
+            cursor.is_none_or(|c| match sort {
+                SortingOrder::Ascending => position(d) > (c.created_at, c.id),
+                SortingOrder::Descending => position(d) < (c.created_at, c.id),
+            })
+        };
+
+        let mut docs: Vec<_> = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid && (date_from..=date_to).contains(&&d.ts.to_utc()))
+            .filter(|d| matches(filter, d) && after_cursor(d))
+            .cloned()
+            .collect();
+
+        match sort {
+            SortingOrder::Ascending => docs.sort_by_key(position),
+            SortingOrder::Descending => docs.sort_by_key(|d| std::cmp::Reverse(position(d))),
+        }
+
+        Ok(docs.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn count_documents_for_pid(
+        &self,
+        pid: &str,
+        (date_from, date_to): (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        filter: &DocumentFilter,
+    ) -> anyhow::Result<u64> {
+        let count = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid && (date_from..=date_to).contains(&&d.ts.to_utc()))
+            .filter(|d| matches(filter, d))
+            .count();
+
+        u64::try_from(count).map_err(std::convert::Into::into)
+    }
+
+    async fn search_documents(
+        &self,
+        owner: &str,
+        query: &str,
+        page: (u64, u64),
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let tables = self.db.read()?;
+        let docs = tables
+            .documents
+            .iter()
+            .filter(|d| {
+                d.key_version.is_none()
+                    && d.content.payload_encoding.is_none()
+                    && tables
+                        .processes
+                        .get(&d.pid)
+                        .is_some_and(|p| p.is_authorized(owner))
+            })
+            .cloned();
+
+        search::fallback_search(docs, &search::terms(query), page)
+    }
+
+    async fn get_chain_head(&self, pid: &str) -> anyhow::Result<Option<String>> {
+        let tables = self.db.read()?;
+        let chain = || {
+            tables
+                .documents
+                .iter()
+                .filter(|d| d.pid == pid && d.hash.is_some())
+        };
+
+        Ok(chain()
+            .filter(|d| !chain().any(|successor| successor.previous_hash == d.hash))
+            .max_by_key(|d| d.ts)
+            .and_then(|d| d.hash.clone()))
+    }
+
+    async fn get_chain_for_pid(&self, pid: &str) -> anyhow::Result<Vec<Document<String>>> {
+        let mut docs: Vec<_> = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid && d.hash.is_some())
+            .cloned()
+            .collect();
+        docs.sort_by_key(|d| d.ts);
+
+        Ok(docs)
+    }
+
+    async fn get_chain_links(
+        &self,
+        pid: &str,
+    ) -> anyhow::Result<Vec<(uuid::Uuid, Option<String>, String)>> {
+        let mut docs: Vec<_> = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid)
+            .filter_map(|d| Some((d.ts, (d.id, d.previous_hash.clone(), d.hash.clone()?))))
+            .collect();
+        docs.sort_by_key(|(ts, _)| *ts);
+
+        Ok(docs.into_iter().map(|(_, link)| link).collect())
+    }
+
+    async fn get_unpublished_pids(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let tables = self.db.read()?;
+
+        Ok(tables
+            .processes
+            .keys()
+            .filter_map(|pid| {
+                let published = tables
+                    .merkle_roots
+                    .iter()
+                    .rev()
+                    .find(|r| r.pid.as_ref() == Some(pid))
+                    .map_or(0, |r| r.tree_size);
+                let chained = tables
+                    .documents
+                    .iter()
+                    .filter(|d| &d.pid == pid && d.hash.is_some())
+                    .count() as u64;
+                (chained > published).then(|| (pid.clone(), chained))
+            })
+            .collect())
+    }
+
+    async fn add_merkle_root(&self, root: &MerkleRoot) -> anyhow::Result<i32> {
+        let mut tables = self.db.write()?;
+        let id = i32::try_from(tables.merkle_roots.len() + 1)?;
+
+        tables.merkle_roots.push(MerkleRoot {
+            id: Some(id),
+            ..root.clone()
+        });
+        Ok(id)
+    }
+
+    async fn get_latest_merkle_root(
+        &self,
+        pid: Option<&str>,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Option<MerkleRoot>> {
+        Ok(self
+            .db
+            .read()?
+            .merkle_roots
+            .iter()
+            .rev()
+            .filter(|r| max_id.is_none_or(|max_id| r.id <= Some(max_id)))
+            .find(|r| r.pid.as_deref() == pid)
+            .cloned())
+    }
+
+    async fn get_latest_process_roots(
+        &self,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Vec<MerkleRoot>> {
+        let mut latest = BTreeMap::new();
+        for root in self
+            .db
+            .read()?
+            .merkle_roots
+            .iter()
+            .filter(|r| max_id.is_none_or(|max_id| r.id <= Some(max_id)))
+        {
+            if let Some(pid) = &root.pid {
+                latest.insert(pid.clone(), root.clone());
+            }
+        }
+
+        Ok(latest.into_values().collect())
+    }
+
+    async fn expire_documents(&self, now: chrono::DateTime<chrono::Utc>) -> anyhow::Result<u64> {
+        let mut tables = self.db.write()?;
+
+        let expired: Vec<usize> = tables
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| {
+                d.expired_at.is_none()
+                    && tables
+                        .retention(&d.pid)
+                        .expires_at(d.ts.to_utc())
+                        .is_some_and(|t| t <= now)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &expired {
+            tables.documents[i].expire(now);
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    async fn get_archivable_pids(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<String>> {
+        let pids: std::collections::BTreeSet<_> = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| is_archivable(d, before))
+            .map(|d| d.pid.clone())
+            .collect();
+
+        Ok(pids.into_iter().collect())
+    }
+
+    async fn get_archivable_documents(
+        &self,
+        pid: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<Document<String>>> {
+        let mut docs: Vec<_> = self
+            .db
+            .read()?
+            .documents
+            .iter()
+            .filter(|d| d.pid == pid && is_archivable(d, before))
+            .cloned()
+            .collect();
+        docs.sort_by_key(|d| d.ts);
+
+        Ok(docs)
+    }
+
+    async fn archive_documents(
+        &self,
+        ids: &[uuid::Uuid],
+        archived_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        for doc in self
+            .db
+            .write()?
+            .documents
+            .iter_mut()
+            .filter(|d| ids.contains(&d.id))
+        {
+            doc.archive(archived_at);
+        }
+        Ok(())
+    }
+
+    async fn get_data_keys(&self, pid: &str) -> anyhow::Result<Vec<WrappedDataKey>> {
+        Ok(self
+            .db
+            .read()?
+            .data_keys
+            .values()
+            .filter(|k| k.pid == pid)
+            .cloned()
+            .collect())
+    }
+
+    async fn add_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<bool> {
+        let mut tables = self.db.write()?;
+        if !tables.processes.contains_key(&key.pid) {
+            anyhow::bail!("Process '{}' does not exist", key.pid);
+        }
+        if tables.shred_certificates.contains_key(&key.pid) {
+            return Ok(false);
+        }
+
+        match tables.data_keys.entry((key.pid.clone(), key.version)) {
+            std::collections::btree_map::Entry::Occupied(_) => Ok(false),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(key.clone());
+                Ok(true)
+            }
+        }
+    }
+
+    async fn get_data_keys_not_wrapped_by(
+        &self,
+        master_key_id: &str,
+    ) -> anyhow::Result<Vec<WrappedDataKey>> {
+        Ok(self
+            .db
+            .read()?
+            .data_keys
+            .values()
+            .filter(|k| k.master_key_id != master_key_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<()> {
+        if let Some(stored) = self
+            .db
+            .write()?
+            .data_keys
+            .get_mut(&(key.pid.clone(), key.version))
+        {
+            stored.master_key_id.clone_from(&key.master_key_id);
+            stored.wrapped_key.clone_from(&key.wrapped_key);
+        }
+        Ok(())
+    }
+}
+
+/// Checks `doc` against the same constraints as in the database schema
+fn check_document(tables: &Tables, doc: &Document<String>) -> anyhow::Result<()> {
+    if !tables.processes.contains_key(&doc.pid) {
+        anyhow::bail!("Process '{}' does not exist", doc.pid);
+    }
+    if tables.documents.iter().any(|d| d.id == doc.id) {
+        anyhow::bail!("Document '{}' already exists", doc.id);
+    }
+    if doc.previous_hash.is_some()
+        && tables
+            .documents
+            .iter()
+            .any(|d| d.pid == doc.pid && d.previous_hash == doc.previous_hash)
+    {
+        anyhow::bail!("Document with the same previous hash already exists");
+    }
+    Ok(())
+}
+
+/// Checks if the document was logged before `before` and is neither archived nor expired
+fn is_archivable(doc: &Document<String>, before: chrono::DateTime<chrono::Utc>) -> bool {
+    doc.ts < before && doc.archived_at.is_none() && doc.expired_at.is_none()
+}
+
+/// Checks if `doc`, as stored, matches `filter`
+fn matches(filter: &DocumentFilter, doc: &Document<String>) -> bool {
+    let header = &doc.content.header;
+    let equals = |filter: &Option<String>, value: Option<&str>| {
+        filter.as_deref().is_none_or(|f| value == Some(f))
+    };
+    let issuer_connector = match &header.issuer_connector {
+        InfoModelId::SimpleId(id) => id,
+        InfoModelId::ComplexId(id) => &id.id,
+    };
+
+    equals(&filter.message_type, message_type_name(&header.type_message).as_deref())
+        && equals(&filter.issuer_connector, Some(issuer_connector))
+        && equals(&filter.sender_agent, Some(&header.sender_agent.to_string()))
+        && equals(&filter.correlation_message, header.correlation_message.as_deref())
+        && equals(&filter.payload_type, doc.content.payload_type.as_deref())
+        && filter.id_prefix.as_deref().is_none_or(|prefix| {
+            header.id.as_deref().is_some_and(|id| id.starts_with(prefix))
+        })
+        // Encrypted documents are only found by the blind index of the transfer contract
+        && filter.transfer_contract.as_deref().is_none_or(|contract| {
+            match &doc.transfer_contract_index {
+                Some(index) => filter.transfer_contract_indexes.contains(index),
+                None => header.transfer_contract.as_deref() == Some(contract),
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MemoryDb, MemoryDocumentStore, MemoryProcessStore};
+    use crate::db::{DocumentStore, ProcessStore};
+    use crate::model::document::{Document, DocumentFilter, GENESIS_HASH};
+    use crate::model::process::{Process, Retention, RetentionPolicy, RetentionStart, Tombstone};
+    use crate::model::SortingOrder;
+    use chrono::SubsecRound;
+    use std::sync::Arc;
+
+    fn stores() -> (MemoryProcessStore, MemoryDocumentStore) {
+        let db = Arc::new(MemoryDb::default());
+        (
+            MemoryProcessStore::new(db.clone()),
+            MemoryDocumentStore::new(db),
+        )
+    }
+
+    fn document(pid: &str, offset: i64) -> Document<String> {
+        let mut doc = crate::test_util::document(pid, &format!("payload {offset}"));
+        doc.ts += chrono::Duration::seconds(offset);
+        doc
+    }
+
+    #[tokio::test]
+    async fn processes() {
+        let (process_store, _) = stores();
+        let process = Process::new("pid".to_string(), vec!["owner".to_string()]);
+
+        process_store
+            .store_process(process.clone())
+            .await
+            .expect("Storing process");
+        assert!(process_store.store_process(process).await.is_err());
+        assert!(process_store.exists_process("pid").await.expect("Reading"));
+        assert_eq!(
+            process_store
+                .get_process("pid")
+                .await
+                .expect("Reading")
+                .map(|p| p.owners),
+            Some(vec!["owner".to_string()])
+        );
+
+        process_store
+            .update_owners("pid", &["other".to_string()], &["owner".to_string()])
+            .await
+            .expect("Updating owners");
+        assert_eq!(
+            process_store
+                .get_process("pid")
+                .await
+                .expect("Reading")
+                .map(|p| p.owners),
+            Some(vec!["other".to_string()])
+        );
+        assert!(process_store
+            .update_owners("unknown", &[], &[])
+            .await
+            .is_err());
+
+        let listed = process_store
+            .get_processes_for_owner("other", 1, 10)
+            .await
+            .expect("Listing");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].pid, "pid");
+        assert!(listed[0].created_at.is_some());
+        assert!(process_store
+            .get_processes_for_owner("owner", 1, 10)
+            .await
+            .expect("Listing")
+            .is_empty());
+
+        assert_eq!(
+            process_store
+                .add_deletion_consent("pid", "other")
+                .await
+                .expect("Consenting"),
+            vec!["other".to_string()]
+        );
+        assert_eq!(
+            process_store
+                .get_deletion_consents("pid")
+                .await
+                .expect("Reading consents"),
+            vec!["other".to_string()]
+        );
+        let tombstone = Tombstone {
+            pid: "pid".to_string(),
+            owners: vec!["other".to_string()],
+            consents: vec!["other".to_string()],
+            chain_length: 0,
+            chain_head: None,
+            deleted_at: chrono::Utc::now().trunc_subsecs(6),
+            signature: "signature".to_string(),
+        };
+        assert!(process_store
+            .delete_process("pid", &tombstone)
+            .await
+            .expect("Deleting"));
+        assert!(process_store
+            .delete_process("pid", &tombstone)
+            .await
+            .is_err());
+        assert!(!process_store
+            .delete_process("unknown", &tombstone)
+            .await
+            .expect("Deleting"));
+        assert_eq!(
+            process_store.get_tombstone("pid").await.expect("Reading"),
+            Some(tombstone)
+        );
+        assert!(process_store
+            .get_process("pid")
+            .await
+            .expect("Reading")
+            .is_some_and(|p| p.owners.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn documents() {
+        let (process_store, doc_store) = stores();
+
+        // Documents require an existing process
+        assert!(doc_store.add_document(document("pid", 0)).await.is_err());
+
+        process_store
+            .store_process(Process::new("pid".to_string(), vec![]))
+            .await
+            .expect("Storing process");
+        for offset in 0..5 {
+            doc_store
+                .add_document(document("pid", offset))
+                .await
+                .expect("Storing document");
+        }
+
+        let from = chrono::Utc::now() - chrono::Duration::hours(1);
+        let to = from + chrono::Duration::hours(2);
+        let page = doc_store
+            .get_documents_for_pid(
+                "pid",
+                &DocumentFilter::default(),
+                (2, 2),
+                &SortingOrder::Descending,
+                (&from, &to),
+                None,
+            )
+            .await
+            .expect("Reading documents");
+        assert_eq!(
+            page.iter()
+                .map(|d| d.content.payload.clone().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            vec!["payload 2", "payload 1"]
+        );
+
+        let id = page[0].id.to_string();
+        assert!(doc_store
+            .get_document(&id, "pid")
+            .await
+            .expect("Reading document")
+            .is_some());
+        assert!(doc_store
+            .get_document(&id, "other")
+            .await
+            .expect("Reading document")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn chain_head() {
+        let (process_store, doc_store) = stores();
+        process_store
+            .store_process(Process::new("pid".to_string(), vec![]))
+            .await
+            .expect("Storing process");
+        assert_eq!(
+            doc_store.get_chain_head("pid").await.expect("Reading"),
+            None
+        );
+
+        let mut first = document("pid", 0);
+        first.link_to(GENESIS_HASH).expect("Linking");
+        let mut second = document("pid", 1);
+        second
+            .link_to(first.hash.as_deref().expect("Hash was set"))
+            .expect("Linking");
+        let head = second.hash.clone();
+
+        doc_store
+            .add_document(first.clone())
+            .await
+            .expect("Storing");
+        doc_store
+            .add_document(second.clone())
+            .await
+            .expect("Storing");
+        assert_eq!(
+            doc_store.get_chain_head("pid").await.expect("Reading"),
+            head
+        );
+        assert_eq!(
+            doc_store.get_chain_links("pid").await.expect("Reading"),
+            [&first, &second]
+                .map(|d| (
+                    d.id,
+                    d.previous_hash.clone(),
+                    d.hash.clone().expect("Hash was set")
+                ))
+                .to_vec()
+        );
+
+        // A fork is rejected
+        let mut fork = document("pid", 2);
+        fork.link_to(first.hash.as_deref().expect("Hash was set"))
+            .expect("Linking");
+        assert!(doc_store.add_document(fork).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        let (process_store, doc_store) = stores();
+        for pid in ["pid", "other"] {
+            process_store
+                .store_process(Process::new(pid.to_string(), vec!["owner".to_string()]))
+                .await
+                .expect("Storing process");
+        }
+        let now = chrono::Utc::now().trunc_subsecs(6);
+        let old = document("pid", -20 * 86400);
+        for doc in [
+            old.clone(),
+            document("pid", 0),
+            document("other", -20 * 86400),
+        ] {
+            doc_store.add_document(doc).await.expect("Storing document");
+        }
+
+        // Documents are kept forever without policy
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 0);
+        assert_eq!(
+            process_store.get_retention("pid").await.expect("Reading"),
+            Retention::default()
+        );
+
+        let global = RetentionPolicy {
+            days: 10,
+            start: RetentionStart::Closed,
+        };
+        process_store
+            .set_retention_policy(None, Some(&global))
+            .await
+            .expect("Setting policy");
+        process_store
+            .set_retention_policy(
+                Some("pid"),
+                Some(&RetentionPolicy {
+                    days: 10,
+                    start: RetentionStart::Logged,
+                }),
+            )
+            .await
+            .expect("Setting policy");
+        assert!(process_store
+            .set_retention_policy(Some("unknown"), Some(&global))
+            .await
+            .is_err());
+        assert_eq!(
+            process_store
+                .get_retention("other")
+                .await
+                .expect("Reading")
+                .policy,
+            Some(global.clone())
+        );
+
+        // Only the old document of the open process expires
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 1);
+        let expired = doc_store
+            .get_document(&old.id.to_string(), "pid")
+            .await
+            .expect("Reading")
+            .expect("Document exists");
+        assert!(expired.content.payload.is_none());
+        assert_eq!(expired.expired_at, Some(now));
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 0);
+
+        // A closed process keeps the time it was first closed
+        let closed_at = now - chrono::TimeDelta::days(15);
+        process_store
+            .close_process("other", closed_at)
+            .await
+            .expect("Closing");
+        process_store
+            .close_process("other", now)
+            .await
+            .expect("Closing");
+        assert_eq!(
+            process_store
+                .get_retention("other")
+                .await
+                .expect("Reading")
+                .closed_at,
+            Some(closed_at)
+        );
+        assert!(process_store.close_process("unknown", now).await.is_err());
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 1);
+
+        // Without own policy, the global policy applies
+        process_store
+            .set_retention_policy(Some("pid"), None)
+            .await
+            .expect("Removing policy");
+        assert_eq!(
+            process_store
+                .get_retention("pid")
+                .await
+                .expect("Reading")
+                .policy,
+            Some(global)
+        );
+    }
+}
@@ -1,31 +1,274 @@
+#[cfg(feature = "memory")]
+pub(crate) mod memory_store;
+pub(crate) mod object_store;
+#[cfg(feature = "postgres")]
 pub(crate) mod postgres_document_store;
+#[cfg(feature = "postgres")]
 pub(crate) mod postgres_process_store;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+mod rows;
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite_document_store;
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite_process_store;
+use crate::model::crypto::WrappedDataKey;
 
-use crate::model::document::Document;
-use crate::model::process::Process;
+use crate::model::document::{Document, DocumentCursor, DocumentFilter};
+use crate::model::merkle::MerkleRoot;
+use crate::model::process::{
+    Process, ProcessInfo, Retention, RetentionPolicy, ShredCertificate, Tombstone,
+};
+use crate::model::search::SearchHit;
 use crate::model::SortingOrder;
+use std::future::Future;
 
-pub(crate) trait ProcessStore {
-    #[allow(dead_code)]
-    async fn get_processes(&self) -> anyhow::Result<Vec<Process>>;
-    #[allow(dead_code)]
-    async fn delete_process(&self, pid: &str) -> anyhow::Result<bool>;
-    #[allow(dead_code)]
-    async fn exists_process(&self, pid: &str) -> anyhow::Result<bool>;
-    async fn get_process(&self, pid: &str) -> anyhow::Result<Option<Process>>;
-    async fn store_process(&self, process: Process) -> anyhow::Result<()>;
+/// Storage backend, selected by the scheme of the `database_url`
+#[derive(Debug, PartialEq)]
+pub(crate) enum Backend {
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    #[cfg(feature = "memory")]
+    Memory,
+}
+
+impl Backend {
+    /// Determines the storage backend from the scheme of `database_url`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the scheme is unknown or the backend is not enabled as cargo feature.
+    pub(crate) fn from_database_url(database_url: &str) -> anyhow::Result<Self> {
+        match database_url.split_once(':').map(|(scheme, _)| scheme) {
+            #[cfg(feature = "postgres")]
+            Some("postgres" | "postgresql") => Ok(Self::Postgres),
+            #[cfg(feature = "sqlite")]
+            Some("sqlite") => Ok(Self::Sqlite),
+            #[cfg(feature = "memory")]
+            Some("memory") => Ok(Self::Memory),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported database_url '{database_url}', the backend might not be enabled"
+            )),
+        }
+    }
 }
 
-pub(crate) trait DocumentStore {
-    async fn add_document(&self, doc: Document<String>) -> anyhow::Result<bool>;
-    async fn exists_document(&self, id: &uuid::Uuid) -> anyhow::Result<bool>;
-    async fn get_document(&self, id: &str, pid: &str) -> anyhow::Result<Option<Document<String>>>;
-    async fn get_documents_for_pid(
+pub(crate) trait ProcessStore: Send + Sync + 'static {
+    #[allow(dead_code)]
+    fn get_processes(&self) -> impl Future<Output = anyhow::Result<Vec<Process>>> + Send;
+    /// Erases the documents and owners of the process `pid` and stores its `tombstone`
+    fn delete_process(
+        &self,
+        pid: &str,
+        tombstone: &Tombstone,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send;
+    /// Records the consent of `owner` to erase the process `pid` and returns all consenting clients
+    fn add_deletion_consent(
         &self,
         pid: &str,
+        owner: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+    /// Returns the clients, which consented to erase the process `pid`
+    fn get_deletion_consents(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+    fn get_tombstone(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<Tombstone>>> + Send;
+    /// Destroys the data keys of the process `pid` and stores its shred `certificate`. Returns
+    /// `false` if the process does not exist.
+    fn shred_process(
+        &self,
+        pid: &str,
+        certificate: &ShredCertificate,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send;
+    fn get_shred_certificate(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<ShredCertificate>>> + Send;
+    #[allow(dead_code)]
+    fn exists_process(&self, pid: &str) -> impl Future<Output = anyhow::Result<bool>> + Send;
+    fn get_process(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<Process>>> + Send;
+    fn store_process(&self, process: Process) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Returns a page of the processes owned by `owner`, the most recently created first
+    fn get_processes_for_owner(
+        &self,
+        owner: &str,
         page: u64,
         size: u64,
+    ) -> impl Future<Output = anyhow::Result<Vec<ProcessInfo>>> + Send;
+    /// Adds the owners `added` to and removes the owners `removed` from the process `pid`
+    fn update_owners(
+        &self,
+        pid: &str,
+        added: &[String],
+        removed: &[String],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Stores the retention `policy` of the process `pid` (or the global policy if `None`) and
+    /// removes the policy if `policy` is `None`
+    fn set_retention_policy(
+        &self,
+        pid: Option<&str>,
+        policy: Option<&RetentionPolicy>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Returns the retention of the process `pid`, falling back to the global policy
+    fn get_retention(&self, pid: &str) -> impl Future<Output = anyhow::Result<Retention>> + Send;
+    /// Closes the process `pid`, a closed process keeps the time it was first closed
+    fn close_process(
+        &self,
+        pid: &str,
+        closed_at: chrono::DateTime<chrono::Utc>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Checks that the database is usable, the document store shares the database of the process
+    /// store
+    fn ping(&self) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+pub(crate) trait DocumentStore: Send + Sync + 'static {
+    fn add_document(
+        &self,
+        doc: Document<String>,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send;
+    /// Stores all `docs` in a single transaction, either all or none of them are stored
+    fn add_documents(
+        &self,
+        docs: Vec<Document<String>>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    fn exists_document(&self, id: &uuid::Uuid)
+        -> impl Future<Output = anyhow::Result<bool>> + Send;
+    fn get_document(
+        &self,
+        id: &str,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<Document<String>>>> + Send;
+    /// Returns the first document of the process `pid` logged with the message id (`@id`)
+    /// `message_id`
+    fn get_document_by_message_id(
+        &self,
+        pid: &str,
+        message_id: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<Document<String>>>> + Send;
+    /// Returns a page of the documents of a process in the date window, which match `filter`,
+    /// ordered by creation time and id. If a `cursor` is given, the page continues after the
+    /// cursor instead of `page`.
+    fn get_documents_for_pid(
+        &self,
+        pid: &str,
+        filter: &DocumentFilter,
+        page: (u64, u64),
         sort: &SortingOrder,
-        date: (&chrono::NaiveDateTime, &chrono::NaiveDateTime),
-    ) -> anyhow::Result<Vec<Document<String>>>;
+        date: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        cursor: Option<&DocumentCursor>,
+    ) -> impl Future<Output = anyhow::Result<Vec<Document<String>>>> + Send;
+    /// Returns the number of documents of a process in the date window, which match `filter`
+    fn count_documents_for_pid(
+        &self,
+        pid: &str,
+        date: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        filter: &DocumentFilter,
+    ) -> impl Future<Output = anyhow::Result<u64>> + Send;
+    /// Returns a page of the documents of the processes owned by `owner`, whose payload matches
+    /// the full-text search `query`, ordered by rank. Encrypted payloads are not searchable.
+    fn search_documents(
+        &self,
+        owner: &str,
+        query: &str,
+        page: (u64, u64),
+    ) -> impl Future<Output = anyhow::Result<Vec<SearchHit>>> + Send;
+    /// Returns the hash of the last document in the hash chain of a process
+    fn get_chain_head(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Option<String>>> + Send;
+    /// Returns all documents of a process, which are part of the hash chain
+    fn get_chain_for_pid(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<Document<String>>>> + Send;
+    /// Returns the id, previous hash and hash of all documents of a process, which are part of the
+    /// hash chain, ordered by creation time (the order they were chained in)
+    fn get_chain_links(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<(uuid::Uuid, Option<String>, String)>>> + Send;
+    /// Returns the pids of all processes with documents not yet covered by a Merkle root, together
+    /// with their number of chained documents
+    fn get_unpublished_pids(
+        &self,
+    ) -> impl Future<Output = anyhow::Result<Vec<(String, u64)>>> + Send;
+    /// Stores a Merkle root and returns its id
+    fn add_merkle_root(
+        &self,
+        root: &MerkleRoot,
+    ) -> impl Future<Output = anyhow::Result<i32>> + Send;
+    /// Returns the latest Merkle root of the process `pid` (or the global root if `None`) with an
+    /// id of at most `max_id`
+    fn get_latest_merkle_root(
+        &self,
+        pid: Option<&str>,
+        max_id: Option<i32>,
+    ) -> impl Future<Output = anyhow::Result<Option<MerkleRoot>>> + Send;
+    /// Returns the latest Merkle root of each process with an id of at most `max_id`, ordered by pid
+    fn get_latest_process_roots(
+        &self,
+        max_id: Option<i32>,
+    ) -> impl Future<Output = anyhow::Result<Vec<MerkleRoot>>> + Send;
+    /// Purges the payload of all documents, whose retention period ended at `now`, and returns
+    /// the number of expired documents
+    fn expire_documents(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> impl Future<Output = anyhow::Result<u64>> + Send;
+    /// Returns the pids of all processes with documents logged before `before`, which are neither
+    /// archived nor expired
+    fn get_archivable_pids(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+    /// Returns the documents of the process `pid` logged before `before`, which are neither
+    /// archived nor expired, ordered by creation time
+    fn get_archivable_documents(
+        &self,
+        pid: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> impl Future<Output = anyhow::Result<Vec<Document<String>>>> + Send;
+    /// Purges the payload of the documents `ids`, which were moved to the archive
+    fn archive_documents(
+        &self,
+        ids: &[uuid::Uuid],
+        archived_at: chrono::DateTime<chrono::Utc>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Returns the data keys of the process `pid`, ordered by version
+    fn get_data_keys(
+        &self,
+        pid: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<WrappedDataKey>>> + Send;
+    /// Stores a new data key, returns `false` if the process has a key with the same version or
+    /// was shredded
+    fn add_data_key(
+        &self,
+        key: &WrappedDataKey,
+    ) -> impl Future<Output = anyhow::Result<bool>> + Send;
+    /// Returns all data keys, which are not wrapped by the master key `master_key_id`
+    fn get_data_keys_not_wrapped_by(
+        &self,
+        master_key_id: &str,
+    ) -> impl Future<Output = anyhow::Result<Vec<WrappedDataKey>>> + Send;
+    /// Replaces the wrapped key and its master key of an existing data key
+    fn update_data_key(
+        &self,
+        key: &WrappedDataKey,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
 }
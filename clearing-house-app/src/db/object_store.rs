@@ -0,0 +1,131 @@
+//! Object stores for the cold archive of documents. Keys are `/` separated paths.
+
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+
+pub(crate) trait ObjectStore: Send + Sync + 'static {
+    /// Stores `data` under `key`, replacing an existing object
+    fn put(&self, key: &str, data: Vec<u8>) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Returns the object stored under `key`
+    fn get(&self, key: &str) -> impl Future<Output = anyhow::Result<Option<Vec<u8>>>> + Send;
+    /// Returns the keys of all objects in the directory `prefix`, ordered by key
+    fn list(&self, prefix: &str) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+    /// Deletes all objects in the directory `prefix`
+    fn delete_all(&self, prefix: &str) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Object store in a directory of the local filesystem
+#[derive(Clone, Debug)]
+pub(crate) struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub(crate) fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Path of the object `key`, which must not leave the root directory
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let key = Path::new(key);
+        if !key.components().all(|c| matches!(c, Component::Normal(_))) {
+            anyhow::bail!("Invalid object key '{}'", key.display());
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Objects are replaced atomically, so readers never see partially written objects
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let mut entries = match tokio::fs::read_dir(self.path(prefix)?).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let prefix = prefix.trim_end_matches('/');
+        let mut keys = Vec::new();
+        loop {
+            let next = entries.next_entry().await?;
+            let Some(entry) = next else {
+                break;
+            };
+            let file_type = entry.file_type().await?;
+            if file_type.is_file() {
+                keys.push(format!("{prefix}/{}", entry.file_name().to_string_lossy()));
+            }
+        }
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    async fn delete_all(&self, prefix: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_dir_all(self.path(prefix)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LocalObjectStore, ObjectStore};
+
+    #[tokio::test]
+    async fn local_object_store() {
+        let dir = tempfile::tempdir().expect("Creating tempdir");
+        let store = LocalObjectStore::new(dir.path());
+
+        store
+            .put("pid/b.jsonl", b"b".to_vec())
+            .await
+            .expect("Storing object");
+        store
+            .put("pid/a.jsonl", b"a".to_vec())
+            .await
+            .expect("Storing object");
+        store
+            .put("pid/a.jsonl", b"replaced".to_vec())
+            .await
+            .expect("Storing object");
+
+        assert_eq!(
+            store.get("pid/a.jsonl").await.expect("Reading"),
+            Some(b"replaced".to_vec())
+        );
+        assert_eq!(store.get("pid/c.jsonl").await.expect("Reading"), None);
+        assert_eq!(
+            store.list("pid/").await.expect("Listing"),
+            vec!["pid/a.jsonl".to_string(), "pid/b.jsonl".to_string()]
+        );
+        assert!(store.list("unknown/").await.expect("Listing").is_empty());
+        assert!(store.get("../outside").await.is_err());
+
+        store.delete_all("pid/").await.expect("Deleting");
+        assert!(store.list("pid/").await.expect("Listing").is_empty());
+        store.delete_all("pid/").await.expect("Deleting");
+    }
+}
@@ -1,5 +1,8 @@
-use crate::model::document::Document;
-use crate::model::ids::{InfoModelDateTime, InfoModelId};
+use super::rows::{cast_i64, DataKeyRow, DocumentRow, MerkleRootRow, SearchHitRow};
+use crate::model::crypto::WrappedDataKey;
+use crate::model::document::{Document, DocumentCursor, DocumentFilter};
+use crate::model::merkle::MerkleRoot;
+use crate::model::search::SearchHit;
 use crate::model::SortingOrder;
 
 pub(crate) struct PostgresDocumentStore {
@@ -20,41 +23,139 @@ impl PostgresDocumentStore {
     }
 }
 
+/// Appends the `WHERE` clause selecting the documents of `pid` created between `date_from` and
+/// `date_to`, which match `filter`
+fn push_conditions(
+    query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    pid: &str,
+    (date_from, date_to): (
+        &chrono::DateTime<chrono::Utc>,
+        &chrono::DateTime<chrono::Utc>,
+    ),
+    filter: &DocumentFilter,
+) {
+    query
+        .push(" WHERE processes.process_id = ")
+        .push_bind(pid.to_string())
+        .push(" AND documents.created_at BETWEEN ")
+        .push_bind(*date_from)
+        .push(" AND ")
+        .push_bind(*date_to);
+
+    let columns = [
+        ("documents.message_type", &filter.message_type),
+        (
+            "COALESCE(documents.issuer_connector ->> '@id', documents.issuer_connector #>> '{}')",
+            &filter.issuer_connector,
+        ),
+        ("documents.sender_agent", &filter.sender_agent),
+        ("documents.correlation_message", &filter.correlation_message),
+        ("documents.payload_type", &filter.payload_type),
+    ];
+    for (column, value) in columns {
+        if let Some(value) = value {
+            query
+                .push(format!(" AND {column} = "))
+                .push_bind(value.clone());
+        }
+    }
+
+    // Encrypted documents are only found by the blind index of the transfer contract
+    if let Some(transfer_contract) = &filter.transfer_contract {
+        query
+            .push(" AND (documents.transfer_contract = ")
+            .push_bind(transfer_contract.clone());
+        if !filter.transfer_contract_indexes.is_empty() {
+            query.push(" OR documents.transfer_contract_index IN (");
+            let mut indexes = query.separated(", ");
+            for index in &filter.transfer_contract_indexes {
+                indexes.push_bind(index.clone());
+            }
+            indexes.push_unseparated(")");
+        }
+        query.push(")");
+    }
+
+    if let Some(prefix) = &filter.id_prefix {
+        let pattern = prefix
+            .replace('\\', r"\\")
+            .replace('%', r"\%")
+            .replace('_', r"\_");
+        query
+            .push(" AND documents.message_id LIKE ")
+            .push_bind(format!("{pattern}%"))
+            .push(r" ESCAPE '\'");
+    }
+}
+
+/// Inserts `doc` with `executor`, which is either the pool or a transaction
+async fn insert_document<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    doc: Document<String>,
+) -> anyhow::Result<()> {
+    let doc = DocumentRow::from(doc);
+
+    sqlx::query(
+        r"INSERT INTO documents
+    (id, process_id, created_at, model_version, correlation_message,
+    transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+    sender_agent, recipient_agent, payload, payload_type, message_id,
+    previous_hash, hash, key_version, message_type, transfer_contract_index,
+    message_context, raw_header, payload_encoding, content_sha256)
+    VALUES
+    ($1, (SELECT id from processes where process_id = $2), $3, $4, $5,
+    $6, $7, $8, $9, $10,
+    $11, $12, $13, $14, $15,
+    $16, $17, $18, $19, $20,
+    $21, $22, $23, $24)",
+    )
+    .bind(doc.id) // 1
+    .bind(doc.process_id) // 2
+    .bind(doc.created_at) // 3
+    .bind(doc.model_version) // 4
+    .bind(doc.correlation_message) // 5
+    .bind(doc.transfer_contract) // 6
+    .bind(doc.issued) // 7
+    .bind(doc.issuer_connector) // 8
+    .bind(doc.content_version) // 9
+    .bind(doc.recipient_connector) // 10
+    .bind(doc.sender_agent) // 11
+    .bind(doc.recipient_agent) // 12
+    .bind(doc.payload) // 13
+    .bind(doc.payload_type) // 14
+    .bind(doc.message_id) // 15
+    .bind(doc.previous_hash) // 16
+    .bind(doc.hash) // 17
+    .bind(doc.key_version) // 18
+    .bind(doc.message_type) // 19
+    .bind(doc.transfer_contract_index) // 20
+    .bind(doc.message_context) // 21
+    .bind(doc.raw_header) // 22
+    .bind(doc.payload_encoding) // 23
+    .bind(doc.content_sha256) // 24
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
 impl super::DocumentStore for PostgresDocumentStore {
     async fn add_document(&self, doc: Document<String>) -> anyhow::Result<bool> {
-        let doc = DocumentRow::from(doc);
-
-        sqlx::query(
-            r"INSERT INTO documents
-        (id, process_id, created_at, model_version, correlation_message,
-        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
-        sender_agent, recipient_agent, payload, payload_type, message_id)
-        VALUES
-        ($1, (SELECT id from processes where process_id = $2), $3, $4, $5,
-        $6, $7, $8, $9, $10,
-        $11, $12, $13, $14, $15)",
-        )
-        .bind(doc.id) // 1
-        .bind(doc.process_id) // 2
-        .bind(doc.created_at) // 3
-        .bind(doc.model_version) // 4
-        .bind(doc.correlation_message) // 5
-        .bind(doc.transfer_contract) // 6
-        .bind(doc.issued) // 7
-        .bind(doc.issuer_connector) // 8
-        .bind(doc.content_version) // 9
-        .bind(doc.recipient_connector) // 10
-        .bind(doc.sender_agent) // 11
-        .bind(doc.recipient_agent) // 12
-        .bind(doc.payload) // 13
-        .bind(doc.payload_type) // 14
-        .bind(doc.message_id) // 15
-        .execute(&self.db)
-        .await?;
+        insert_document(&self.db, doc).await?;
 
         Ok(true)
     }
 
+    async fn add_documents(&self, docs: Vec<Document<String>>) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+        for doc in docs {
+            insert_document(&mut *tx, doc).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
     async fn exists_document(&self, id: &uuid::Uuid) -> anyhow::Result<bool> {
         sqlx::query("SELECT id FROM documents WHERE id = $1")
             .bind(id)
@@ -68,7 +169,9 @@ impl super::DocumentStore for PostgresDocumentStore {
         sqlx::query_as::<_, DocumentRow>(
             r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
         transfer_contract, issued, issuer_connector, content_version, recipient_connector,
-        sender_agent, recipient_agent, payload, payload_type, message_id
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
         FROM documents
         LEFT JOIN processes ON processes.id = documents.process_id
         WHERE id = $1 AND processes.process_id = $2",
@@ -87,120 +190,796 @@ impl super::DocumentStore for PostgresDocumentStore {
     ///
     /// Disabled `clippy::cast_possible_wrap` because cast is handled
     #[allow(clippy::cast_possible_wrap)]
+    async fn get_document_by_message_id(
+        &self,
+        pid: &str,
+        message_id: &str,
+    ) -> anyhow::Result<Option<Document<String>>> {
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.message_id = $2
+        ORDER BY documents.created_at ASC, documents.id ASC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .bind(message_id)
+        .fetch_optional(&self.db)
+        .await
+        .map(|r| r.map(DocumentRow::into))
+        .map_err(std::convert::Into::into)
+    }
+
     async fn get_documents_for_pid(
         &self,
         pid: &str,
-        page: u64,
-        size: u64,
+        filter: &DocumentFilter,
+        (page, size): (u64, u64),
         sort: &SortingOrder,
-        (date_from, date_to): (&chrono::NaiveDateTime, &chrono::NaiveDateTime),
+        dates: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        cursor: Option<&DocumentCursor>,
     ) -> anyhow::Result<Vec<Document<String>>> {
-        let sort_order = match sort {
-            SortingOrder::Ascending => "ASC",
-            SortingOrder::Descending => "DESC",
+        let (sort_order, cursor_op) = match sort {
+            SortingOrder::Ascending => ("ASC", ">"),
+            SortingOrder::Descending => ("DESC", "<"),
+        };
+
+        let mut query = sqlx::QueryBuilder::new(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id",
+        );
+        push_conditions(&mut query, pid, dates, filter);
+        // A cursor replaces the offset, the id orders documents with the same timestamp
+        let offset = match cursor {
+            Some(cursor) => {
+                query
+                    .push(format!(
+                        " AND (documents.created_at, documents.id) {cursor_op} ("
+                    ))
+                    .push_bind(cursor.created_at)
+                    .push(", ")
+                    .push_bind(cursor.id)
+                    .push(")");
+                0
+            }
+            None => (page - 1) * size,
         };
+        query
+            .push(format!(
+                " ORDER BY documents.created_at {sort_order}, documents.id {sort_order} LIMIT "
+            ))
+            .push_bind(cast_i64(size)?)
+            .push(" OFFSET ")
+            .push_bind(cast_i64(offset)?);
+
+        let rows = query
+            .build_query_as::<DocumentRow>()
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(rows.into_iter().map(DocumentRow::into).collect())
+    }
+
+    async fn count_documents_for_pid(
+        &self,
+        pid: &str,
+        dates: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        filter: &DocumentFilter,
+    ) -> anyhow::Result<u64> {
+        let mut query = sqlx::QueryBuilder::new(
+            r"SELECT COUNT(*)
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id",
+        );
+        push_conditions(&mut query, pid, dates, filter);
 
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(&self.db)
+            .await?;
+
+        u64::try_from(count).map_err(std::convert::Into::into)
+    }
+
+    async fn search_documents(
+        &self,
+        owner: &str,
+        query: &str,
+        (page, size): (u64, u64),
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        // Highlights are only computed for the hits of the requested page
+        sqlx::query_as::<_, SearchHitRow>(
+            r"SELECT hits.process_id, hits.id, hits.message_id, hits.created_at, hits.rank,
+            ts_headline('simple', payload_text(hits.payload), hits.search_query, 'MaxFragments=3') AS highlight
+        FROM (
+            SELECT processes.process_id, documents.id, documents.message_id, documents.created_at,
+                documents.payload, search_query, ts_rank(documents.payload_tsv, search_query) AS rank
+            FROM documents
+            JOIN processes ON processes.id = documents.process_id
+            JOIN process_owners po ON po.process_id = processes.id
+            JOIN clients c ON c.id = po.client_id
+            CROSS JOIN websearch_to_tsquery('simple', $2) search_query
+            WHERE c.client_id = $1 AND documents.payload_tsv @@ search_query
+            ORDER BY rank DESC, documents.created_at DESC, documents.id
+            LIMIT $3 OFFSET $4
+        ) hits
+        ORDER BY hits.rank DESC, hits.created_at DESC, hits.id",
+        )
+        .bind(owner) // 1
+        .bind(query) // 2
+        .bind(cast_i64(size)?) // 3
+        .bind(cast_i64((page - 1) * size)?) // 4
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(SearchHitRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_chain_head(&self, pid: &str) -> anyhow::Result<Option<String>> {
+        sqlx::query_scalar::<_, String>(
+            r"SELECT documents.hash
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM documents successor
+            WHERE successor.process_id = documents.process_id AND successor.previous_hash = documents.hash)
+        ORDER BY documents.created_at DESC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_chain_for_pid(&self, pid: &str) -> anyhow::Result<Vec<Document<String>>> {
         sqlx::query_as::<_, DocumentRow>(
-            format!(
-                r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
         transfer_contract, issued, issuer_connector, content_version, recipient_connector,
-        sender_agent, recipient_agent, payload, payload_type, message_id
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
         FROM documents
         LEFT JOIN processes ON processes.id = documents.process_id
-        WHERE processes.process_id = $1 AND documents.created_at BETWEEN $2 AND $3
-        ORDER BY created_at {sort_order}
-        LIMIT $4 OFFSET $5")
-                .as_str(),
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        ORDER BY documents.created_at ASC",
         )
             .bind(pid)
-            .bind(date_from)
-            .bind(date_to)
-            .bind(cast_i64(size)?)
-            .bind(cast_i64((page - 1) * size)?)
             .fetch_all(&self.db)
             .await
             .map(|r| r.into_iter().map(DocumentRow::into).collect())
             .map_err(std::convert::Into::into)
     }
-}
 
-/// Cast u64 to i64 with out-of-range check
-fn cast_i64(value: u64) -> anyhow::Result<i64> {
-    if value > i64::MAX as u64 {
-        Err(anyhow::anyhow!("size out-of-range"))
-    } else {
-        #[allow(clippy::cast_possible_wrap)]
-        Ok(value as i64)
+    async fn get_chain_links(
+        &self,
+        pid: &str,
+    ) -> anyhow::Result<Vec<(uuid::Uuid, Option<String>, String)>> {
+        sqlx::query_as::<_, (uuid::Uuid, Option<String>, String)>(
+            r"SELECT documents.id, previous_hash, hash
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        ORDER BY documents.created_at ASC",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_unpublished_pids(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            r"SELECT process_id, chained FROM (SELECT p.process_id,
+            (SELECT COUNT(*) FROM documents d WHERE d.process_id = p.id AND d.hash IS NOT NULL) AS chained,
+            COALESCE((SELECT r.tree_size FROM merkle_roots r WHERE r.process_id = p.id ORDER BY r.id DESC LIMIT 1), 0) AS published
+        FROM processes p) AS t
+        WHERE chained > published",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|(pid, chained)| Ok((pid, u64::try_from(chained)?)))
+            .collect()
+    }
+
+    async fn add_merkle_root(&self, root: &MerkleRoot) -> anyhow::Result<i32> {
+        sqlx::query_scalar::<_, i32>(
+            r"INSERT INTO merkle_roots
+        (process_id, tree_size, root, covers_up_to, signature, created_at)
+        VALUES
+        ((SELECT id from processes where process_id = $1), $2, $3, $4, $5, $6)
+        RETURNING id",
+        )
+        .bind(root.pid.as_deref()) // 1
+        .bind(cast_i64(root.tree_size)?) // 2
+        .bind(&root.root) // 3
+        .bind(root.covers_up_to) // 4
+        .bind(&root.signature) // 5
+        .bind(root.created_at.naive_utc()) // 6
+        .fetch_one(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_latest_merkle_root(
+        &self,
+        pid: Option<&str>,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Option<MerkleRoot>> {
+        sqlx::query_as::<_, MerkleRootRow>(
+            r"SELECT r.id, p.process_id, r.tree_size, r.root, r.covers_up_to, r.signature, r.created_at
+        FROM merkle_roots r
+        LEFT JOIN processes p ON p.id = r.process_id
+        WHERE (($1::VARCHAR IS NULL AND r.process_id IS NULL) OR p.process_id = $1)
+        AND ($2::INTEGER IS NULL OR r.id <= $2)
+        ORDER BY r.id DESC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .bind(max_id)
+        .fetch_optional(&self.db)
+        .await?
+        .map(MerkleRoot::try_from)
+        .transpose()
+    }
+
+    async fn get_latest_process_roots(
+        &self,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Vec<MerkleRoot>> {
+        sqlx::query_as::<_, MerkleRootRow>(
+            r"SELECT * FROM (
+            SELECT DISTINCT ON (r.process_id) r.id, p.process_id, r.tree_size, r.root, r.covers_up_to, r.signature, r.created_at
+            FROM merkle_roots r
+            JOIN processes p ON p.id = r.process_id
+            WHERE ($1::INTEGER IS NULL OR r.id <= $1)
+            ORDER BY r.process_id, r.id DESC
+        ) latest
+        ORDER BY latest.process_id",
+        )
+        .bind(max_id)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(MerkleRoot::try_from)
+        .collect()
+    }
+
+    async fn expire_documents(&self, now: chrono::DateTime<chrono::Utc>) -> anyhow::Result<u64> {
+        sqlx::query(
+            r"WITH retention AS (
+            SELECT p.id AS process_id, p.closed_at,
+                COALESCE(r.retention_days, g.retention_days) AS retention_days,
+                COALESCE(r.retention_start, g.retention_start) AS retention_start
+            FROM processes p
+            LEFT JOIN retention_policies r ON r.process_id = p.id
+            LEFT JOIN retention_policies g ON g.process_id IS NULL
+        )
+        UPDATE documents d SET payload = NULL, payload_type = NULL, payload_encoding = NULL, expired_at = $1
+        FROM retention
+        WHERE d.process_id = retention.process_id AND d.expired_at IS NULL
+        AND CASE retention.retention_start
+            WHEN 'logged' THEN d.created_at AT TIME ZONE 'UTC'
+            WHEN 'closed' THEN CASE WHEN retention.closed_at IS NOT NULL
+                THEN GREATEST(d.created_at AT TIME ZONE 'UTC', retention.closed_at) END
+        END + make_interval(days => retention.retention_days) <= $1",
+        )
+        .bind(now.naive_utc())
+        .execute(&self.db)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_archivable_pids(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<String>> {
+        sqlx::query_scalar::<_, String>(
+            r"SELECT DISTINCT p.process_id FROM processes p
+        JOIN documents d ON d.process_id = p.id
+        WHERE d.created_at < $1 AND d.archived_at IS NULL AND d.expired_at IS NULL
+        ORDER BY p.process_id",
+        )
+        .bind(before)
+        .fetch_all(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
     }
-}
 
-#[derive(sqlx::FromRow)]
-struct DocumentRow {
-    id: uuid::Uuid,
-    process_id: String,
-    created_at: chrono::NaiveDateTime,
-    model_version: String,
-    correlation_message: Option<String>,
-    transfer_contract: Option<String>,
-    issued: sqlx::types::Json<InfoModelDateTime>,
-    issuer_connector: sqlx::types::Json<InfoModelId>,
-    content_version: Option<String>,
-    recipient_connector: Option<sqlx::types::Json<Vec<InfoModelId>>>,
-    sender_agent: String,
-    recipient_agent: Option<sqlx::types::Json<Vec<InfoModelId>>>,
-    payload: Option<Vec<u8>>,
-    payload_type: Option<String>,
-    message_id: Option<String>,
+    async fn get_archivable_documents(
+        &self,
+        pid: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<Document<String>>> {
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.created_at < $2
+        AND documents.archived_at IS NULL AND documents.expired_at IS NULL
+        ORDER BY documents.created_at ASC",
+        )
+        .bind(pid)
+        .bind(before)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DocumentRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn archive_documents(
+        &self,
+        ids: &[uuid::Uuid],
+        archived_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r"UPDATE documents SET payload = NULL, payload_type = NULL, payload_encoding = NULL, archived_at = $2
+        WHERE id = ANY($1)",
+        )
+        .bind(ids)
+        .bind(archived_at.naive_utc())
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_data_keys(&self, pid: &str) -> anyhow::Result<Vec<WrappedDataKey>> {
+        sqlx::query_as::<_, DataKeyRow>(
+            r"SELECT p.process_id, k.key_version, k.master_key_id, k.wrapped_key, k.created_at
+        FROM data_keys k
+        JOIN processes p ON p.id = k.process_id
+        WHERE p.process_id = $1
+        ORDER BY k.key_version",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DataKeyRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn add_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<bool> {
+        sqlx::query(
+            r"INSERT INTO data_keys (process_id, key_version, master_key_id, wrapped_key, created_at)
+        SELECT id, $2, $3, $4, $5 FROM processes WHERE process_id = $1
+        AND NOT EXISTS (SELECT 1 FROM shred_certificates s WHERE s.process_id = processes.id)
+        ON CONFLICT DO NOTHING",
+        )
+        .bind(&key.pid) // 1
+        .bind(key.version) // 2
+        .bind(&key.master_key_id) // 3
+        .bind(&key.wrapped_key) // 4
+        .bind(key.created_at.naive_utc()) // 5
+        .execute(&self.db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_data_keys_not_wrapped_by(
+        &self,
+        master_key_id: &str,
+    ) -> anyhow::Result<Vec<WrappedDataKey>> {
+        sqlx::query_as::<_, DataKeyRow>(
+            r"SELECT p.process_id, k.key_version, k.master_key_id, k.wrapped_key, k.created_at
+        FROM data_keys k
+        JOIN processes p ON p.id = k.process_id
+        WHERE k.master_key_id <> $1
+        ORDER BY p.process_id, k.key_version",
+        )
+        .bind(master_key_id)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DataKeyRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn update_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<()> {
+        sqlx::query(
+            r"UPDATE data_keys SET master_key_id = $3, wrapped_key = $4
+        WHERE process_id = (SELECT id FROM processes WHERE process_id = $1) AND key_version = $2",
+        )
+        .bind(&key.pid) // 1
+        .bind(key.version) // 2
+        .bind(&key.master_key_id) // 3
+        .bind(&key.wrapped_key) // 4
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
 }
 
-impl From<Document<String>> for DocumentRow {
-    fn from(value: Document<String>) -> Self {
-        Self {
-            id: value.id,
-            process_id: value.pid,
-            created_at: value.ts.naive_utc(),
-            model_version: value.content.header.model_version,
-            correlation_message: value.content.header.correlation_message,
-            transfer_contract: value.content.header.transfer_contract,
-            issued: sqlx::types::Json(value.content.header.issued),
-            issuer_connector: sqlx::types::Json(value.content.header.issuer_connector),
-            content_version: value.content.header.content_version,
-            recipient_connector: value.content.header.recipient_connector.map(sqlx::types::Json),
-            sender_agent: value.content.header.sender_agent.to_string(),
-            recipient_agent: value.content.header.recipient_agent.map(sqlx::types::Json),
-            payload: value.content.payload.map(|s| s.as_bytes().to_owned()),
-            payload_type: value.content.payload_type,
-            message_id: value.content.header.id,
+#[cfg(test)]
+mod test {
+    use super::PostgresDocumentStore;
+    use crate::db::postgres_process_store::PostgresProcessStore;
+    use crate::db::{DocumentStore, ProcessStore};
+    use crate::model::document::{Document, DocumentCursor, DocumentFilter, GENESIS_HASH};
+    use crate::model::ids::{InfoModelComplexId, InfoModelId, MessageType};
+    use crate::model::process::Process;
+    use crate::model::SortingOrder;
+    use chrono::SubsecRound;
+    use testcontainers::ContainerAsync;
+    use testcontainers_modules::postgres::Postgres;
+
+    /// Stores in a new Postgres container, which must be kept alive, containing the process `pid`
+    async fn stores() -> (
+        ContainerAsync<Postgres>,
+        PostgresProcessStore,
+        PostgresDocumentStore,
+    ) {
+        let (container, database_url) = crate::test_util::start_postgres().await;
+        let pool = crate::setup_postgres(&database_url)
+            .await
+            .expect("Setting up database");
+        let process_store = PostgresProcessStore::new(pool.clone(), false).await;
+        let doc_store = PostgresDocumentStore::new(pool, false).await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+        (container, process_store, doc_store)
+    }
+
+    fn document(pid: &str, offset: i64) -> Document<String> {
+        let mut doc = crate::test_util::document(pid, &format!("payload {offset}"));
+        doc.ts = doc.ts.trunc_subsecs(6) + chrono::Duration::seconds(offset);
+        doc
+    }
+
+    #[tokio::test]
+    async fn documents() {
+        let (_container, _, doc_store) = stores().await;
+
+        let mut docs = Vec::new();
+        let mut previous_hash = GENESIS_HASH.to_string();
+        for offset in 0..5 {
+            let mut doc = document("pid", offset);
+            doc.link_to(&previous_hash)
+                .expect("Document is serializable");
+            previous_hash = doc.hash.clone().expect("Hash was set");
+            docs.push(doc.clone());
+            doc_store.add_document(doc).await.expect("Storing document");
+        }
+
+        // Timestamps keep their microseconds, so the hashes stay valid
+        let stored = doc_store
+            .get_chain_for_pid("pid")
+            .await
+            .expect("Reading chain");
+        assert_eq!(stored.len(), 5);
+        for (stored, doc) in stored.iter().zip(&docs) {
+            assert_eq!(stored.ts.to_utc(), doc.ts.to_utc());
+            assert!(stored.is_linked_to(doc.previous_hash.as_deref().expect("Document is linked")));
+        }
+        assert_eq!(
+            doc_store
+                .get_chain_links("pid")
+                .await
+                .expect("Reading chain links"),
+            docs.iter()
+                .map(|d| (
+                    d.id,
+                    d.previous_hash.clone(),
+                    d.hash.clone().expect("Hash was set")
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        // Date filtering is inclusive
+        let all = DocumentFilter::default();
+        let (from, to) = (docs[1].ts.to_utc(), docs[3].ts.to_utc());
+        assert_eq!(
+            doc_store
+                .count_documents_for_pid("pid", (&from, &to), &all)
+                .await
+                .expect("Counting documents"),
+            3
+        );
+
+        // Cursors continue after the previous page, documents logged at the same time are
+        // ordered by their id
+        let mut twin = document("pid", 2);
+        twin.ts = docs[2].ts;
+        doc_store
+            .add_document(twin.clone())
+            .await
+            .expect("Storing document");
+        let (from, to) = (docs[0].ts.to_utc(), docs[4].ts.to_utc());
+        for sort in [SortingOrder::Ascending, SortingOrder::Descending] {
+            let mut expected: Vec<_> = docs.iter().chain([&twin]).map(|d| (d.ts, d.id)).collect();
+            expected.sort();
+            if matches!(sort, SortingOrder::Descending) {
+                expected.reverse();
+            }
+
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = doc_store
+                    .get_documents_for_pid(
+                        "pid",
+                        &all,
+                        (1, 2),
+                        &sort,
+                        (&from, &to),
+                        cursor.as_ref(),
+                    )
+                    .await
+                    .expect("Reading documents");
+                let Some(last) = page.last() else {
+                    break;
+                };
+                cursor = Some(DocumentCursor::after(last));
+                paged.extend(page.iter().map(|d| (d.ts, d.id)));
+            }
+            assert_eq!(paged, expected);
         }
     }
-}
 
-impl From<DocumentRow> for Document<String> {
-    fn from(value: DocumentRow) -> Self {
-        use chrono::TimeZone;
-
-        Self {
-            id: value.id,
-            pid: value.process_id,
-            ts: chrono::Local.from_utc_datetime(&value.created_at),
-            content: crate::model::ids::message::IdsMessage {
-                header: crate::model::ids::message::IdsHeader {
-                    model_version: value.model_version,
-                    correlation_message: value.correlation_message,
-                    transfer_contract: value.transfer_contract,
-                    issued: value.issued.0,
-                    issuer_connector: value.issuer_connector.0,
-                    content_version: value.content_version,
-                    recipient_connector: value.recipient_connector.map(|s| s.0),
-                    sender_agent: InfoModelId::SimpleId(value.sender_agent),
-                    recipient_agent: value.recipient_agent.map(|s| s.0),
-                    id: value.message_id,
-                    ..Default::default()
-                },
-                payload: value.payload.map(|b| String::from_utf8_lossy(&b).to_string()),
-                payload_type: value.payload_type,
-
-            },
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn filters() {
+        let (_container, _, doc_store) = stores().await;
+
+        let mut log = document("pid", 0);
+        log.content.header.type_message = MessageType::LogMessage;
+        log.content.header.id = Some("https://msg/log_1".to_string());
+        log.content.header.issuer_connector = InfoModelId::new("https://connector/a".to_string());
+        log.content.header.sender_agent = InfoModelId::new("https://agent/a".to_string());
+        log.content.header.correlation_message = Some("https://msg/request".to_string());
+        log.content.payload_type = Some("application/json".to_string());
+        let mut request = document("pid", 1);
+        request.content.header.type_message = MessageType::RequestMessage;
+        request.content.header.id = Some("https://msg/request".to_string());
+        request.content.header.issuer_connector =
+            InfoModelId::ComplexId(InfoModelComplexId::new("https://connector/b".to_string()));
+        request.content.header.transfer_contract = Some("https://contract".to_string());
+        let mut encrypted = document("pid", 2);
+        encrypted.content.header.id = Some(r"https://msg/log_%\".to_string());
+        encrypted.content.header.transfer_contract = Some("ciphertext".to_string());
+        encrypted.transfer_contract_index = Some("index".to_string());
+        encrypted.key_version = Some(1);
+        for doc in [&log, &request, &encrypted] {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
+        }
+
+        let from = log.ts.to_utc();
+        let to = encrypted.ts.to_utc();
+        let query = |filter: DocumentFilter| {
+            let doc_store = &doc_store;
+            async move {
+                let docs = doc_store
+                    .get_documents_for_pid(
+                        "pid",
+                        &filter,
+                        (1, 10),
+                        &SortingOrder::Ascending,
+                        (&from, &to),
+                        None,
+                    )
+                    .await
+                    .expect("Reading documents");
+                let count = doc_store
+                    .count_documents_for_pid("pid", (&from, &to), &filter)
+                    .await
+                    .expect("Counting documents");
+                assert_eq!(u64::try_from(docs.len()).ok(), Some(count));
+                docs.iter().map(|d| d.id).collect::<Vec<_>>()
+            }
+        };
+        let some = |value: &str| Some(value.to_string());
+
+        assert_eq!(
+            query(DocumentFilter {
+                message_type: some("ids:LogMessage"),
+                ..DocumentFilter::default()
+            })
+            .await,
+            vec![log.id]
+        );
+        // Issuer connectors are stored as JSON string (`#>>`) or as object with `@id` (`->>`)
+        for (issuer, expected) in [
+            ("https://connector/a", log.id),
+            ("https://connector/b", request.id),
+        ] {
+            assert_eq!(
+                query(DocumentFilter {
+                    issuer_connector: some(issuer),
+                    ..DocumentFilter::default()
+                })
+                .await,
+                vec![expected]
+            );
+        }
+        assert_eq!(
+            query(DocumentFilter {
+                sender_agent: some("https://agent/a"),
+                correlation_message: some("https://msg/request"),
+                payload_type: some("application/json"),
+                ..DocumentFilter::default()
+            })
+            .await,
+            vec![log.id]
+        );
+        let contract = DocumentFilter {
+            transfer_contract: some("https://contract"),
+            transfer_contract_indexes: vec!["other".to_string(), "index".to_string()],
+            ..DocumentFilter::default()
+        };
+        assert_eq!(query(contract).await, vec![request.id, encrypted.id]);
+
+        // Id prefixes are case-sensitive and wildcards and the escape character are matched
+        // literally
+        let prefix = |prefix: &str| DocumentFilter {
+            id_prefix: some(prefix),
+            ..DocumentFilter::default()
+        };
+        assert_eq!(query(prefix("https://msg/")).await.len(), 3);
+        assert_eq!(
+            query(prefix("https://msg/log_")).await,
+            vec![log.id, encrypted.id]
+        );
+        assert_eq!(query(prefix("https://msg/log_%")).await, vec![encrypted.id]);
+        assert_eq!(
+            query(prefix(r"https://msg/log_%\")).await,
+            vec![encrypted.id]
+        );
+        assert!(query(prefix("https://msg/log*")).await.is_empty());
+        assert!(query(prefix("https://msg/lo_")).await.is_empty());
+        assert!(query(prefix("HTTPS://msg/")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn search() {
+        let (_container, process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("other".to_string(), vec!["other".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let mut matching = document("pid", 0);
+        matching.content.payload = Some("Lieferung über die Straße: ASSET X".to_string());
+        let mut encrypted = document("pid", 1);
+        encrypted.content.payload = Some("asset x".to_string());
+        encrypted.key_version = Some(1);
+        let mut foreign = document("other", 0);
+        foreign.content.payload = Some("asset x".to_string());
+        let mut binary = document("pid", 3);
+        binary
+            .content
+            .set_payload(Some("application/octet-stream".to_string()), b"asset x");
+        for doc in [
+            &matching,
+            &encrypted,
+            &foreign,
+            &binary,
+            &document("pid", 2),
+        ] {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
         }
+
+        // Only plaintext text payloads of owned processes are indexed via `payload_text`
+        let hits = doc_store
+            .search_documents("owner", "asset x", (1, 10))
+            .await
+            .expect("Searching");
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].pid.as_str(), hits[0].id), ("pid", matching.id));
+        assert!(hits[0].highlight.contains("<b>ASSET</b> <b>X</b>"));
+        assert!(hits[0].rank > 0.0);
+
+        // Non-ASCII payloads are converted from UTF-8
+        let hits = doc_store
+            .search_documents("owner", "straße", (1, 10))
+            .await
+            .expect("Searching");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].highlight.contains("<b>Straße</b>"));
+
+        assert!(doc_store
+            .search_documents("owner", "asset delivered", (1, 10))
+            .await
+            .expect("Searching")
+            .is_empty());
+        assert!(doc_store
+            .search_documents("owner", "asset x", (2, 10))
+            .await
+            .expect("Searching")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn timestamptz_migration() {
+        /// First migration storing the creation time of documents with time zone
+        const TIMESTAMPTZ_VERSION: i64 = 20_261_018_200_000;
+
+        let (_container, database_url) = crate::test_util::start_postgres().await;
+        let pool = sqlx::PgPool::connect(&database_url)
+            .await
+            .expect("Connecting to database");
+        // The conversion must not depend on the time zone of the session
+        sqlx::query("ALTER DATABASE postgres SET timezone TO 'Europe/Berlin'")
+            .execute(&pool)
+            .await
+            .expect("Setting time zone");
+        pool.close().await;
+        let pool = sqlx::PgPool::connect(&database_url)
+            .await
+            .expect("Connecting to database");
+
+        let migrator = sqlx::migrate!();
+        let before = sqlx::migrate::Migrator {
+            migrations: migrator
+                .iter()
+                .filter(|m| m.version < TIMESTAMPTZ_VERSION)
+                .cloned()
+                .collect(),
+            ..sqlx::migrate::Migrator::DEFAULT
+        };
+        before.run(&pool).await.expect("Migrating database");
+
+        let doc = document("pid", 0);
+        let created_at = chrono::NaiveDate::from_ymd_opt(2024, 1, 2)
+            .and_then(|d| d.and_hms_micro_opt(3, 4, 5, 123_456))
+            .expect("Valid time");
+        sqlx::query("INSERT INTO processes (process_id) VALUES ('pid')")
+            .execute(&pool)
+            .await
+            .expect("Storing process");
+        sqlx::query(
+            r"INSERT INTO documents
+        (id, process_id, created_at, model_version, issued, issuer_connector, sender_agent)
+        VALUES ($1, (SELECT id FROM processes WHERE process_id = 'pid'), $2, $3, $4, $5, $6)",
+        )
+        .bind(doc.id)
+        .bind(created_at)
+        .bind(&doc.content.header.model_version)
+        .bind(sqlx::types::Json(&doc.content.header.issued))
+        .bind(sqlx::types::Json(&doc.content.header.issuer_connector))
+        .bind(doc.content.header.sender_agent.to_string())
+        .execute(&pool)
+        .await
+        .expect("Storing document");
+
+        migrator.run(&pool).await.expect("Migrating database");
+        let stored = PostgresDocumentStore::new(pool, false)
+            .await
+            .get_document(&doc.id.to_string(), "pid")
+            .await
+            .expect("Reading document")
+            .expect("Document exists");
+        assert_eq!(stored.ts.to_utc(), created_at.and_utc());
     }
 }
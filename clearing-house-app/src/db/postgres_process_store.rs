@@ -1,4 +1,7 @@
-use crate::model::process::Process;
+use super::rows::{RetentionRow, ShredCertificateRow, TombstoneRow};
+use crate::model::process::{
+    Process, ProcessInfo, Retention, RetentionPolicy, ShredCertificate, Tombstone,
+};
 use sqlx::Row;
 
 pub(crate) struct PostgresProcessStore {
@@ -33,13 +36,166 @@ impl super::ProcessStore for PostgresProcessStore {
         .map_err(std::convert::Into::into)
     }
 
-    async fn delete_process(&self, pid: &str) -> anyhow::Result<bool> {
-        sqlx::query("DELETE FROM processes WHERE process_id = $1 CASCADE")
-            .bind(pid)
-            .execute(&self.db)
-            .await
-            .map(|r| r.rows_affected() == 1)
-            .map_err(std::convert::Into::into)
+    async fn delete_process(&self, pid: &str, tombstone: &Tombstone) -> anyhow::Result<bool> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(pid) =
+            sqlx::query_scalar::<_, i32>(r"SELECT id FROM processes WHERE process_id = $1")
+                .bind(pid)
+                .fetch_optional(&mut *tx)
+                .await?
+        else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            r"INSERT INTO tombstones
+        (process_id, owners, consents, chain_length, chain_head, deleted_at, signature)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(pid) // 1
+        .bind(sqlx::types::Json(&tombstone.owners)) // 2
+        .bind(sqlx::types::Json(&tombstone.consents)) // 3
+        .bind(i64::try_from(tombstone.chain_length)?) // 4
+        .bind(&tombstone.chain_head) // 5
+        .bind(tombstone.deleted_at.naive_utc()) // 6
+        .bind(&tombstone.signature) // 7
+        .execute(&mut *tx)
+        .await?;
+
+        // The process itself is kept, as it is referenced by the tombstone and Merkle roots
+        for table in [
+            "documents",
+            "data_keys",
+            "process_owners",
+            "deletion_consents",
+        ] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE process_id = $1"))
+                .bind(pid)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn add_deletion_consent(&self, pid: &str, owner: &str) -> anyhow::Result<Vec<String>> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query(
+            r"INSERT INTO deletion_consents (process_id, client_id)
+        SELECT p.id, c.id FROM processes p, clients c WHERE p.process_id = $1 AND c.client_id = $2
+        ON CONFLICT DO NOTHING",
+        )
+        .bind(pid)
+        .bind(owner)
+        .execute(&mut *tx)
+        .await?;
+
+        let consents = sqlx::query_scalar::<_, String>(
+            r"SELECT c.client_id FROM deletion_consents dc
+        JOIN processes p ON p.id = dc.process_id
+        JOIN clients c ON c.id = dc.client_id
+        WHERE p.process_id = $1
+        ORDER BY c.client_id",
+        )
+        .bind(pid)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(consents)
+    }
+
+    async fn get_deletion_consents(&self, pid: &str) -> anyhow::Result<Vec<String>> {
+        sqlx::query_scalar::<_, String>(
+            r"SELECT c.client_id FROM deletion_consents dc
+        JOIN processes p ON p.id = dc.process_id
+        JOIN clients c ON c.id = dc.client_id
+        WHERE p.process_id = $1
+        ORDER BY c.client_id",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_tombstone(&self, pid: &str) -> anyhow::Result<Option<Tombstone>> {
+        sqlx::query_as::<_, TombstoneRow>(
+            r"SELECT p.process_id, t.owners, t.consents, t.chain_length, t.chain_head, t.deleted_at, t.signature
+        FROM tombstones t
+        JOIN processes p ON p.id = t.process_id
+        WHERE p.process_id = $1",
+        )
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await?
+        .map(Tombstone::try_from)
+        .transpose()
+    }
+
+    async fn shred_process(
+        &self,
+        pid: &str,
+        certificate: &ShredCertificate,
+    ) -> anyhow::Result<bool> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(pid) =
+            sqlx::query_scalar::<_, i32>(r"SELECT id FROM processes WHERE process_id = $1")
+                .bind(pid)
+                .fetch_optional(&mut *tx)
+                .await?
+        else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            r"INSERT INTO shred_certificates
+        (process_id, owners, consents, key_versions, chain_length, chain_head, shredded_at, signature)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(pid) // 1
+        .bind(sqlx::types::Json(&certificate.owners)) // 2
+        .bind(sqlx::types::Json(&certificate.consents)) // 3
+        .bind(sqlx::types::Json(&certificate.key_versions)) // 4
+        .bind(i64::try_from(certificate.chain_length)?) // 5
+        .bind(&certificate.chain_head) // 6
+        .bind(certificate.shredded_at.naive_utc()) // 7
+        .bind(&certificate.signature) // 8
+        .execute(&mut *tx)
+        .await?;
+
+        // The documents are kept, without their data keys they are only ciphertext
+        for table in ["data_keys", "deletion_consents"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE process_id = $1"))
+                .bind(pid)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_shred_certificate(&self, pid: &str) -> anyhow::Result<Option<ShredCertificate>> {
+        sqlx::query_as::<_, ShredCertificateRow>(
+            r"SELECT p.process_id, s.owners, s.consents, s.key_versions, s.chain_length, s.chain_head,
+        s.shredded_at, s.signature
+        FROM shred_certificates s
+        JOIN processes p ON p.id = s.process_id
+        WHERE p.process_id = $1",
+        )
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await?
+        .map(ShredCertificate::try_from)
+        .transpose()
     }
 
     async fn exists_process(&self, pid: &str) -> anyhow::Result<bool> {
@@ -80,25 +236,7 @@ impl super::ProcessStore for PostgresProcessStore {
         let pid = process_row.get::<i32, _>("id");
 
         for o in process.owners {
-            // Check if client exists
-            let client_row = sqlx::query(r"SELECT id FROM clients WHERE client_id = $1")
-                .bind(&o)
-                .fetch_optional(&mut *tx)
-                .await?;
-
-            // If not, create it
-            let client_row = match client_row {
-                Some(crow) => crow,
-                None => {
-                    sqlx::query(r"INSERT INTO clients (client_id) VALUES ($1) RETURNING id")
-                        .bind(&o)
-                        .fetch_one(&mut *tx)
-                        .await?
-                }
-            };
-
-            // Get id of client
-            let client_id = client_row.get::<i32, _>("id");
+            let client_id = get_or_create_client(&mut tx, &o).await?;
 
             // Create process owner
             sqlx::query(r"INSERT INTO process_owners (process_id, client_id) VALUES ($1, $2)")
@@ -112,6 +250,184 @@ impl super::ProcessStore for PostgresProcessStore {
 
         Ok(())
     }
+
+    async fn get_processes_for_owner(
+        &self,
+        owner: &str,
+        page: u64,
+        size: u64,
+    ) -> anyhow::Result<Vec<ProcessInfo>> {
+        sqlx::query_as::<_, ProcessInfoRow>(
+            r"SELECT p.process_id, p.created_at, ARRAY_AGG(c.client_id) AS owners,
+            (SELECT COUNT(*) FROM documents d WHERE d.process_id = p.id) AS document_count
+        FROM processes p
+        JOIN process_owners po ON p.id = po.process_id
+        JOIN clients c ON po.client_id = c.id
+        WHERE p.id IN (SELECT po.process_id FROM process_owners po
+            JOIN clients c ON po.client_id = c.id
+            WHERE c.client_id = $1)
+        GROUP BY p.id, p.process_id, p.created_at
+        ORDER BY p.created_at DESC, p.process_id
+        LIMIT $2 OFFSET $3",
+        )
+        .bind(owner)
+        .bind(i64::try_from(size)?)
+        .bind(i64::try_from((page - 1) * size)?)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(ProcessInfo::try_from)
+        .collect()
+    }
+
+    async fn update_owners(
+        &self,
+        pid: &str,
+        added: &[String],
+        removed: &[String],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        let pid = sqlx::query_scalar::<_, i32>(r"SELECT id FROM processes WHERE process_id = $1")
+            .bind(pid)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Process '{pid}' does not exist"))?;
+
+        for o in removed {
+            sqlx::query(
+                r"DELETE FROM process_owners
+            WHERE process_id = $1 AND client_id = (SELECT id FROM clients WHERE client_id = $2)",
+            )
+            .bind(pid)
+            .bind(o)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for o in added {
+            let client_id = get_or_create_client(&mut tx, o).await?;
+
+            sqlx::query(
+                r"INSERT INTO process_owners (process_id, client_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+            )
+            .bind(pid)
+            .bind(client_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn set_retention_policy(
+        &self,
+        pid: Option<&str>,
+        policy: Option<&RetentionPolicy>,
+    ) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        let process_id = match pid {
+            Some(pid) => Some(
+                sqlx::query_scalar::<_, i32>(r"SELECT id FROM processes WHERE process_id = $1")
+                    .bind(pid)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("Process '{pid}' does not exist"))?,
+            ),
+            None => None,
+        };
+
+        sqlx::query(r"DELETE FROM retention_policies WHERE process_id IS NOT DISTINCT FROM $1")
+            .bind(process_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(policy) = policy {
+            sqlx::query(
+                r"INSERT INTO retention_policies (process_id, retention_days, retention_start)
+            VALUES ($1, $2, $3)",
+            )
+            .bind(process_id)
+            .bind(i32::try_from(policy.days)?)
+            .bind(policy.start.as_str())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_retention(&self, pid: &str) -> anyhow::Result<Retention> {
+        sqlx::query_as::<_, RetentionRow>(
+            r"SELECT COALESCE(r.retention_days, g.retention_days) AS retention_days,
+            COALESCE(r.retention_start, g.retention_start) AS retention_start, p.closed_at
+        FROM processes p
+        LEFT JOIN retention_policies r ON r.process_id = p.id
+        LEFT JOIN retention_policies g ON g.process_id IS NULL
+        WHERE p.process_id = $1",
+        )
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Process '{pid}' does not exist"))?
+        .try_into()
+    }
+
+    async fn close_process(
+        &self,
+        pid: &str,
+        closed_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r"UPDATE processes SET closed_at = COALESCE(closed_at, $2) WHERE process_id = $1",
+        )
+        .bind(pid)
+        .bind(closed_at.naive_utc())
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!("Process '{pid}' does not exist");
+        }
+        Ok(())
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.db).await?;
+        Ok(())
+    }
+}
+
+/// Returns the id of the client `client_id` and creates the client, if it does not exist
+async fn get_or_create_client(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    client_id: &str,
+) -> anyhow::Result<i32> {
+    // Check if client exists
+    let client_row = sqlx::query(r"SELECT id FROM clients WHERE client_id = $1")
+        .bind(client_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    // If not, create it
+    let client_row = match client_row {
+        Some(crow) => crow,
+        None => {
+            sqlx::query(r"INSERT INTO clients (client_id) VALUES ($1) RETURNING id")
+                .bind(client_id)
+                .fetch_one(&mut **tx)
+                .await?
+        }
+    };
+
+    // Get id of client
+    Ok(client_row.get::<i32, _>("id"))
 }
 
 #[derive(sqlx::FromRow, Debug)]
@@ -134,3 +450,24 @@ impl From<ProcessRow> for Process {
         Self::new(value.process_id, value.owners)
     }
 }
+
+#[derive(sqlx::FromRow)]
+struct ProcessInfoRow {
+    process_id: String,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    owners: Vec<String>,
+    document_count: i64,
+}
+
+impl TryFrom<ProcessInfoRow> for ProcessInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ProcessInfoRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pid: value.process_id,
+            created_at: value.created_at,
+            owners: value.owners,
+            document_count: u64::try_from(value.document_count)?,
+        })
+    }
+}
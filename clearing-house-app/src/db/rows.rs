@@ -0,0 +1,339 @@
+//! Rows of the SQL database backends
+
+use crate::model::crypto::WrappedDataKey;
+use crate::model::document::Document;
+use crate::model::ids::message::PayloadEncoding;
+use crate::model::ids::{InfoModelDateTime, InfoModelId, MessageType};
+use crate::model::merkle::MerkleRoot;
+use crate::model::process::{Retention, RetentionPolicy, ShredCertificate, Tombstone};
+#[cfg(feature = "postgres")]
+use crate::model::search::SearchHit;
+use std::collections::HashMap;
+
+/// Cast u64 to i64 with out-of-range check
+pub(super) fn cast_i64(value: u64) -> anyhow::Result<i64> {
+    if value > i64::MAX as u64 {
+        Err(anyhow::anyhow!("size out-of-range"))
+    } else {
+        #[allow(clippy::cast_possible_wrap)]
+        Ok(value as i64)
+    }
+}
+
+/// IDS message type of its stored name, `None` if the name is unknown
+fn parse_message_type(name: &str) -> Option<MessageType> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+/// Stored name of a payload encoding
+fn encoding_name(encoding: PayloadEncoding) -> &'static str {
+    match encoding {
+        PayloadEncoding::Base64 => "base64",
+    }
+}
+
+/// Payload encoding of its stored name, `None` if the name is unknown
+fn parse_encoding(name: &str) -> Option<PayloadEncoding> {
+    match name {
+        "base64" => Some(PayloadEncoding::Base64),
+        _ => None,
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DocumentRow {
+    pub(super) id: uuid::Uuid,
+    pub(super) process_id: String,
+    /// `TIMESTAMPTZ` in Postgres, UTC as text in `SQLite`
+    pub(super) created_at: chrono::DateTime<chrono::Utc>,
+    pub(super) model_version: String,
+    pub(super) correlation_message: Option<String>,
+    pub(super) transfer_contract: Option<String>,
+    pub(super) issued: sqlx::types::Json<InfoModelDateTime>,
+    pub(super) issuer_connector: sqlx::types::Json<InfoModelId>,
+    pub(super) content_version: Option<String>,
+    pub(super) recipient_connector: Option<sqlx::types::Json<Vec<InfoModelId>>>,
+    pub(super) sender_agent: String,
+    pub(super) recipient_agent: Option<sqlx::types::Json<Vec<InfoModelId>>>,
+    /// Binary payloads as they were received, encrypted payloads as ciphertext
+    pub(super) payload: Option<Vec<u8>>,
+    pub(super) payload_type: Option<String>,
+    /// `NULL` for text payloads
+    pub(super) payload_encoding: Option<String>,
+    pub(super) message_id: Option<String>,
+    pub(super) previous_hash: Option<String>,
+    pub(super) hash: Option<String>,
+    /// `NULL` for documents logged before the digest was stored
+    pub(super) content_sha256: Option<String>,
+    pub(super) expired_at: Option<chrono::NaiveDateTime>,
+    pub(super) archived_at: Option<chrono::NaiveDateTime>,
+    pub(super) key_version: Option<i32>,
+    pub(super) message_type: Option<String>,
+    pub(super) transfer_contract_index: Option<String>,
+    /// `NULL` for documents logged before the context was stored, JSON `null` if the message had
+    /// no context
+    pub(super) message_context: Option<sqlx::types::Json<Option<HashMap<String, String>>>>,
+    pub(super) raw_header: Option<String>,
+}
+
+impl From<Document<String>> for DocumentRow {
+    fn from(value: Document<String>) -> Self {
+        let payload = match (value.key_version, value.content.payload_bytes()) {
+            (None, Ok(bytes)) => bytes,
+            _ => value
+                .content
+                .payload
+                .as_ref()
+                .map(|s| s.as_bytes().to_owned()),
+        };
+
+        Self {
+            id: value.id,
+            process_id: value.pid,
+            created_at: value.ts.to_utc(),
+            model_version: value.content.header.model_version,
+            correlation_message: value.content.header.correlation_message,
+            transfer_contract: value.content.header.transfer_contract,
+            issued: sqlx::types::Json(value.content.header.issued),
+            issuer_connector: sqlx::types::Json(value.content.header.issuer_connector),
+            content_version: value.content.header.content_version,
+            recipient_connector: value
+                .content
+                .header
+                .recipient_connector
+                .map(sqlx::types::Json),
+            sender_agent: value.content.header.sender_agent.to_string(),
+            recipient_agent: value.content.header.recipient_agent.map(sqlx::types::Json),
+            payload,
+            payload_type: value.content.payload_type,
+            payload_encoding: value
+                .content
+                .payload_encoding
+                .map(|e| encoding_name(e).to_string()),
+            message_id: value.content.header.id,
+            previous_hash: value.previous_hash,
+            hash: value.hash,
+            content_sha256: value.content_sha256,
+            expired_at: value.expired_at.map(|t| t.naive_utc()),
+            archived_at: value.archived_at.map(|t| t.naive_utc()),
+            key_version: value.key_version,
+            message_type: crate::model::document::message_type_name(
+                &value.content.header.type_message,
+            ),
+            transfer_contract_index: value.transfer_contract_index,
+            message_context: Some(sqlx::types::Json(value.content.header.context)),
+            raw_header: value.content.raw_header,
+        }
+    }
+}
+
+impl From<DocumentRow> for Document<String> {
+    fn from(value: DocumentRow) -> Self {
+        use base64::Engine;
+
+        // Documents logged before the message type and context were stored get the defaults
+        let mut header = crate::model::ids::message::IdsHeader::default();
+        if let Some(message_type) = value.message_type.as_deref().and_then(parse_message_type) {
+            header.type_message = message_type;
+        }
+        if let Some(context) = value.message_context {
+            header.context = context.0;
+        }
+        let payload_encoding = value.payload_encoding.as_deref().and_then(parse_encoding);
+        let payload = value
+            .payload
+            .map(|b| match (payload_encoding, value.key_version) {
+                (Some(PayloadEncoding::Base64), None) => {
+                    base64::engine::general_purpose::STANDARD.encode(b)
+                }
+                _ => String::from_utf8_lossy(&b).to_string(),
+            });
+
+        Self {
+            id: value.id,
+            pid: value.process_id,
+            ts: value.created_at.with_timezone(&chrono::Local),
+            content: crate::model::ids::message::IdsMessage {
+                header: crate::model::ids::message::IdsHeader {
+                    model_version: value.model_version,
+                    correlation_message: value.correlation_message,
+                    transfer_contract: value.transfer_contract,
+                    issued: value.issued.0,
+                    issuer_connector: value.issuer_connector.0,
+                    content_version: value.content_version,
+                    recipient_connector: value.recipient_connector.map(|s| s.0),
+                    sender_agent: InfoModelId::SimpleId(value.sender_agent),
+                    recipient_agent: value.recipient_agent.map(|s| s.0),
+                    id: value.message_id,
+                    ..header
+                },
+                payload,
+                payload_type: value.payload_type,
+                payload_encoding,
+                raw_header: value.raw_header,
+            },
+            previous_hash: value.previous_hash,
+            hash: value.hash,
+            content_sha256: value.content_sha256,
+            expired_at: value.expired_at.map(|t| t.and_utc()),
+            archived_at: value.archived_at.map(|t| t.and_utc()),
+            key_version: value.key_version,
+            transfer_contract_index: value.transfer_contract_index,
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[derive(sqlx::FromRow)]
+pub(super) struct SearchHitRow {
+    process_id: String,
+    id: uuid::Uuid,
+    message_id: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    rank: f32,
+    highlight: String,
+}
+
+#[cfg(feature = "postgres")]
+impl From<SearchHitRow> for SearchHit {
+    fn from(value: SearchHitRow) -> Self {
+        Self {
+            pid: value.process_id,
+            id: value.id,
+            message_id: value.message_id,
+            created_at: value.created_at,
+            rank: value.rank,
+            highlight: value.highlight,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct MerkleRootRow {
+    id: i32,
+    process_id: Option<String>,
+    tree_size: i64,
+    root: String,
+    covers_up_to: Option<i32>,
+    signature: String,
+    created_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<MerkleRootRow> for MerkleRoot {
+    type Error = anyhow::Error;
+
+    fn try_from(value: MerkleRootRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: Some(value.id),
+            pid: value.process_id,
+            tree_size: u64::try_from(value.tree_size)?,
+            root: value.root,
+            covers_up_to: value.covers_up_to,
+            signature: value.signature,
+            created_at: value.created_at.and_utc(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct TombstoneRow {
+    process_id: String,
+    owners: sqlx::types::Json<Vec<String>>,
+    consents: sqlx::types::Json<Vec<String>>,
+    chain_length: i64,
+    chain_head: Option<String>,
+    deleted_at: chrono::NaiveDateTime,
+    signature: String,
+}
+
+impl TryFrom<TombstoneRow> for Tombstone {
+    type Error = anyhow::Error;
+
+    fn try_from(value: TombstoneRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pid: value.process_id,
+            owners: value.owners.0,
+            consents: value.consents.0,
+            chain_length: u64::try_from(value.chain_length)?,
+            chain_head: value.chain_head,
+            deleted_at: value.deleted_at.and_utc(),
+            signature: value.signature,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ShredCertificateRow {
+    process_id: String,
+    owners: sqlx::types::Json<Vec<String>>,
+    consents: sqlx::types::Json<Vec<String>>,
+    key_versions: sqlx::types::Json<Vec<i32>>,
+    chain_length: i64,
+    chain_head: Option<String>,
+    shredded_at: chrono::NaiveDateTime,
+    signature: String,
+}
+
+impl TryFrom<ShredCertificateRow> for ShredCertificate {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ShredCertificateRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pid: value.process_id,
+            owners: value.owners.0,
+            consents: value.consents.0,
+            key_versions: value.key_versions.0,
+            chain_length: u64::try_from(value.chain_length)?,
+            chain_head: value.chain_head,
+            shredded_at: value.shredded_at.and_utc(),
+            signature: value.signature,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct RetentionRow {
+    retention_days: Option<i32>,
+    retention_start: Option<String>,
+    closed_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<RetentionRow> for Retention {
+    type Error = anyhow::Error;
+
+    fn try_from(value: RetentionRow) -> Result<Self, Self::Error> {
+        let policy = match (value.retention_days, value.retention_start) {
+            (Some(days), Some(start)) => Some(RetentionPolicy {
+                days: u32::try_from(days)?,
+                start: start.parse()?,
+            }),
+            _ => None,
+        };
+
+        Ok(Self {
+            policy,
+            closed_at: value.closed_at.map(|t| t.and_utc()),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DataKeyRow {
+    process_id: String,
+    key_version: i32,
+    master_key_id: String,
+    wrapped_key: Vec<u8>,
+    created_at: chrono::NaiveDateTime,
+}
+
+impl From<DataKeyRow> for WrappedDataKey {
+    fn from(value: DataKeyRow) -> Self {
+        Self {
+            pid: value.process_id,
+            version: value.key_version,
+            master_key_id: value.master_key_id,
+            wrapped_key: value.wrapped_key,
+            created_at: value.created_at.and_utc(),
+        }
+    }
+}
@@ -0,0 +1,1561 @@
+use super::rows::{cast_i64, DataKeyRow, DocumentRow, MerkleRootRow};
+use crate::model::crypto::WrappedDataKey;
+use crate::model::document::{Document, DocumentCursor, DocumentFilter};
+use crate::model::merkle::MerkleRoot;
+use crate::model::search::{self, SearchHit};
+use crate::model::SortingOrder;
+
+pub(crate) struct SqliteDocumentStore {
+    db: sqlx::SqlitePool,
+}
+
+impl SqliteDocumentStore {
+    pub(crate) async fn new(db: sqlx::SqlitePool, clear_db: bool) -> Self {
+        if clear_db {
+            info!("Clearing database 'documents'");
+            sqlx::query("DELETE FROM documents")
+                .execute(&db)
+                .await
+                .expect("Clearing database 'documents' failed");
+        }
+
+        Self { db }
+    }
+}
+
+/// Appends the `WHERE` clause selecting the documents of `pid` created between `date_from` and
+/// `date_to`, which match `filter`
+fn push_conditions(
+    query: &mut sqlx::QueryBuilder<'_, sqlx::Sqlite>,
+    pid: &str,
+    (date_from, date_to): (
+        &chrono::DateTime<chrono::Utc>,
+        &chrono::DateTime<chrono::Utc>,
+    ),
+    filter: &DocumentFilter,
+) {
+    query
+        .push(" WHERE processes.process_id = ")
+        .push_bind(pid.to_string())
+        .push(" AND documents.created_at BETWEEN ")
+        .push_bind(date_from.naive_utc())
+        .push(" AND ")
+        .push_bind(date_to.naive_utc());
+
+    let columns = [
+        ("documents.message_type", &filter.message_type),
+        (
+            r#"COALESCE(json_extract(documents.issuer_connector, '$."@id"'), json_extract(documents.issuer_connector, '$'))"#,
+            &filter.issuer_connector,
+        ),
+        ("documents.sender_agent", &filter.sender_agent),
+        ("documents.correlation_message", &filter.correlation_message),
+        ("documents.payload_type", &filter.payload_type),
+    ];
+    for (column, value) in columns {
+        if let Some(value) = value {
+            query
+                .push(format!(" AND {column} = "))
+                .push_bind(value.clone());
+        }
+    }
+
+    // Encrypted documents are only found by the blind index of the transfer contract
+    if let Some(transfer_contract) = &filter.transfer_contract {
+        query
+            .push(" AND (documents.transfer_contract = ")
+            .push_bind(transfer_contract.clone());
+        if !filter.transfer_contract_indexes.is_empty() {
+            query.push(" OR documents.transfer_contract_index IN (");
+            let mut indexes = query.separated(", ");
+            for index in &filter.transfer_contract_indexes {
+                indexes.push_bind(index.clone());
+            }
+            indexes.push_unseparated(")");
+        }
+        query.push(")");
+    }
+
+    // GLOB is case-sensitive unlike LIKE, its wildcards are matched literally inside brackets
+    if let Some(prefix) = &filter.id_prefix {
+        let pattern: String = prefix
+            .chars()
+            .map(|c| match c {
+                '*' | '?' | '[' => format!("[{c}]"),
+                c => c.to_string(),
+            })
+            .collect();
+        query
+            .push(" AND documents.message_id GLOB ")
+            .push_bind(format!("{pattern}*"));
+    }
+}
+
+/// Inserts `doc` with `executor`, which is either the pool or a transaction
+async fn insert_document<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    doc: Document<String>,
+) -> anyhow::Result<()> {
+    let doc = DocumentRow::from(doc);
+
+    sqlx::query(
+        r"INSERT INTO documents
+    (id, process_id, created_at, model_version, correlation_message,
+    transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+    sender_agent, recipient_agent, payload, payload_type, message_id,
+    previous_hash, hash, key_version, message_type, transfer_contract_index,
+    message_context, raw_header, payload_encoding, content_sha256)
+    VALUES
+    ($1, (SELECT id from processes where process_id = $2), $3, $4, $5,
+    $6, $7, $8, $9, $10,
+    $11, $12, $13, $14, $15,
+    $16, $17, $18, $19, $20,
+    $21, $22, $23, $24)",
+    )
+    .bind(doc.id) // 1
+    .bind(doc.process_id) // 2
+    .bind(doc.created_at.naive_utc()) // 3
+    .bind(doc.model_version) // 4
+    .bind(doc.correlation_message) // 5
+    .bind(doc.transfer_contract) // 6
+    .bind(doc.issued) // 7
+    .bind(doc.issuer_connector) // 8
+    .bind(doc.content_version) // 9
+    .bind(doc.recipient_connector) // 10
+    .bind(doc.sender_agent) // 11
+    .bind(doc.recipient_agent) // 12
+    .bind(doc.payload) // 13
+    .bind(doc.payload_type) // 14
+    .bind(doc.message_id) // 15
+    .bind(doc.previous_hash) // 16
+    .bind(doc.hash) // 17
+    .bind(doc.key_version) // 18
+    .bind(doc.message_type) // 19
+    .bind(doc.transfer_contract_index) // 20
+    .bind(doc.message_context) // 21
+    .bind(doc.raw_header) // 22
+    .bind(doc.payload_encoding) // 23
+    .bind(doc.content_sha256) // 24
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+impl super::DocumentStore for SqliteDocumentStore {
+    async fn add_document(&self, doc: Document<String>) -> anyhow::Result<bool> {
+        insert_document(&self.db, doc).await?;
+
+        Ok(true)
+    }
+
+    async fn add_documents(&self, docs: Vec<Document<String>>) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+        for doc in docs {
+            insert_document(&mut *tx, doc).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn exists_document(&self, id: &uuid::Uuid) -> anyhow::Result<bool> {
+        sqlx::query("SELECT id FROM documents WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map(|r| r.is_some())
+            .map_err(std::convert::Into::into)
+    }
+
+    async fn get_document(&self, id: &str, pid: &str) -> anyhow::Result<Option<Document<String>>> {
+        // Ids are stored as blob, so a malformed id cannot match any document
+        let Ok(id) = uuid::Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE documents.id = $1 AND processes.process_id = $2",
+        )
+        .bind(id)
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await
+        .map(|r| r.map(DocumentRow::into))
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_document_by_message_id(
+        &self,
+        pid: &str,
+        message_id: &str,
+    ) -> anyhow::Result<Option<Document<String>>> {
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.message_id = $2
+        ORDER BY documents.created_at ASC, documents.id ASC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .bind(message_id)
+        .fetch_optional(&self.db)
+        .await
+        .map(|r| r.map(DocumentRow::into))
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_documents_for_pid(
+        &self,
+        pid: &str,
+        filter: &DocumentFilter,
+        (page, size): (u64, u64),
+        sort: &SortingOrder,
+        dates: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        cursor: Option<&DocumentCursor>,
+    ) -> anyhow::Result<Vec<Document<String>>> {
+        let (sort_order, cursor_op) = match sort {
+            SortingOrder::Ascending => ("ASC", ">"),
+            SortingOrder::Descending => ("DESC", "<"),
+        };
+
+        // Timestamps are stored as text in the same format as the bound parameters, so that
+        // comparing and sorting them lexicographically yields the chronological order
+        let mut query = sqlx::QueryBuilder::new(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id",
+        );
+        push_conditions(&mut query, pid, dates, filter);
+        // A cursor replaces the offset, the id orders documents with the same timestamp
+        let offset = match cursor {
+            Some(cursor) => {
+                query
+                    .push(format!(
+                        " AND (documents.created_at, documents.id) {cursor_op} ("
+                    ))
+                    .push_bind(cursor.created_at.naive_utc())
+                    .push(", ")
+                    .push_bind(cursor.id)
+                    .push(")");
+                0
+            }
+            None => (page - 1) * size,
+        };
+        query
+            .push(format!(
+                " ORDER BY documents.created_at {sort_order}, documents.id {sort_order} LIMIT "
+            ))
+            .push_bind(cast_i64(size)?)
+            .push(" OFFSET ")
+            .push_bind(cast_i64(offset)?);
+
+        let rows = query
+            .build_query_as::<DocumentRow>()
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(rows.into_iter().map(DocumentRow::into).collect())
+    }
+
+    async fn count_documents_for_pid(
+        &self,
+        pid: &str,
+        dates: (
+            &chrono::DateTime<chrono::Utc>,
+            &chrono::DateTime<chrono::Utc>,
+        ),
+        filter: &DocumentFilter,
+    ) -> anyhow::Result<u64> {
+        let mut query = sqlx::QueryBuilder::new(
+            r"SELECT COUNT(*)
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id",
+        );
+        push_conditions(&mut query, pid, dates, filter);
+
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(&self.db)
+            .await?;
+
+        u64::try_from(count).map_err(std::convert::Into::into)
+    }
+
+    async fn search_documents(
+        &self,
+        owner: &str,
+        query: &str,
+        page: (u64, u64),
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let terms = search::terms(query);
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Without a full-text index, text payloads containing all terms are ranked by the fallback
+        let mut candidates = sqlx::QueryBuilder::new(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        JOIN processes ON processes.id = documents.process_id
+        JOIN process_owners po ON po.process_id = processes.id
+        JOIN clients c ON c.id = po.client_id
+        WHERE documents.key_version IS NULL AND documents.payload_encoding IS NULL
+        AND c.client_id = ",
+        );
+        candidates.push_bind(owner.to_string());
+        for term in &terms {
+            candidates
+                .push(" AND instr(lower(documents.payload), ")
+                .push_bind(term.clone())
+                .push(") > 0");
+        }
+        let rows = candidates
+            .build_query_as::<DocumentRow>()
+            .fetch_all(&self.db)
+            .await?;
+
+        search::fallback_search(rows.into_iter().map(DocumentRow::into), &terms, page)
+    }
+
+    async fn get_chain_head(&self, pid: &str) -> anyhow::Result<Option<String>> {
+        sqlx::query_scalar::<_, String>(
+            r"SELECT documents.hash
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM documents successor
+            WHERE successor.process_id = documents.process_id AND successor.previous_hash = documents.hash)
+        ORDER BY documents.created_at DESC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_chain_for_pid(&self, pid: &str) -> anyhow::Result<Vec<Document<String>>> {
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        ORDER BY documents.created_at ASC",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DocumentRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_chain_links(
+        &self,
+        pid: &str,
+    ) -> anyhow::Result<Vec<(uuid::Uuid, Option<String>, String)>> {
+        sqlx::query_as::<_, (uuid::Uuid, Option<String>, String)>(
+            r"SELECT documents.id, previous_hash, hash
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.hash IS NOT NULL
+        ORDER BY documents.created_at ASC",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_unpublished_pids(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            r"SELECT process_id, chained FROM (SELECT p.process_id,
+            (SELECT COUNT(*) FROM documents d WHERE d.process_id = p.id AND d.hash IS NOT NULL) AS chained,
+            COALESCE((SELECT r.tree_size FROM merkle_roots r WHERE r.process_id = p.id ORDER BY r.id DESC LIMIT 1), 0) AS published
+        FROM processes p) AS t
+        WHERE chained > published",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|(pid, chained)| Ok((pid, u64::try_from(chained)?)))
+            .collect()
+    }
+
+    async fn add_merkle_root(&self, root: &MerkleRoot) -> anyhow::Result<i32> {
+        sqlx::query_scalar::<_, i32>(
+            r"INSERT INTO merkle_roots
+        (process_id, tree_size, root, covers_up_to, signature, created_at)
+        VALUES
+        ((SELECT id from processes where process_id = $1), $2, $3, $4, $5, $6)
+        RETURNING id",
+        )
+        .bind(root.pid.as_deref()) // 1
+        .bind(cast_i64(root.tree_size)?) // 2
+        .bind(&root.root) // 3
+        .bind(root.covers_up_to) // 4
+        .bind(&root.signature) // 5
+        .bind(root.created_at.naive_utc()) // 6
+        .fetch_one(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_latest_merkle_root(
+        &self,
+        pid: Option<&str>,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Option<MerkleRoot>> {
+        sqlx::query_as::<_, MerkleRootRow>(
+            r"SELECT r.id, p.process_id, r.tree_size, r.root, r.covers_up_to, r.signature, r.created_at
+        FROM merkle_roots r
+        LEFT JOIN processes p ON p.id = r.process_id
+        WHERE (($1 IS NULL AND r.process_id IS NULL) OR p.process_id = $1)
+        AND ($2 IS NULL OR r.id <= $2)
+        ORDER BY r.id DESC
+        LIMIT 1",
+        )
+        .bind(pid)
+        .bind(max_id)
+        .fetch_optional(&self.db)
+        .await?
+        .map(MerkleRoot::try_from)
+        .transpose()
+    }
+
+    async fn get_latest_process_roots(
+        &self,
+        max_id: Option<i32>,
+    ) -> anyhow::Result<Vec<MerkleRoot>> {
+        sqlx::query_as::<_, MerkleRootRow>(
+            r"SELECT r.id, p.process_id, r.tree_size, r.root, r.covers_up_to, r.signature, r.created_at
+        FROM merkle_roots r
+        JOIN processes p ON p.id = r.process_id
+        WHERE r.id = (SELECT MAX(latest.id) FROM merkle_roots latest
+            WHERE latest.process_id = r.process_id AND ($1 IS NULL OR latest.id <= $1))
+        ORDER BY p.process_id",
+        )
+        .bind(max_id)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(MerkleRoot::try_from)
+        .collect()
+    }
+
+    async fn expire_documents(&self, now: chrono::DateTime<chrono::Utc>) -> anyhow::Result<u64> {
+        sqlx::query(
+            r"WITH retention AS (
+            SELECT p.id AS process_id, p.closed_at,
+                COALESCE(r.retention_days, g.retention_days) AS retention_days,
+                COALESCE(r.retention_start, g.retention_start) AS retention_start
+            FROM processes p
+            LEFT JOIN retention_policies r ON r.process_id = p.id
+            LEFT JOIN retention_policies g ON g.process_id IS NULL
+        )
+        UPDATE documents SET payload = NULL, payload_type = NULL, payload_encoding = NULL, expired_at = $1
+        FROM retention
+        WHERE documents.process_id = retention.process_id AND documents.expired_at IS NULL
+        AND datetime(CASE retention.retention_start
+            WHEN 'logged' THEN documents.created_at
+            WHEN 'closed' THEN max(documents.created_at, retention.closed_at)
+        END, '+' || retention.retention_days || ' days') <= datetime($1)",
+        )
+        .bind(now.naive_utc())
+        .execute(&self.db)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_archivable_pids(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<String>> {
+        sqlx::query_scalar::<_, String>(
+            r"SELECT DISTINCT p.process_id FROM processes p
+        JOIN documents d ON d.process_id = p.id
+        WHERE d.created_at < $1 AND d.archived_at IS NULL AND d.expired_at IS NULL
+        ORDER BY p.process_id",
+        )
+        .bind(before.naive_utc())
+        .fetch_all(&self.db)
+        .await
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_archivable_documents(
+        &self,
+        pid: &str,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<Document<String>>> {
+        sqlx::query_as::<_, DocumentRow>(
+            r"SELECT documents.id, processes.process_id, documents.created_at, model_version, correlation_message,
+        transfer_contract, issued, issuer_connector, content_version, recipient_connector,
+        sender_agent, recipient_agent, payload, payload_type, message_id, previous_hash, hash, expired_at, archived_at, key_version,
+        message_type, transfer_contract_index, message_context, raw_header, payload_encoding,
+        content_sha256
+        FROM documents
+        LEFT JOIN processes ON processes.id = documents.process_id
+        WHERE processes.process_id = $1 AND documents.created_at < $2
+        AND documents.archived_at IS NULL AND documents.expired_at IS NULL
+        ORDER BY documents.created_at ASC",
+        )
+        .bind(pid)
+        .bind(before.naive_utc())
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DocumentRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn archive_documents(
+        &self,
+        ids: &[uuid::Uuid],
+        archived_at: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        for id in ids {
+            sqlx::query(
+                r"UPDATE documents SET payload = NULL, payload_type = NULL, payload_encoding = NULL, archived_at = $2
+            WHERE id = $1",
+            )
+            .bind(id)
+            .bind(archived_at.naive_utc())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_data_keys(&self, pid: &str) -> anyhow::Result<Vec<WrappedDataKey>> {
+        sqlx::query_as::<_, DataKeyRow>(
+            r"SELECT p.process_id, k.key_version, k.master_key_id, k.wrapped_key, k.created_at
+        FROM data_keys k
+        JOIN processes p ON p.id = k.process_id
+        WHERE p.process_id = $1
+        ORDER BY k.key_version",
+        )
+        .bind(pid)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DataKeyRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn add_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<bool> {
+        sqlx::query(
+            r"INSERT INTO data_keys (process_id, key_version, master_key_id, wrapped_key, created_at)
+        SELECT id, $2, $3, $4, $5 FROM processes WHERE process_id = $1
+        AND NOT EXISTS (SELECT 1 FROM shred_certificates s WHERE s.process_id = processes.id)
+        ON CONFLICT DO NOTHING",
+        )
+        .bind(&key.pid) // 1
+        .bind(key.version) // 2
+        .bind(&key.master_key_id) // 3
+        .bind(&key.wrapped_key) // 4
+        .bind(key.created_at.naive_utc()) // 5
+        .execute(&self.db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn get_data_keys_not_wrapped_by(
+        &self,
+        master_key_id: &str,
+    ) -> anyhow::Result<Vec<WrappedDataKey>> {
+        sqlx::query_as::<_, DataKeyRow>(
+            r"SELECT p.process_id, k.key_version, k.master_key_id, k.wrapped_key, k.created_at
+        FROM data_keys k
+        JOIN processes p ON p.id = k.process_id
+        WHERE k.master_key_id <> $1
+        ORDER BY p.process_id, k.key_version",
+        )
+        .bind(master_key_id)
+        .fetch_all(&self.db)
+        .await
+        .map(|r| r.into_iter().map(DataKeyRow::into).collect())
+        .map_err(std::convert::Into::into)
+    }
+
+    async fn update_data_key(&self, key: &WrappedDataKey) -> anyhow::Result<()> {
+        sqlx::query(
+            r"UPDATE data_keys SET master_key_id = $3, wrapped_key = $4
+        WHERE process_id = (SELECT id FROM processes WHERE process_id = $1) AND key_version = $2",
+        )
+        .bind(&key.pid) // 1
+        .bind(key.version) // 2
+        .bind(&key.master_key_id) // 3
+        .bind(&key.wrapped_key) // 4
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqliteDocumentStore;
+    use crate::db::sqlite_process_store::SqliteProcessStore;
+    use crate::db::{DocumentStore, ProcessStore};
+    use crate::model::crypto::WrappedDataKey;
+    use crate::model::document::{Document, DocumentCursor, DocumentFilter, GENESIS_HASH};
+    use crate::model::ids::message::IdsHeader;
+    use crate::model::ids::{InfoModelComplexId, InfoModelId, MessageType};
+    use crate::model::process::{
+        Process, Retention, RetentionPolicy, RetentionStart, ShredCertificate, Tombstone,
+    };
+    use crate::model::SortingOrder;
+    use chrono::SubsecRound;
+
+    async fn stores() -> (SqliteProcessStore, SqliteDocumentStore) {
+        let pool = crate::setup_sqlite("sqlite::memory:")
+            .await
+            .expect("Setting up database");
+        let process_store = SqliteProcessStore::new(pool.clone(), false).await;
+        let doc_store = SqliteDocumentStore::new(pool, false).await;
+        (process_store, doc_store)
+    }
+
+    fn document(pid: &str, offset: i64) -> Document<String> {
+        let mut doc = crate::test_util::document(pid, &format!("payload {offset}"));
+        doc.ts += chrono::Duration::seconds(offset);
+        doc
+    }
+
+    fn payloads(docs: &[Document<String>]) -> Vec<String> {
+        docs.iter()
+            .map(|d| d.content.payload.clone().unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn processes() {
+        let (process_store, doc_store) = stores().await;
+        let owners = vec!["owner".to_string(), "other".to_string()];
+
+        process_store
+            .store_process(Process::new("pid".to_string(), owners.clone()))
+            .await
+            .expect("Storing process");
+        process_store
+            .store_process(Process::new("empty".to_string(), vec![]))
+            .await
+            .expect("Storing process");
+        assert!(process_store
+            .store_process(Process::new("pid".to_string(), vec![]))
+            .await
+            .is_err());
+
+        let mut stored = process_store
+            .get_process("pid")
+            .await
+            .expect("Reading")
+            .expect("Process exists")
+            .owners;
+        stored.sort();
+        assert_eq!(stored, vec!["other".to_string(), "owner".to_string()]);
+        assert!(process_store
+            .get_process("empty")
+            .await
+            .expect("Reading")
+            .is_some_and(|p| p.owners.is_empty()));
+        assert_eq!(
+            process_store.get_processes().await.expect("Reading").len(),
+            2
+        );
+
+        process_store
+            .update_owners("pid", &["new".to_string()], &["owner".to_string()])
+            .await
+            .expect("Updating owners");
+        let mut stored = process_store
+            .get_process("pid")
+            .await
+            .expect("Reading")
+            .expect("Process exists")
+            .owners;
+        stored.sort();
+        assert_eq!(stored, vec!["new".to_string(), "other".to_string()]);
+        assert!(process_store
+            .update_owners("unknown", &[], &[])
+            .await
+            .is_err());
+
+        let doc = document("pid", 0);
+        doc_store
+            .add_document(doc.clone())
+            .await
+            .expect("Storing document");
+
+        let listed = process_store
+            .get_processes_for_owner("new", 1, 10)
+            .await
+            .expect("Listing");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            (listed[0].pid.as_str(), listed[0].document_count),
+            ("pid", 1)
+        );
+        assert_eq!(listed[0].owners.len(), 2);
+        assert!(listed[0].created_at.is_some());
+        assert!(process_store
+            .get_processes_for_owner("new", 2, 10)
+            .await
+            .expect("Listing")
+            .is_empty());
+
+        assert_eq!(
+            process_store
+                .add_deletion_consent("pid", "other")
+                .await
+                .expect("Consenting"),
+            vec!["other".to_string()]
+        );
+        assert_eq!(
+            process_store
+                .get_deletion_consents("pid")
+                .await
+                .expect("Reading consents"),
+            vec!["other".to_string()]
+        );
+        let tombstone = Tombstone {
+            pid: "pid".to_string(),
+            owners: vec!["other".to_string()],
+            consents: vec!["other".to_string()],
+            chain_length: 0,
+            chain_head: None,
+            deleted_at: chrono::Utc::now().trunc_subsecs(6),
+            signature: "signature".to_string(),
+        };
+        assert!(process_store
+            .delete_process("pid", &tombstone)
+            .await
+            .expect("Deleting"));
+        assert!(process_store
+            .delete_process("pid", &tombstone)
+            .await
+            .is_err());
+        assert!(!process_store
+            .delete_process("unknown", &tombstone)
+            .await
+            .expect("Deleting"));
+        assert_eq!(
+            process_store.get_tombstone("pid").await.expect("Reading"),
+            Some(tombstone)
+        );
+        assert!(process_store
+            .get_process("pid")
+            .await
+            .expect("Reading")
+            .is_some_and(|p| p.owners.is_empty()));
+        assert!(!doc_store.exists_document(&doc.id).await.expect("Reading"));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn documents() {
+        let (process_store, doc_store) = stores().await;
+        for pid in ["pid", "other"] {
+            process_store
+                .store_process(Process::new(pid.to_string(), vec!["owner".to_string()]))
+                .await
+                .expect("Storing process");
+        }
+
+        let mut docs = Vec::new();
+        for offset in 0..5 {
+            let doc = document("pid", offset);
+            docs.push(doc.clone());
+            doc_store.add_document(doc).await.expect("Storing document");
+        }
+        doc_store
+            .add_document(document("other", 0))
+            .await
+            .expect("Storing document");
+
+        // Pagination and sorting
+        let all = DocumentFilter::default();
+        let (from, to) = (docs[0].ts.to_utc(), docs[4].ts.to_utc());
+        let page = doc_store
+            .get_documents_for_pid(
+                "pid",
+                &all,
+                (2, 2),
+                &SortingOrder::Descending,
+                (&from, &to),
+                None,
+            )
+            .await
+            .expect("Reading documents");
+        assert_eq!(payloads(&page), vec!["payload 2", "payload 1"]);
+
+        // Date filtering is inclusive
+        let from = docs[1].ts.to_utc();
+        let to = docs[3].ts.to_utc();
+        let page = doc_store
+            .get_documents_for_pid(
+                "pid",
+                &all,
+                (1, 10),
+                &SortingOrder::Ascending,
+                (&from, &to),
+                None,
+            )
+            .await
+            .expect("Reading documents");
+        assert_eq!(payloads(&page), vec!["payload 1", "payload 2", "payload 3"]);
+        assert_eq!(
+            doc_store
+                .count_documents_for_pid("pid", (&from, &to), &all)
+                .await
+                .expect("Counting documents"),
+            3
+        );
+
+        // Documents are only returned for their own process
+        let id = docs[0].id.to_string();
+        assert!(doc_store
+            .get_document(&id, "pid")
+            .await
+            .expect("Reading document")
+            .is_some_and(|d| d.id == docs[0].id && d.content.payload == docs[0].content.payload));
+        assert!(doc_store
+            .get_document(&id, "other")
+            .await
+            .expect("Reading document")
+            .is_none());
+        assert!(doc_store
+            .exists_document(&docs[0].id)
+            .await
+            .expect("Reading"));
+
+        // Cursors continue after the previous page, documents logged at the same time are
+        // ordered by their id
+        let mut twin = document("pid", 2);
+        twin.ts = docs[2].ts;
+        doc_store
+            .add_document(twin.clone())
+            .await
+            .expect("Storing document");
+        let from = docs[0].ts.to_utc();
+        let to = docs[4].ts.to_utc();
+        for sort in [SortingOrder::Ascending, SortingOrder::Descending] {
+            let mut expected: Vec<_> = docs.iter().chain([&twin]).map(|d| (d.ts, d.id)).collect();
+            expected.sort();
+            if matches!(sort, SortingOrder::Descending) {
+                expected.reverse();
+            }
+
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = doc_store
+                    .get_documents_for_pid(
+                        "pid",
+                        &all,
+                        (1, 2),
+                        &sort,
+                        (&from, &to),
+                        cursor.as_ref(),
+                    )
+                    .await
+                    .expect("Reading documents");
+                let Some(last) = page.last() else {
+                    break;
+                };
+                cursor = Some(DocumentCursor::after(last));
+                paged.extend(page.iter().map(|d| (d.ts, d.id)));
+            }
+            assert_eq!(paged, expected);
+        }
+    }
+
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn filters() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let mut log = document("pid", 0);
+        log.content.header.type_message = MessageType::LogMessage;
+        log.content.header.id = Some("https://msg/log_1".to_string());
+        log.content.header.issuer_connector = InfoModelId::new("https://connector/a".to_string());
+        log.content.header.sender_agent = InfoModelId::new("https://agent/a".to_string());
+        log.content.header.correlation_message = Some("https://msg/request".to_string());
+        log.content.payload_type = Some("application/json".to_string());
+        let mut request = document("pid", 1);
+        request.content.header.type_message = MessageType::RequestMessage;
+        request.content.header.id = Some("https://msg/request".to_string());
+        request.content.header.issuer_connector =
+            InfoModelId::ComplexId(InfoModelComplexId::new("https://connector/b".to_string()));
+        request.content.header.transfer_contract = Some("https://contract".to_string());
+        // Encrypted documents only expose the blind index of their transfer contract
+        let mut encrypted = document("pid", 2);
+        encrypted.content.header.id = Some("https://msg/log_%".to_string());
+        encrypted.content.header.transfer_contract = Some("ciphertext".to_string());
+        encrypted.transfer_contract_index = Some("index".to_string());
+        encrypted.key_version = Some(1);
+        for doc in [&log, &request, &encrypted] {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
+        }
+
+        let from = log.ts.to_utc();
+        let to = encrypted.ts.to_utc();
+        let query = |filter: DocumentFilter| {
+            let doc_store = &doc_store;
+            async move {
+                let docs = doc_store
+                    .get_documents_for_pid(
+                        "pid",
+                        &filter,
+                        (1, 10),
+                        &SortingOrder::Ascending,
+                        (&from, &to),
+                        None,
+                    )
+                    .await
+                    .expect("Reading documents");
+                let count = doc_store
+                    .count_documents_for_pid("pid", (&from, &to), &filter)
+                    .await
+                    .expect("Counting documents");
+                assert_eq!(u64::try_from(docs.len()).ok(), Some(count));
+                docs.iter().map(|d| d.id).collect::<Vec<_>>()
+            }
+        };
+        let some = |value: &str| Some(value.to_string());
+
+        assert_eq!(query(DocumentFilter::default()).await.len(), 3);
+        assert_eq!(
+            query(DocumentFilter {
+                message_type: some("ids:LogMessage"),
+                ..DocumentFilter::default()
+            })
+            .await,
+            vec![log.id]
+        );
+        for (issuer, expected) in [
+            ("https://connector/a", log.id),
+            ("https://connector/b", request.id),
+        ] {
+            assert_eq!(
+                query(DocumentFilter {
+                    issuer_connector: some(issuer),
+                    ..DocumentFilter::default()
+                })
+                .await,
+                vec![expected]
+            );
+        }
+        assert_eq!(
+            query(DocumentFilter {
+                sender_agent: some("https://agent/a"),
+                correlation_message: some("https://msg/request"),
+                payload_type: some("application/json"),
+                ..DocumentFilter::default()
+            })
+            .await,
+            vec![log.id]
+        );
+
+        // Transfer contracts match in plaintext or by one of the given blind indexes
+        let contract = |indexes: &[&str]| DocumentFilter {
+            transfer_contract: some("https://contract"),
+            transfer_contract_indexes: indexes.iter().map(ToString::to_string).collect(),
+            ..DocumentFilter::default()
+        };
+        assert_eq!(query(contract(&[])).await, vec![request.id]);
+        assert_eq!(
+            query(contract(&["other", "index"])).await,
+            vec![request.id, encrypted.id]
+        );
+
+        // Id prefixes are case-sensitive and wildcards are matched literally
+        let prefix = |prefix: &str| DocumentFilter {
+            id_prefix: some(prefix),
+            ..DocumentFilter::default()
+        };
+        assert_eq!(query(prefix("https://msg/")).await.len(), 3);
+        assert_eq!(
+            query(prefix("https://msg/log_")).await,
+            vec![log.id, encrypted.id]
+        );
+        assert_eq!(query(prefix("https://msg/log_%")).await, vec![encrypted.id]);
+        assert!(query(prefix("https://msg/log*")).await.is_empty());
+        assert!(query(prefix("HTTPS://msg/")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn search() {
+        let (process_store, doc_store) = stores().await;
+        for (pid, owner) in [("pid", "owner"), ("other", "other")] {
+            process_store
+                .store_process(Process::new(pid.to_string(), vec![owner.to_string()]))
+                .await
+                .expect("Storing process");
+        }
+
+        let mut matching = document("pid", 0);
+        matching.content.payload = Some("Delivery of ASSET X".to_string());
+        let mut encrypted = document("pid", 1);
+        encrypted.content.payload = Some("asset x".to_string());
+        encrypted.key_version = Some(1);
+        let mut foreign = document("other", 0);
+        foreign.content.payload = Some("asset x".to_string());
+        let mut binary = document("pid", 3);
+        binary
+            .content
+            .set_payload(Some("application/octet-stream".to_string()), b"asset x");
+        for doc in [
+            &matching,
+            &encrypted,
+            &foreign,
+            &binary,
+            &document("pid", 2),
+        ] {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
+        }
+
+        // Only plaintext text payloads of owned processes are searched
+        let hits = doc_store
+            .search_documents("owner", "asset x", (1, 10))
+            .await
+            .expect("Searching");
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].pid.as_str(), hits[0].id), ("pid", matching.id));
+        assert_eq!(hits[0].highlight, "Delivery of <b>ASSET</b> <b>X</b>");
+        assert!(doc_store
+            .search_documents("owner", "asset delivered", (1, 10))
+            .await
+            .expect("Searching")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn message_type_and_context() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let mut agreement = document("pid", 0);
+        agreement.content.header.type_message = MessageType::ContractAgreementMessage;
+        agreement.content.header.context = Some(std::collections::HashMap::from([(
+            "ids".to_string(),
+            "https://w3id.org/idsa/core/".to_string(),
+        )]));
+        let mut without_context = document("pid", 1);
+        without_context.content.header.type_message = MessageType::LogMessage;
+        without_context.content.header.context = None;
+        let legacy = document("pid", 2);
+        for doc in [&agreement, &without_context, &legacy] {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
+        }
+        // Documents logged before the type and context were stored
+        sqlx::query(
+            "UPDATE documents SET message_type = NULL, message_context = NULL WHERE id = $1",
+        )
+        .bind(legacy.id)
+        .execute(&doc_store.db)
+        .await
+        .expect("Updating document");
+
+        let doc_store = &doc_store;
+        let get = |id: uuid::Uuid| async move {
+            doc_store
+                .get_document(&id.to_string(), "pid")
+                .await
+                .expect("Reading")
+                .expect("Document exists")
+                .content
+                .header
+        };
+        for doc in [&agreement, &without_context] {
+            let header = get(doc.id).await;
+            assert_eq!(
+                serde_json::to_value(&header.type_message).ok(),
+                serde_json::to_value(&doc.content.header.type_message).ok()
+            );
+            assert_eq!(header.context, doc.content.header.context);
+        }
+        let header = get(legacy.id).await;
+        assert!(matches!(header.type_message, MessageType::Message));
+        assert_eq!(header.context, IdsHeader::default().context);
+    }
+
+    #[tokio::test]
+    async fn binary_payload() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let data = b"%PDF-1.7\n\xe2\xe3\xcf\xd3".to_vec();
+        let mut doc = document("pid", 0);
+        doc.content
+            .set_payload(Some("application/pdf".to_string()), &data);
+        doc_store
+            .add_document(doc.clone())
+            .await
+            .expect("Storing document");
+
+        // Stored as received
+        let stored: Vec<u8> = sqlx::query_scalar("SELECT payload FROM documents WHERE id = $1")
+            .bind(doc.id)
+            .fetch_one(&doc_store.db)
+            .await
+            .expect("Reading payload");
+        assert_eq!(stored, data);
+
+        let read = doc_store
+            .get_document(&doc.id.to_string(), "pid")
+            .await
+            .expect("Reading")
+            .expect("Document exists");
+        assert_eq!(read.content.payload, doc.content.payload);
+        assert_eq!(
+            read.content.payload_type.as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(
+            read.content.payload_bytes().expect("Valid payload"),
+            Some(data)
+        );
+    }
+
+    #[tokio::test]
+    async fn raw_header() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let mut doc = document("pid", 0);
+        doc.content.raw_header =
+            Some("{ \"@type\" : \"ids:LogMessage\",\n  \"custom:property\": [1, 2] }".to_string());
+        doc.link_to(GENESIS_HASH).expect("Document is serializable");
+        doc_store
+            .add_document(doc.clone())
+            .await
+            .expect("Storing document");
+
+        let stored = doc_store
+            .get_document(&doc.id.to_string(), "pid")
+            .await
+            .expect("Reading")
+            .expect("Document exists");
+        assert_eq!(stored.content.raw_header, doc.content.raw_header);
+        assert_eq!(stored.content_sha256, doc.content_sha256);
+        assert!(stored.is_linked_to(GENESIS_HASH));
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let first = document("pid", 0);
+        let second = document("pid", 1);
+        doc_store
+            .add_documents(vec![first.clone(), second.clone()])
+            .await
+            .expect("Storing documents");
+        for doc in [&first, &second] {
+            assert!(doc_store.exists_document(&doc.id).await.expect("Reading"));
+        }
+
+        // The transaction is rolled back, if a document cannot be stored
+        let third = document("pid", 2);
+        assert!(doc_store
+            .add_documents(vec![third.clone(), first])
+            .await
+            .is_err());
+        assert!(!doc_store.exists_document(&third.id).await.expect("Reading"));
+    }
+
+    #[tokio::test]
+    async fn message_id() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let mut first = document("pid", 0);
+        first.content.header.id = Some("msg".to_string());
+        first.content.header.transfer_contract = Some("contract".to_string());
+        let mut second = document("pid", 1);
+        second.content.header.id = Some("msg".to_string());
+        doc_store
+            .add_documents(vec![second, first.clone()])
+            .await
+            .expect("Storing documents");
+
+        // The first document with the message id is found, with unchanged content
+        let found = doc_store
+            .get_document_by_message_id("pid", "msg")
+            .await
+            .expect("Reading")
+            .expect("Document exists");
+        assert_eq!(found.id, first.id);
+        assert!(found.has_same_content(&first));
+        assert!(doc_store
+            .get_document_by_message_id("other", "msg")
+            .await
+            .expect("Reading")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn merkle_roots() {
+        use crate::model::merkle::MerkleRoot;
+
+        let (process_store, doc_store) = stores().await;
+        for pid in ["a", "b"] {
+            process_store
+                .store_process(Process::new(pid.to_string(), vec![]))
+                .await
+                .expect("Storing process");
+        }
+
+        let root = |pid: Option<&str>, tree_size| MerkleRoot {
+            id: None,
+            pid: pid.map(ToString::to_string),
+            tree_size,
+            root: format!("root {tree_size}"),
+            covers_up_to: None,
+            signature: String::new(),
+            created_at: chrono::Utc::now(),
+        };
+        let first = doc_store
+            .add_merkle_root(&root(Some("a"), 1))
+            .await
+            .expect("Storing");
+        doc_store
+            .add_merkle_root(&root(Some("b"), 2))
+            .await
+            .expect("Storing");
+        doc_store
+            .add_merkle_root(&root(Some("a"), 3))
+            .await
+            .expect("Storing");
+        doc_store
+            .add_merkle_root(&root(None, 4))
+            .await
+            .expect("Storing");
+
+        let latest = |pid, max_id| doc_store.get_latest_merkle_root(pid, max_id);
+        let size = |r: Option<MerkleRoot>| r.map(|r| r.tree_size);
+        assert_eq!(
+            size(latest(Some("a"), None).await.expect("Reading")),
+            Some(3)
+        );
+        assert_eq!(
+            size(latest(Some("a"), Some(first)).await.expect("Reading")),
+            Some(1)
+        );
+        assert_eq!(size(latest(None, None).await.expect("Reading")), Some(4));
+
+        let process_roots = doc_store
+            .get_latest_process_roots(Some(first + 1))
+            .await
+            .expect("Reading");
+        assert_eq!(
+            process_roots
+                .iter()
+                .map(|r| (r.pid.as_deref(), r.tree_size))
+                .collect::<Vec<_>>(),
+            vec![(Some("a"), 1), (Some("b"), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        let (process_store, doc_store) = stores().await;
+        for pid in ["pid", "other"] {
+            process_store
+                .store_process(Process::new(pid.to_string(), vec!["owner".to_string()]))
+                .await
+                .expect("Storing process");
+        }
+        let now = chrono::Utc::now().trunc_subsecs(6);
+        let old = document("pid", -20 * 86400);
+        for doc in [
+            old.clone(),
+            document("pid", 0),
+            document("other", -20 * 86400),
+        ] {
+            doc_store.add_document(doc).await.expect("Storing document");
+        }
+
+        // Documents are kept forever without policy
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 0);
+        assert_eq!(
+            process_store.get_retention("pid").await.expect("Reading"),
+            Retention::default()
+        );
+
+        let global = RetentionPolicy {
+            days: 10,
+            start: RetentionStart::Closed,
+        };
+        process_store
+            .set_retention_policy(None, Some(&global))
+            .await
+            .expect("Setting policy");
+        process_store
+            .set_retention_policy(
+                Some("pid"),
+                Some(&RetentionPolicy {
+                    days: 10,
+                    start: RetentionStart::Logged,
+                }),
+            )
+            .await
+            .expect("Setting policy");
+        assert!(process_store
+            .set_retention_policy(Some("unknown"), Some(&global))
+            .await
+            .is_err());
+        assert_eq!(
+            process_store
+                .get_retention("other")
+                .await
+                .expect("Reading")
+                .policy,
+            Some(global.clone())
+        );
+
+        // Only the old document of the open process expires
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 1);
+        let expired = doc_store
+            .get_document(&old.id.to_string(), "pid")
+            .await
+            .expect("Reading")
+            .expect("Document exists");
+        assert!(expired.content.payload.is_none());
+        assert_eq!(expired.expired_at, Some(now));
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 0);
+
+        // A closed process keeps the time it was first closed
+        let closed_at = now - chrono::TimeDelta::days(15);
+        process_store
+            .close_process("other", closed_at)
+            .await
+            .expect("Closing");
+        process_store
+            .close_process("other", now)
+            .await
+            .expect("Closing");
+        assert_eq!(
+            process_store
+                .get_retention("other")
+                .await
+                .expect("Reading")
+                .closed_at,
+            Some(closed_at)
+        );
+        assert!(process_store.close_process("unknown", now).await.is_err());
+        assert_eq!(doc_store.expire_documents(now).await.expect("Expiring"), 1);
+
+        // Without own policy, the global policy applies
+        process_store
+            .set_retention_policy(Some("pid"), None)
+            .await
+            .expect("Removing policy");
+        assert_eq!(
+            process_store
+                .get_retention("pid")
+                .await
+                .expect("Reading")
+                .policy,
+            Some(global)
+        );
+    }
+
+    #[tokio::test]
+    async fn data_keys() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+
+        let key = |version: i32, master_key_id: &str| WrappedDataKey {
+            pid: "pid".to_string(),
+            version,
+            master_key_id: master_key_id.to_string(),
+            wrapped_key: vec![1, 2, 3],
+            created_at: chrono::Utc::now().trunc_subsecs(6),
+        };
+        let first = key(1, "old");
+        assert!(doc_store.add_data_key(&first).await.expect("Storing key"));
+        assert!(!doc_store
+            .add_data_key(&key(1, "new"))
+            .await
+            .expect("Storing key"));
+        assert!(doc_store
+            .add_data_key(&key(2, "new"))
+            .await
+            .expect("Storing key"));
+        assert!(!doc_store
+            .add_data_key(&WrappedDataKey {
+                pid: "unknown".to_string(),
+                ..key(1, "new")
+            })
+            .await
+            .expect("Storing key"));
+
+        let keys = doc_store.get_data_keys("pid").await.expect("Reading");
+        assert_eq!(
+            keys.iter().map(|k| k.version).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(keys[0], first);
+
+        let stale = doc_store
+            .get_data_keys_not_wrapped_by("new")
+            .await
+            .expect("Reading");
+        assert_eq!(stale, vec![first.clone()]);
+        doc_store
+            .update_data_key(&WrappedDataKey {
+                master_key_id: "new".to_string(),
+                wrapped_key: vec![4, 5, 6],
+                ..first
+            })
+            .await
+            .expect("Updating key");
+        assert!(doc_store
+            .get_data_keys_not_wrapped_by("new")
+            .await
+            .expect("Reading")
+            .is_empty());
+        assert_eq!(
+            doc_store.get_data_keys("pid").await.expect("Reading")[0].wrapped_key,
+            vec![4, 5, 6]
+        );
+    }
+
+    #[tokio::test]
+    async fn shredding() {
+        let (process_store, doc_store) = stores().await;
+        process_store
+            .store_process(Process::new("pid".to_string(), vec!["owner".to_string()]))
+            .await
+            .expect("Storing process");
+        let doc = document("pid", 0);
+        doc_store
+            .add_document(doc.clone())
+            .await
+            .expect("Storing document");
+        let key = WrappedDataKey {
+            pid: "pid".to_string(),
+            version: 1,
+            master_key_id: "master".to_string(),
+            wrapped_key: vec![1, 2, 3],
+            created_at: chrono::Utc::now().trunc_subsecs(6),
+        };
+        assert!(doc_store.add_data_key(&key).await.expect("Storing key"));
+
+        let certificate = ShredCertificate {
+            pid: "pid".to_string(),
+            owners: vec!["owner".to_string()],
+            consents: vec!["owner".to_string()],
+            key_versions: vec![1],
+            chain_length: 1,
+            chain_head: Some("head".to_string()),
+            shredded_at: chrono::Utc::now().trunc_subsecs(6),
+            signature: "signature".to_string(),
+        };
+        assert_eq!(
+            process_store
+                .get_shred_certificate("pid")
+                .await
+                .expect("Reading"),
+            None
+        );
+        assert!(process_store
+            .shred_process("pid", &certificate)
+            .await
+            .expect("Shredding"));
+        assert!(process_store
+            .shred_process("pid", &certificate)
+            .await
+            .is_err());
+        assert!(!process_store
+            .shred_process("unknown", &certificate)
+            .await
+            .expect("Shredding"));
+        assert_eq!(
+            process_store
+                .get_shred_certificate("pid")
+                .await
+                .expect("Reading"),
+            Some(certificate)
+        );
+
+        // The documents are kept, but the data keys are gone for good
+        assert!(doc_store.exists_document(&doc.id).await.expect("Reading"));
+        assert!(doc_store
+            .get_data_keys("pid")
+            .await
+            .expect("Reading")
+            .is_empty());
+        assert!(!doc_store.add_data_key(&key).await.expect("Storing key"));
+    }
+}
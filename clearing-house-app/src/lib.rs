@@ -18,38 +18,84 @@ mod db;
 pub mod model;
 mod ports;
 mod services;
+#[cfg(test)]
+mod test_util;
 pub mod util;
 
-type PostgresLoggingService = services::logging_service::LoggingService<
-    db::postgres_process_store::PostgresProcessStore,
-    db::postgres_document_store::PostgresDocumentStore,
->;
-
 /// Contains the application state
-#[derive(Clone)]
-pub(crate) struct AppState {
-    pub logging_service: Arc<PostgresLoggingService>,
+pub(crate) struct AppState<P, D> {
+    pub logging_service: Arc<services::logging_service::LoggingService<P, D>>,
     pub daps_client: Arc<ids_daps_client::ReqwestDapsClient>,
+    pub dat_cache: Arc<services::dat_cache::DatCache>,
+    pub health_service: Arc<services::health_service::HealthService>,
     pub cert_util: Arc<ids_daps_cert::CertUtil>,
 }
 
-impl AppState {
-    /// Connect to the database and execute database migrations
-    async fn setup_postgres(conf: &config::CHConfig) -> anyhow::Result<sqlx::PgPool> {
-        info!("Connecting to database");
-        let pool = sqlx::PgPool::connect(&conf.database_url).await?;
+// Manual implementation, because derive would require `P: Clone` and `D: Clone`
+impl<P, D> Clone for AppState<P, D> {
+    fn clone(&self) -> Self {
+        Self {
+            logging_service: self.logging_service.clone(),
+            daps_client: self.daps_client.clone(),
+            dat_cache: self.dat_cache.clone(),
+            health_service: self.health_service.clone(),
+            cert_util: self.cert_util.clone(),
+        }
+    }
+}
 
-        info!("Migrating database");
-        sqlx::migrate!()
-            .run(&pool)
-            .await
-            .expect("Failed to migrate database!");
+impl<P, D> axum::extract::FromRef<AppState<P, D>> for Arc<ids_daps_client::ReqwestDapsClient> {
+    fn from_ref(state: &AppState<P, D>) -> Self {
+        state.daps_client.clone()
+    }
+}
 
-        Ok(pool)
+impl<P: db::ProcessStore, D: db::DocumentStore> axum::extract::FromRef<AppState<P, D>>
+    for model::claims::Issuer
+{
+    fn from_ref(state: &AppState<P, D>) -> Self {
+        Self(state.logging_service.issuer().to_string())
     }
+}
+
+/// Connect to the database and execute database migrations
+#[cfg(feature = "postgres")]
+async fn setup_postgres(database_url: &str) -> anyhow::Result<sqlx::PgPool> {
+    info!("Connecting to database");
+    let pool = sqlx::PgPool::connect(database_url).await?;
 
-    /// Initialize the application state from config
-    async fn init(conf: &config::CHConfig) -> anyhow::Result<Self> {
+    info!("Migrating database");
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to migrate database!");
+
+    Ok(pool)
+}
+
+/// Connect to (or create) the `SQLite` database and execute database migrations
+#[cfg(feature = "sqlite")]
+async fn setup_sqlite(database_url: &str) -> anyhow::Result<sqlx::SqlitePool> {
+    use std::str::FromStr;
+
+    info!("Connecting to database");
+    let options = sqlx::sqlite::SqliteConnectOptions::from_str(database_url)?
+        .create_if_missing(true)
+        .foreign_keys(true);
+    let pool = sqlx::SqlitePool::connect_with(options).await?;
+
+    info!("Migrating database");
+    sqlx::migrate!("./migrations_sqlite")
+        .run(&pool)
+        .await
+        .expect("Failed to migrate database!");
+
+    Ok(pool)
+}
+
+impl<P: db::ProcessStore, D: db::DocumentStore> AppState<P, D> {
+    /// Initialize the application state from config and the stores
+    fn init(conf: &config::CHConfig, process_store: P, doc_store: D) -> Self {
         let cert_util = Arc::new(
             ids_daps_cert::CertUtil::load_certificate(
                 std::path::Path::new(&conf.p12_path),
@@ -58,26 +104,31 @@ impl AppState {
             .expect("Load certificate failed"),
         );
 
-        #[cfg(feature = "postgres")]
-        let pool = Self::setup_postgres(conf).await?;
-
-        trace!("Initializing Process store");
-        let process_store =
-            db::postgres_process_store::PostgresProcessStore::new(pool.clone(), conf.clear_db)
-                .await;
-
-        trace!("Initializing Document store");
-        let doc_store =
-            db::postgres_document_store::PostgresDocumentStore::new(pool, conf.clear_db).await;
-
         trace!("Initializing services");
-        let doc_service = Arc::new(services::document_service::DocumentService::new(doc_store));
+        let archive = conf
+            .archive_path
+            .as_ref()
+            .map(|path| {
+                services::document_service::Archive::new(
+                    db::object_store::LocalObjectStore::new(path),
+                    &cert_util,
+                )
+            })
+            .transpose()
+            .expect("Loading archive key failed");
+        let keys = conf.key_ring().expect("Loading master keys failed");
+        let encrypted = keys.is_some();
+        let doc_service = Arc::new(services::document_service::DocumentService::new(
+            doc_store, archive, keys,
+        ));
         let logging_service = Arc::new(services::logging_service::LoggingService::new(
             process_store,
             doc_service.clone(),
             cert_util.clone(),
             conf.issuer.clone(),
             conf.static_process_owner.clone(),
+            conf.receipt_mode,
+            conf.deletion_quorum,
         ));
 
         let daps_client = ids_daps_client::ReqwestDapsClient::from_cert_util(
@@ -85,12 +136,149 @@ impl AppState {
             &conf.token_scope,
             &conf.daps_certs_url,
             &conf.daps_token_url,
-            300_u64,
+            model::constants::DAPS_JWKS_CACHE_TTL,
         );
 
         let daps_client = Arc::new(daps_client);
+        let dat_cache = Arc::new(services::dat_cache::DatCache::new(
+            daps_client.clone(),
+            conf.dat_refresh_margin
+                .unwrap_or(model::constants::DEFAULT_DAT_REFRESH_MARGIN),
+        ));
+        dat_cache.clone().spawn_refresher();
+
+        let health_service = Arc::new(
+            services::health_service::HealthService::new(
+                std::path::Path::new(&conf.p12_path),
+                conf.p12_password.as_deref().unwrap_or(""),
+                &cert_util,
+                &conf.daps_certs_url,
+                model::constants::DAPS_JWKS_CACHE_TTL,
+            )
+            .expect("Initializing health checks failed"),
+        );
 
-        Ok(Self { logging_service, daps_client, cert_util })
+        Self::spawn_merkle_root_publisher(
+            logging_service.clone(),
+            conf.merkle_root_interval
+                .unwrap_or(model::constants::DEFAULT_MERKLE_ROOT_INTERVAL),
+        );
+
+        let retention_policy = conf
+            .retention_days
+            .map(|days| model::process::RetentionPolicy {
+                days,
+                start: conf.retention_start,
+            });
+        Self::spawn_document_expiry(
+            logging_service.clone(),
+            retention_policy,
+            conf.retention_interval
+                .unwrap_or(model::constants::DEFAULT_RETENTION_INTERVAL),
+        );
+
+        if encrypted {
+            Self::spawn_key_rewrap(logging_service.clone());
+        }
+
+        if conf.archive_path.is_some() {
+            Self::spawn_archiver(
+                logging_service.clone(),
+                conf.archive_after_days.unwrap_or_default(),
+                conf.archive_interval
+                    .unwrap_or(model::constants::DEFAULT_ARCHIVE_INTERVAL),
+            );
+        }
+
+        Self {
+            logging_service,
+            daps_client,
+            dat_cache,
+            health_service,
+            cert_util,
+        }
+    }
+
+    /// Periodically publish signed Merkle roots over the stored documents
+    fn spawn_merkle_root_publisher(
+        logging_service: Arc<services::logging_service::LoggingService<P, D>>,
+        interval: u64,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval));
+            loop {
+                interval.tick().await;
+                debug!("Publishing Merkle roots");
+                let result = logging_service.publish_merkle_roots().await;
+                if let Err(e) = result {
+                    error!("Error while publishing Merkle roots: {e:?}");
+                }
+            }
+        });
+    }
+
+    /// Wraps the data keys, which are still wrapped by a previous master key, with the current one
+    fn spawn_key_rewrap(logging_service: Arc<services::logging_service::LoggingService<P, D>>) {
+        tokio::spawn(async move {
+            let result = logging_service.rewrap_data_keys().await;
+            match result {
+                Ok(0) => {}
+                Ok(rewrapped) => {
+                    info!("Rewrapped {rewrapped} data keys with the current master key");
+                }
+                Err(e) => error!("Error while rewrapping data keys: {e:?}"),
+            }
+        });
+    }
+
+    /// Periodically moves documents of past months to the archive
+    fn spawn_archiver(
+        logging_service: Arc<services::logging_service::LoggingService<P, D>>,
+        older_than_days: u32,
+        interval: u64,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval));
+            loop {
+                interval.tick().await;
+                debug!("Archiving documents");
+                let result = logging_service.archive_documents(older_than_days).await;
+                match result {
+                    Ok(0) => {}
+                    Ok(archived) => info!("Archived {archived} documents"),
+                    Err(e) => error!("Error while archiving documents: {e:?}"),
+                }
+            }
+        });
+    }
+
+    /// Stores the global retention policy and periodically expires documents after their retention
+    /// period
+    fn spawn_document_expiry(
+        logging_service: Arc<services::logging_service::LoggingService<P, D>>,
+        policy: Option<model::process::RetentionPolicy>,
+        interval: u64,
+    ) {
+        tokio::spawn(async move {
+            let result = logging_service
+                .set_global_retention_policy(policy.as_ref())
+                .await;
+            if let Err(e) = result {
+                error!("Error while storing global retention policy: {e:?}");
+            }
+
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval));
+            loop {
+                interval.tick().await;
+                debug!("Expiring documents");
+                let result = logging_service.expire_documents().await;
+                match result {
+                    Ok(0) => {}
+                    Ok(expired) => info!("Expired {expired} documents"),
+                    Err(e) => error!("Error while expiring documents: {e:?}"),
+                }
+            }
+        });
     }
 }
 
@@ -98,7 +286,12 @@ impl AppState {
 ///
 /// # Errors
 ///
-/// Throws an error if the `AppState` cannot be initialized
+/// Throws an error if the backend of the `database_url` is not supported or the `AppState` cannot
+/// be initialized
+#[cfg_attr(
+    not(any(feature = "postgres", feature = "sqlite")),
+    allow(clippy::unused_async)
+)]
 pub async fn app() -> anyhow::Result<axum::Router> {
     // Read configuration
     let conf = config::read_config(None);
@@ -106,9 +299,61 @@ pub async fn app() -> anyhow::Result<axum::Router> {
 
     tracing::info!("Config read successfully! Initializing application ...");
 
-    // Initialize application state
-    let app_state = AppState::init(&conf).await?;
+    // Initialize stores and application state for the configured backend
+    let router = match db::Backend::from_database_url(&conf.database_url)? {
+        #[cfg(feature = "postgres")]
+        db::Backend::Postgres => {
+            let pool = setup_postgres(&conf.database_url).await?;
+
+            trace!("Initializing Process store");
+            let process_store =
+                db::postgres_process_store::PostgresProcessStore::new(pool.clone(), conf.clear_db)
+                    .await;
+
+            trace!("Initializing Document store");
+            let doc_store =
+                db::postgres_document_store::PostgresDocumentStore::new(pool, conf.clear_db).await;
+
+            ports::router(conf.body_limits()).with_state(AppState::init(
+                &conf,
+                process_store,
+                doc_store,
+            ))
+        }
+        #[cfg(feature = "sqlite")]
+        db::Backend::Sqlite => {
+            let pool = setup_sqlite(&conf.database_url).await?;
+
+            trace!("Initializing Process store");
+            let process_store =
+                db::sqlite_process_store::SqliteProcessStore::new(pool.clone(), conf.clear_db)
+                    .await;
+
+            trace!("Initializing Document store");
+            let doc_store =
+                db::sqlite_document_store::SqliteDocumentStore::new(pool, conf.clear_db).await;
+
+            ports::router(conf.body_limits()).with_state(AppState::init(
+                &conf,
+                process_store,
+                doc_store,
+            ))
+        }
+        #[cfg(feature = "memory")]
+        db::Backend::Memory => {
+            info!("Using in-memory database, data is lost on shutdown");
+            let db = Arc::new(db::memory_store::MemoryDb::default());
+
+            let process_store = db::memory_store::MemoryProcessStore::new(db.clone());
+            let doc_store = db::memory_store::MemoryDocumentStore::new(db);
+
+            ports::router(conf.body_limits()).with_state(AppState::init(
+                &conf,
+                process_store,
+                doc_store,
+            ))
+        }
+    };
 
-    // Setup router
-    Ok(ports::router().with_state(app_state))
+    Ok(router)
 }
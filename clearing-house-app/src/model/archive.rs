@@ -0,0 +1,209 @@
+//! Cold archive of documents. The documents of a process are archived in bundles per month of
+//! logging, each bundle consists of the documents as JSON lines and a signed manifest.
+
+use crate::model::document::Document;
+
+/// Signed record of an archive bundle
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveManifest {
+    pub pid: String,
+    /// Month the documents were logged in (`YYYY-MM`)
+    pub month: String,
+    pub document_count: u64,
+    /// Hex encoded SHA-256 digest of the bundle
+    pub bundle_digest: String,
+    pub archived_at: chrono::DateTime<chrono::Utc>,
+    /// Signed `ArchiveManifestClaims` as JWS
+    pub signature: String,
+}
+
+/// Claims of a signed `ArchiveManifest`
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveManifestClaims {
+    pub process_id: String,
+    pub month: String,
+    pub document_count: u64,
+    pub bundle_digest: String,
+    pub archived_at: i64,
+    pub clearing_house_version: String,
+}
+
+impl ArchiveManifest {
+    /// Creates a new manifest of `bundle` and signs it with the key of `cert_util`
+    ///
+    /// # Errors
+    ///
+    /// Only if issues with reading the key or signing the claims occur.
+    pub fn new_signed(
+        pid: String,
+        month: String,
+        document_count: u64,
+        bundle: &[u8],
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> anyhow::Result<Self> {
+        use sha2::Digest;
+
+        let archived_at = chrono::Utc::now();
+        let bundle_digest = hex::encode(sha2::Sha256::digest(bundle));
+        let claims = ArchiveManifestClaims {
+            process_id: pid.clone(),
+            month: month.clone(),
+            document_count,
+            bundle_digest: bundle_digest.clone(),
+            archived_at: archived_at.timestamp(),
+            clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let signature = crate::model::claims::sign_jws(&claims, cert_util)?;
+
+        Ok(Self {
+            pid,
+            month,
+            document_count,
+            bundle_digest,
+            archived_at,
+            signature,
+        })
+    }
+
+    /// Verifies that this manifest was signed with `key` for `bundle` of the process `pid` for
+    /// `month`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the signature is invalid or the manifest does not match `bundle`.
+    pub fn verify(
+        &self,
+        pid: &str,
+        month: &str,
+        bundle: &[u8],
+        key: &jsonwebtoken::DecodingKey,
+    ) -> anyhow::Result<()> {
+        use sha2::Digest;
+
+        let claims: ArchiveManifestClaims = crate::model::claims::verify_jws(&self.signature, key)?;
+        anyhow::ensure!(
+            claims.process_id == pid
+                && claims.month == month
+                && claims.document_count == self.document_count
+                && claims.bundle_digest == self.bundle_digest,
+            "Manifest does not match its signature"
+        );
+        anyhow::ensure!(
+            self.pid == pid && self.month == month,
+            "Manifest belongs to another bundle"
+        );
+        anyhow::ensure!(
+            hex::encode(sha2::Sha256::digest(bundle)) == self.bundle_digest,
+            "Bundle does not match the digest of its manifest"
+        );
+        Ok(())
+    }
+}
+
+/// Directory of the bundles of the process `pid`. The pid is hex encoded, so it is a valid and
+/// unique path segment.
+#[must_use]
+pub fn process_prefix(pid: &str) -> String {
+    format!("{}/", hex::encode(pid))
+}
+
+/// Key of the bundle of the process `pid` for `month`
+#[must_use]
+pub fn bundle_key(pid: &str, month: &str) -> String {
+    format!("{}{month}.jsonl", process_prefix(pid))
+}
+
+/// Key of the manifest of the bundle of the process `pid` for `month`
+#[must_use]
+pub fn manifest_key(pid: &str, month: &str) -> String {
+    format!("{}{month}.manifest.json", process_prefix(pid))
+}
+
+/// Month (`YYYY-MM`) of the bundle containing the document `doc`
+#[must_use]
+pub fn month(doc: &Document<String>) -> String {
+    doc.ts.to_utc().format("%Y-%m").to_string()
+}
+
+/// Start of the month (UTC) of `t`. Only documents logged before the start of the current month
+/// are archived, so every bundle is written once.
+#[must_use]
+pub fn start_of_month(t: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+    use chrono::Datelike;
+
+    t.date_naive()
+        .with_day(1)
+        .unwrap_or_else(|| t.date_naive())
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+}
+
+/// Encodes `docs` as JSON lines
+///
+/// # Errors
+///
+/// Throws an error if a document cannot be serialized.
+pub fn encode_bundle(docs: &[Document<String>]) -> serde_json::Result<Vec<u8>> {
+    let mut bundle = Vec::new();
+    for doc in docs {
+        serde_json::to_writer(&mut bundle, doc)?;
+        bundle.push(b'\n');
+    }
+    Ok(bundle)
+}
+
+/// Decodes the documents of a bundle
+///
+/// # Errors
+///
+/// Throws an error if a line is not a valid document.
+pub fn decode_bundle(bundle: &[u8]) -> serde_json::Result<Vec<Document<String>>> {
+    bundle
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bundle_key, decode_bundle, encode_bundle, month, start_of_month};
+    use crate::model::document::GENESIS_HASH;
+    use crate::test_util::document;
+
+    #[test]
+    fn bundle_roundtrip() {
+        let mut doc = document("pid", "line\nbreak");
+        doc.link_to(GENESIS_HASH).expect("Document is serializable");
+        let hash = doc.hash.clone().expect("Hash was set");
+
+        let bundle = encode_bundle(&[doc.clone(), doc.clone()]).expect("Encoding");
+        let decoded = decode_bundle(&bundle).expect("Decoding");
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].id, doc.id);
+        assert_eq!(decoded[0].content.payload, doc.content.payload);
+        // Archived documents are still verifiable
+        assert_eq!(
+            decoded[0]
+                .chain_hash(GENESIS_HASH)
+                .expect("Document is serializable"),
+            hash
+        );
+    }
+
+    #[test]
+    fn bundle_keys() {
+        let t = chrono::DateTime::parse_from_rfc3339("2024-02-29T23:59:59+00:00")
+            .expect("Valid date")
+            .to_utc();
+        assert_eq!(start_of_month(t).to_rfc3339(), "2024-02-01T00:00:00+00:00");
+
+        let mut doc = document("../pid", "payload");
+        doc.ts = t.into();
+        assert_eq!(month(&doc), "2024-02");
+        assert_eq!(
+            bundle_key(&doc.pid, "2024-02"),
+            "2e2e2f706964/2024-02.jsonl"
+        );
+    }
+}
@@ -1,12 +1,11 @@
-use crate::model::constants::{ENV_SHARED_SECRET};
+use crate::model::constants::ENV_SHARED_SECRET;
 use crate::model::ids;
-use crate::AppState;
+use axum::extract::FromRequestParts;
 use axum::response::IntoResponse;
 use ids_daps_client::DapsError;
 use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
-use axum::extract::FromRequestParts;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ChClaims {
@@ -33,10 +32,40 @@ pub struct ExtractIdsMessage<T> {
     pub ids_message: ids::message::IdsMessage<T>,
 }
 
-impl<S: Send + Sync, T: serde::de::DeserializeOwned + Send> axum::extract::FromRequest<S>
-    for ExtractIdsMessage<T>
+/// Payload of an IDS message, which is read from the `payload` part of a multipart request
+pub trait FromPayload: Sized {
+    /// Reads the payload from `data` of the type `media_type`, `None` if there is no payload
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if `data` is not a valid payload.
+    fn from_payload(media_type: Option<&str>, data: bytes::Bytes) -> anyhow::Result<Option<Self>>;
+}
+
+/// Payloads of all types other than `RawPayload` are JSON
+impl<T: serde::de::DeserializeOwned> FromPayload for T {
+    fn from_payload(_media_type: Option<&str>, data: bytes::Bytes) -> anyhow::Result<Option<Self>> {
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+impl FromPayload for ids::message::RawPayload {
+    fn from_payload(media_type: Option<&str>, data: bytes::Bytes) -> anyhow::Result<Option<Self>> {
+        Ok(Some(Self {
+            media_type: media_type.map(ToString::to_string),
+            data,
+        }))
+    }
+}
+
+/// URI of the clearing house, which issues the responses
+#[derive(Clone, Debug)]
+pub struct Issuer(pub String);
+
+impl<S: Send + Sync, T: FromPayload + Send> axum::extract::FromRequest<S> for ExtractIdsMessage<T>
 where
-    AppState: axum::extract::FromRef<S>,
+    Arc<ids_daps_client::ReqwestDapsClient>: axum::extract::FromRef<S>,
+    Issuer: axum::extract::FromRef<S>,
 {
     type Rejection = axum::response::Response;
 
@@ -44,40 +73,53 @@ where
     async fn from_request(req: axum::extract::Request, state: &S) -> Result<Self, Self::Rejection> {
         // Extract the state to get the DAPS Client
         let (mut parts, body) = req.into_parts();
-        let axum::extract::State(app_state) =
-            axum::extract::State::<AppState>::from_request_parts(&mut parts, state)
+        let axum::extract::State(daps_client) = axum::extract::State::<
+            Arc<ids_daps_client::ReqwestDapsClient>,
+        >::from_request_parts(&mut parts, state)
+        .await
+        .map_err(axum::response::IntoResponse::into_response)?;
+        let axum::extract::State(Issuer(issuer)) =
+            axum::extract::State::<Issuer>::from_request_parts(&mut parts, state)
                 .await
                 .map_err(axum::response::IntoResponse::into_response)?;
 
         // Assemble request again and do the Multipart extraction
         let req = axum::extract::Request::from_parts(parts, body);
-        let multipart = axum::extract::Multipart::from_request(req, state).await.map_err(|_| {
-            (
-                axum::http::StatusCode::BAD_REQUEST,
-                "Expecting multipart request",
-            )
-                .into_response()
-        })?;
+        let multipart = axum::extract::Multipart::from_request(req, state)
+            .await
+            .map_err(|_| {
+                (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "Expecting multipart request",
+                )
+                    .into_response()
+            })?;
 
         // Extracting the relevant multipart fields
-        let multipart_fields = match extract_multipart_fields(multipart).await {
-            Ok(fields) => fields,
-            Err(e) => {
-                tracing::error!("Error extracting multipart fields: {e}");
-                return Err(e.into_response());
-            }
-        };
+        let mut multipart_fields = HashMap::new();
+        if let Err(e) = extract_multipart_fields(multipart, &mut multipart_fields).await {
+            tracing::error!("Error extracting multipart fields: {e}");
+            return Err(multipart_rejection(&issuer, e, &multipart_fields));
+        }
 
         // Parsing the header
         let header: ids::message::IdsHeader = multipart_fields
             .get("header")
-            .cloned()
-            .map(|b| serde_json::from_slice(&b))
+            .map(|f| serde_json::from_slice(&f.data))
             .transpose()
             .map_err(|e| {
-                let raw_body = String::from_utf8_lossy(multipart_fields.get("header").expect("The 'header' field should exist"));
-                
-                tracing::error!("...retrieve and parse header: {} | raw body: {:?}", e, raw_body);
+                let raw_body = String::from_utf8_lossy(
+                    &multipart_fields
+                        .get("header")
+                        .expect("The 'header' field should exist")
+                        .data,
+                );
+
+                tracing::error!(
+                    "...retrieve and parse header: {} | raw body: {:?}",
+                    e,
+                    raw_body
+                );
                 (
                     axum::http::StatusCode::BAD_REQUEST,
                     "Invalid 'header' multipart",
@@ -92,23 +134,31 @@ where
                     .into_response()
             })?;
         tracing::trace!("Header: {:#?}", header);
-        
+
+        // Keeping the header as it was received, the parsed header drops unknown fields. JSON is
+        // valid UTF-8, so the conversion never fails for a parsed header.
+        let raw_header = multipart_fields
+            .get("header")
+            .and_then(|f| String::from_utf8(f.data.to_vec()).ok());
+
         // Parsing the payload if exists
-        let payload = multipart_fields
-            .get("payload")
-            .cloned();
+        let payload = multipart_fields.get("payload").cloned();
 
         let payload: Option<T> = if let Some(payload) = payload {
-            tracing::trace!("Payload: {:?}", payload);
-            let parsed_payload: Option<T> = serde_json::from_slice(&payload)
-                .map_err(|e| {
-                    let raw_body = String::from_utf8_lossy(payload.as_ref());
-                    
-                tracing::error!("...retrieve and parse payload: {} as json '{raw_body}'", e);
-                (axum::http::StatusCode::BAD_REQUEST, "Invalid payload").into_response()
-            })?;
+            tracing::trace!("Payload: {:?}", payload.data);
+            let parsed_payload: Option<T> =
+                T::from_payload(payload.content_type.as_deref(), payload.data.clone()).map_err(
+                    |e| {
+                        let raw_body = String::from_utf8_lossy(payload.data.as_ref());
+
+                        tracing::error!("...retrieve and parse payload: {} from '{raw_body}'", e);
+                        (axum::http::StatusCode::BAD_REQUEST, "Invalid payload").into_response()
+                    },
+                )?;
             parsed_payload
-        } else { None };
+        } else {
+            None
+        };
 
         // Validate the DAPS Token
         tracing::debug!("Validating the DAPS Token ...");
@@ -121,11 +171,10 @@ where
                 .into_response()
         })?;
 
-        let token_claims = app_state
-            .daps_client
+        let token_claims = daps_client
             .validate_dat(&token.token_value)
             .await
-            .map(|t|t.claims)
+            .map(|t| t.claims)
             .map_err(|e| match e {
                 DapsError::InvalidToken => {
                     tracing::error!("Invalid DAPS Token");
@@ -156,6 +205,8 @@ where
             header,
             payload,
             payload_type: None,
+            payload_encoding: None,
+            raw_header,
         };
 
         Ok(ExtractIdsMessage {
@@ -165,11 +216,21 @@ where
     }
 }
 
+/// Part of a multipart request
+#[derive(Clone)]
+struct MultipartField {
+    content_type: Option<String>,
+    data: bytes::Bytes,
+}
+
+/// Reads the `header` and `payload` parts of `multipart` into `fields`. Parts are read chunk by
+/// chunk, so requests exceeding the body limit of the route are aborted as soon as the limit is
+/// reached, and all other parts are skipped without buffering them.
+#[allow(tail_expr_drop_order)] // The fields are dropped before the next one is read
 async fn extract_multipart_fields(
     mut multipart: axum::extract::Multipart,
-) -> Result<HashMap<String, bytes::Bytes>, axum::extract::multipart::MultipartError> {
-    let mut fields: HashMap<String, bytes::Bytes> = HashMap::new();
-
+    fields: &mut HashMap<String, MultipartField>,
+) -> Result<(), axum::extract::multipart::MultipartError> {
     while let Some(field) = multipart.next_field().await? {
         let name = if let Some(name) = field.name() {
             name.to_string()
@@ -177,14 +238,120 @@ async fn extract_multipart_fields(
             // We require a name for each field, otherwise we skip it...
             continue;
         };
-        let data = field.bytes().await?;
+        let content_type = field.content_type().map(ToString::to_string);
+        let keep = matches!(name.as_str(), "header" | "payload");
+        let data = read_field(field, keep).await?;
+        if !keep {
+            tracing::debug!("Skipped unknown part `{}`", name);
+            continue;
+        }
 
         tracing::debug!("Length of `{}` is {} bytes", name, data.len());
 
-        fields.insert(name, data);
+        fields.insert(name, MultipartField { content_type, data });
     }
 
-    Ok(fields)
+    Ok(())
+}
+
+/// Reads `field` chunk by chunk and returns its data, if `keep` is set
+async fn read_field(
+    mut field: axum::extract::multipart::Field<'_>,
+    keep: bool,
+) -> Result<bytes::Bytes, axum::extract::multipart::MultipartError> {
+    let mut data = bytes::BytesMut::new();
+    loop {
+        let chunk = field.chunk().await?;
+        let Some(chunk) = chunk else {
+            return Ok(data.freeze());
+        };
+        if keep {
+            data.extend_from_slice(&chunk);
+        }
+    }
+}
+
+/// Response to a multipart request, which could not be read. Requests exceeding the body limit of
+/// the route are answered with a `RejectionMessage`, correlated to the header, if it was read.
+fn multipart_rejection(
+    issuer: &str,
+    e: axum::extract::multipart::MultipartError,
+    fields: &HashMap<String, MultipartField>,
+) -> axum::response::Response {
+    if e.status() != axum::http::StatusCode::PAYLOAD_TOO_LARGE {
+        return e.into_response();
+    }
+
+    let correlation_id = fields
+        .get("header")
+        .and_then(|f| serde_json::from_slice::<ids::message::IdsHeader>(&f.data).ok())
+        .and_then(|h| h.id);
+    (
+        axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+        ids::RejectionMessage::new(issuer, "Payload too large".to_string(), correlation_id),
+    )
+        .into_response()
+}
+
+/// Checks if the security token in the raw header `raw_header` of a message expired before `now`.
+/// The token was validated when the message was received, so its signature is not checked again.
+/// Tokens without readable expiry count as valid, headers without token as expired.
+#[must_use]
+pub fn security_token_expired(raw_header: &str, now: chrono::DateTime<chrono::Utc>) -> bool {
+    let Ok(header) = serde_json::from_str::<ids::message::IdsHeader>(raw_header) else {
+        return false;
+    };
+    let Some(token) = header.security_token else {
+        return true;
+    };
+
+    token_expiry(&token.token_value).is_some_and(|exp| exp.timestamp() < now.timestamp())
+}
+
+/// Checks if the security token in the raw header `raw_header` of a message was issued to
+/// `client_id`, i.e. if `client_id` sent the message. As in `security_token_expired`, the signature
+/// of the token is not checked again.
+#[must_use]
+pub fn security_token_issued_to(raw_header: &str, client_id: &str) -> bool {
+    #[derive(serde::Deserialize)]
+    struct Subject {
+        sub: String,
+    }
+
+    serde_json::from_str::<ids::message::IdsHeader>(raw_header)
+        .ok()
+        .and_then(|header| header.security_token)
+        .and_then(|token| unverified_claims::<Subject>(&token.token_value))
+        .is_some_and(|claims| claims.sub == client_id)
+}
+
+/// Reads the expiry (`exp`) of the JWT `token` without checking its signature. Returns `None` if
+/// the token is not readable.
+#[must_use]
+pub fn token_expiry(token: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    #[derive(serde::Deserialize)]
+    struct Expiry {
+        exp: i64,
+    }
+
+    let claims = unverified_claims::<Expiry>(token)?;
+    chrono::DateTime::from_timestamp(claims.exp, 0)
+}
+
+/// Reads the claims of the JWT `token` without checking its signature or expiry
+fn unverified_claims<T: serde::de::DeserializeOwned>(token: &str) -> Option<T> {
+    let mut validation = jsonwebtoken::Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+    jsonwebtoken::decode::<T>(
+        token,
+        &jsonwebtoken::DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .ok()
+    .map(|token| token.claims)
 }
 
 /// Returns the `JWKSet` for the RSA keypair at `key_path`
@@ -196,7 +363,8 @@ async fn extract_multipart_fields(
 pub fn get_jwks(cert_util: &Arc<ids_daps_cert::CertUtil>) -> Option<jsonwebtoken::jwk::JwkSet> {
     use base64::Engine;
 
-    let params = cert_util.rsa_exponent_and_modulus()
+    let params = cert_util
+        .rsa_exponent_and_modulus()
         .expect("Cannot extract RSA parameters from certificate");
 
     let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
@@ -226,48 +394,57 @@ pub fn get_jwks(cert_util: &Arc<ids_daps_cert::CertUtil>) -> Option<jsonwebtoken
     Some(jsonwebtoken::jwk::JwkSet { keys: vec![jwk] })
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct Claims<T> {
-    #[serde(rename = "iss")]
-    issuer: String,
-    #[serde(rename = "iat")]
-    issued_at: Timestamp,
-    #[serde(rename = "aud")]
-    audience: String,
-    #[serde(rename = "exp")]
-    expiration_time: Timestamp,
-    #[allow(clippy::struct_field_names)]
-    #[serde(flatten)]
-    private_claims: T,
+/// Signs `claims` with the private key of `cert_util` (PS512) and returns the compact JWS
+///
+/// # Errors
+///
+/// Only if issues with reading the key or signing the claims occur.
+pub fn sign_jws<T: serde::Serialize>(
+    claims: &T,
+    cert_util: &ids_daps_cert::CertUtil,
+) -> anyhow::Result<String> {
+    let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::PS512);
+    header.typ = None;
+    header.kid = cert_util.fingerprint().ok();
+
+    let private_key_der = cert_util
+        .private_key_der()
+        .map_err(|e| anyhow::anyhow!("Error getting private_key_der: {e}"))?;
+    let private_key = jsonwebtoken::EncodingKey::from_rsa_der(&private_key_der);
+
+    Ok(jsonwebtoken::encode(&header, claims, &private_key)?)
 }
 
-struct Timestamp(chrono::DateTime<chrono::Utc>);
-
-impl serde::Serialize for Timestamp {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_i64(self.0.timestamp())
-    }
+/// Returns the public key of `cert_util`, which verifies JWS signed by `sign_jws`
+///
+/// # Errors
+///
+/// Only if issues with reading the key occur.
+pub fn verifying_key(
+    cert_util: &ids_daps_cert::CertUtil,
+) -> anyhow::Result<jsonwebtoken::DecodingKey> {
+    let params = cert_util
+        .rsa_exponent_and_modulus()
+        .map_err(|e| anyhow::anyhow!("Error getting RSA parameters: {e}"))?;
+    Ok(jsonwebtoken::DecodingKey::from_rsa_raw_components(
+        &params.modulus,
+        &params.exponent,
+    ))
 }
 
-impl<'de> serde::Deserialize<'de> for Timestamp {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::Error;
-
-        let unix_secs = i64::deserialize(deserializer)?;
-        let timestamp = chrono::DateTime::from_timestamp(unix_secs, 0);
-
-        if let Some(ts) = timestamp {
-            Ok(Timestamp(ts))
-        } else {
-            Err(D::Error::custom("Invalid timestamp"))
-        }
-    }
+/// Verifies the JWS `jws` signed by `sign_jws` with `key` and returns its claims
+///
+/// # Errors
+///
+/// Throws an error if the signature is invalid or the claims cannot be read.
+pub fn verify_jws<T: serde::de::DeserializeOwned>(
+    jws: &str,
+    key: &jsonwebtoken::DecodingKey,
+) -> anyhow::Result<T> {
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::PS512);
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    Ok(jsonwebtoken::decode::<T>(jws, key, &validation)?.claims)
 }
 
 /// Decodes the given `token` and validates it against the given `audience`
@@ -280,7 +457,7 @@ pub fn decode_token<T: Clone + serde::Serialize + for<'de> serde::Deserialize<'d
     audience: &str,
 ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
     let key = match env::var(ENV_SHARED_SECRET) {
-        Ok(secret) => jsonwebtoken::DecodingKey::from_secret(&secret.to_string().into_bytes()),
+        Ok(secret) => jsonwebtoken::DecodingKey::from_secret(secret.as_bytes()),
         Err(e) => {
             error!(
                 "Shared Secret not configured. Please configure environment variable {}",
@@ -297,3 +474,103 @@ pub fn decode_token<T: Clone + serde::Serialize + for<'de> serde::Deserialize<'d
         .map(|t| t.claims)
         .map_err(|e| anyhow::anyhow!("{e}"))?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        extract_multipart_fields, multipart_rejection, security_token_expired,
+        security_token_issued_to,
+    };
+    use axum::response::IntoResponse;
+
+    fn raw_header(token: Option<&str>) -> String {
+        let mut header = serde_json::json!({
+            "@type": "ids:LogMessage",
+            "@id": "https://w3id.org/idsa/autogen/LogMessage/1",
+            "ids:modelVersion": "4.2.7",
+            "ids:issued": "2026-10-18T12:00:00Z",
+            "ids:issuerConnector": "http://connector",
+            "ids:senderAgent": "http://agent",
+            "custom:property": [1, 2, 3]
+        });
+        if let Some(token) = token {
+            header["ids:securityToken"] = serde_json::json!({
+                "@type": "ids:DynamicAttributeToken",
+                "ids:tokenFormat": "idsc:JWT",
+                "ids:tokenValue": token
+            });
+        }
+        header.to_string()
+    }
+
+    #[test]
+    fn token_expiry() {
+        let now = chrono::Utc::now();
+        let token = |exp: chrono::DateTime<chrono::Utc>| {
+            jsonwebtoken::encode(
+                &jsonwebtoken::Header::default(),
+                &serde_json::json!({ "sub": "connector", "exp": exp.timestamp() }),
+                &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+            )
+            .expect("Signing token")
+        };
+
+        let expired = raw_header(Some(&token(now - chrono::TimeDelta::hours(1))));
+        assert!(security_token_expired(&expired, now));
+        let valid = raw_header(Some(&token(now + chrono::TimeDelta::hours(1))));
+        assert!(!security_token_expired(&valid, now));
+
+        assert!(!security_token_expired(
+            &raw_header(Some("not a token")),
+            now
+        ));
+        assert!(!security_token_expired("not a header", now));
+        assert!(security_token_expired(&raw_header(None), now));
+
+        assert!(security_token_issued_to(&valid, "connector"));
+        assert!(security_token_issued_to(&expired, "connector"));
+        assert!(!security_token_issued_to(&valid, "other"));
+        assert!(!security_token_issued_to(&raw_header(None), "connector"));
+    }
+
+    #[tokio::test]
+    async fn payload_too_large() {
+        use tower::ServiceExt;
+
+        async fn handler(multipart: axum::extract::Multipart) -> axum::response::Response {
+            let mut fields = std::collections::HashMap::new();
+            let result = extract_multipart_fields(multipart, &mut fields).await;
+            match result {
+                Ok(()) => axum::http::StatusCode::OK.into_response(),
+                Err(e) => multipart_rejection("https://ch.example", e, &fields),
+            }
+        }
+        let app = axum::Router::new()
+            .route("/", axum::routing::post(handler))
+            .layer(axum::extract::DefaultBodyLimit::max(1024));
+        let request = |payload_size: usize| {
+            let body = format!(
+                "--b\r\nContent-Disposition: form-data; name=\"header\"\r\n\r\n{}\r\n\
+                 --b\r\nContent-Disposition: form-data; name=\"payload\"\r\n\r\n{}\r\n--b--\r\n",
+                raw_header(None),
+                "x".repeat(payload_size)
+            );
+            axum::http::Request::post("/")
+                .header("Content-Type", "multipart/form-data; boundary=b")
+                .body(axum::body::Body::from(body))
+                .expect("Valid request")
+        };
+
+        let response = app.clone().oneshot(request(100)).await.expect("Response");
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+
+        let response = app.oneshot(request(2048)).await.expect("Response");
+        assert_eq!(response.status(), axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Reading body");
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("ids:RejectionMessage"));
+        assert!(body.contains(r#""ids:rejectionReason":"Payload too large""#));
+    }
+}
@@ -87,6 +87,24 @@ pub const DEFAULT_NUM_RESPONSE_ENTRIES: u64 = 100;
 
 pub const DEFAULT_DOC_TYPE: &str = "IDS_MESSAGE";
 
+// definition of default interval (in seconds) for publishing Merkle roots
+pub const DEFAULT_MERKLE_ROOT_INTERVAL: u64 = 3600;
+// definition of default interval (in seconds) for expiring documents
+pub const DEFAULT_RETENTION_INTERVAL: u64 = 3600;
+// definition of default interval (in seconds) for archiving documents
+pub const DEFAULT_ARCHIVE_INTERVAL: u64 = 86400;
+// definition of default body limits (in bytes) for logging messages and all other requests
+pub const DEFAULT_MAX_LOG_SIZE: usize = 16 * 1024 * 1024;
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 2 * 1024 * 1024;
+// definition of maximum number of messages logged in a single batch
+pub const MAX_BATCH_SIZE: usize = 1000;
+// definition of default time (in seconds) before its expiry, from which the DAT is refreshed
+pub const DEFAULT_DAT_REFRESH_MARGIN: u64 = 60;
+// definition of time (in seconds) the JWKS of the DAPS is cached
+pub const DAPS_JWKS_CACHE_TTL: u64 = 300;
+// definition of time (in seconds) after which a failed request to the DAPS is retried
+pub const DAPS_RETRY_INTERVAL: i64 = 10;
+
 // split string symbols for vec_to_string and string_to_vec
 pub const SPLIT_QUOTE: &str = "'";
 pub const SPLIT_SIGN: &str = "~";
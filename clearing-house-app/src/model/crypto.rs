@@ -0,0 +1,362 @@
+//! Envelope encryption of documents at rest. Every process has its own data keys, which are only
+//! stored wrapped by a master key, so the master key can be rotated without re-encrypting the
+//! documents.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::Aes256Gcm;
+use base64::Engine;
+
+/// Length of the nonce, which is prepended to every ciphertext
+const NONCE_LEN: usize = 12;
+
+/// Master key for wrapping the data keys of the processes
+#[derive(Clone)]
+pub struct MasterKey {
+    /// Identifier of the key, derived from its digest
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    /// Parses a base64 encoded 256 bit key
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the key is not valid base64 or not 256 bits long.
+    pub fn parse(encoded: &str) -> anyhow::Result<Self> {
+        use sha2::Digest;
+
+        let key = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("Master key must be 256 bits long"))?;
+
+        Ok(Self {
+            id: hex::encode(&sha2::Sha256::digest(&key)[..8]),
+            cipher,
+        })
+    }
+
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+// Manual implementation, which never prints the key itself
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MasterKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Master keys of the clearing house. The first key wraps new data keys, the others are only kept
+/// to unwrap data keys until they are rewrapped with the first key.
+#[derive(Clone, Debug)]
+pub struct KeyRing {
+    keys: Vec<MasterKey>,
+}
+
+impl KeyRing {
+    /// Parses base64 encoded master keys separated by commas or whitespace, starting with the
+    /// current master key
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if no key is given or a key is invalid.
+    pub fn parse(encoded: &str) -> anyhow::Result<Self> {
+        let keys = encoded
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|k| !k.is_empty())
+            .map(MasterKey::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            anyhow::bail!("No master key given");
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Master key used for wrapping new data keys
+    #[must_use]
+    pub fn current(&self) -> &MasterKey {
+        &self.keys[0]
+    }
+
+    /// Generates a new data key with `version` for the process `pid`, wrapped by the current
+    /// master key
+    ///
+    /// # Errors
+    ///
+    /// Only if the encryption fails.
+    pub fn generate_data_key(
+        &self,
+        pid: &str,
+        version: i32,
+    ) -> anyhow::Result<(WrappedDataKey, DataKey)> {
+        let key = Aes256Gcm::generate_key(&mut OsRng);
+        let wrapped = WrappedDataKey {
+            pid: pid.to_string(),
+            version,
+            master_key_id: self.current().id.clone(),
+            wrapped_key: encrypt(&self.current().cipher, &key, &wrapped_key_aad(pid, version))?,
+            created_at: chrono::Utc::now(),
+        };
+
+        Ok((wrapped, DataKey::new(version, &key)?))
+    }
+
+    /// Unwraps the data key `wrapped`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the master key of `wrapped` is unknown or the wrapped key was modified.
+    pub fn unwrap(&self, wrapped: &WrappedDataKey) -> anyhow::Result<DataKey> {
+        DataKey::new(wrapped.version, &self.unwrap_raw(wrapped)?)
+    }
+
+    /// Wraps the data key `wrapped` with the current master key. Returns `None` if it is already
+    /// wrapped with the current master key.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the data key cannot be unwrapped.
+    pub fn rewrap(&self, wrapped: &WrappedDataKey) -> anyhow::Result<Option<WrappedDataKey>> {
+        if wrapped.master_key_id == self.current().id {
+            return Ok(None);
+        }
+
+        let key = self.unwrap_raw(wrapped)?;
+        Ok(Some(WrappedDataKey {
+            master_key_id: self.current().id.clone(),
+            wrapped_key: encrypt(
+                &self.current().cipher,
+                &key,
+                &wrapped_key_aad(&wrapped.pid, wrapped.version),
+            )?,
+            ..wrapped.clone()
+        }))
+    }
+
+    fn unwrap_raw(&self, wrapped: &WrappedDataKey) -> anyhow::Result<Vec<u8>> {
+        let master_key = self
+            .keys
+            .iter()
+            .find(|k| k.id == wrapped.master_key_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown master key '{}'", wrapped.master_key_id))?;
+
+        decrypt(
+            &master_key.cipher,
+            &wrapped.wrapped_key,
+            &wrapped_key_aad(&wrapped.pid, wrapped.version),
+        )
+    }
+}
+
+/// Data key of a process as stored in the database
+#[derive(Clone, Debug, PartialEq)]
+pub struct WrappedDataKey {
+    pub pid: String,
+    pub version: i32,
+    /// Id of the master key, which wraps this key
+    pub master_key_id: String,
+    pub wrapped_key: Vec<u8>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Public information about a data key of a process
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DataKeyInfo {
+    pub pid: String,
+    pub version: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<&WrappedDataKey> for DataKeyInfo {
+    fn from(key: &WrappedDataKey) -> Self {
+        Self {
+            pid: key.pid.clone(),
+            version: key.version,
+            created_at: key.created_at,
+        }
+    }
+}
+
+/// Unwrapped data key of a process
+pub struct DataKey {
+    version: i32,
+    cipher: Aes256Gcm,
+    /// Key for blind indexes, derived from the data key
+    index_key: Vec<u8>,
+}
+
+impl DataKey {
+    fn new(version: i32, key: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            version,
+            cipher: Aes256Gcm::new_from_slice(key)
+                .map_err(|_| anyhow::anyhow!("Data key must be 256 bits long"))?,
+            index_key: hmac_sha256(key, BLIND_INDEX_INFO)?,
+        })
+    }
+
+    #[must_use]
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Encrypts `plaintext` bound to `aad` and returns nonce and ciphertext base64 encoded
+    ///
+    /// # Errors
+    ///
+    /// Only if the encryption fails.
+    pub fn encrypt(&self, plaintext: &str, aad: &str) -> anyhow::Result<String> {
+        let ciphertext = encrypt(&self.cipher, plaintext.as_bytes(), aad.as_bytes())?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(ciphertext))
+    }
+
+    /// Decrypts the output of `encrypt`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the ciphertext was modified or is bound to a different `aad`.
+    pub fn decrypt(&self, ciphertext: &str, aad: &str) -> anyhow::Result<String> {
+        let ciphertext = base64::engine::general_purpose::STANDARD.decode(ciphertext)?;
+        let plaintext = decrypt(&self.cipher, &ciphertext, aad.as_bytes())?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// Keyed digest of `value`, which allows looking up encrypted values by equality without
+    /// revealing them
+    ///
+    /// # Errors
+    ///
+    /// Only if the digest cannot be computed.
+    pub fn blind_index(&self, value: &str) -> anyhow::Result<String> {
+        hmac_sha256(&self.index_key, value.as_bytes()).map(hex::encode)
+    }
+}
+
+/// Context for deriving the blind index key from a data key
+const BLIND_INDEX_INFO: &[u8] = b"clearing-house blind index";
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use hmac::Mac;
+
+    let mut mac = <hmac::Hmac<sha2::Sha256> as Mac>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("Invalid HMAC key"))?;
+    mac.update(msg);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Binds a wrapped data key to its process and version, so wrapped keys cannot be swapped
+fn wrapped_key_aad(pid: &str, version: i32) -> Vec<u8> {
+    format!("{pid}:{version}").into_bytes()
+}
+
+fn encrypt(cipher: &Aes256Gcm, msg: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg, aad })
+        .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+
+    let mut result = nonce.to_vec();
+    result.extend(ciphertext);
+    Ok(result)
+}
+
+fn decrypt(cipher: &Aes256Gcm, data: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        anyhow::bail!("Ciphertext is too short");
+    }
+    let (nonce, msg) = data.split_at(NONCE_LEN);
+
+    cipher
+        .decrypt(nonce.into(), Payload { msg, aad })
+        .map_err(|_| anyhow::anyhow!("Decryption failed"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::KeyRing;
+    use crate::test_util::{TEST_MASTER_KEY as KEY_1, TEST_MASTER_KEY_2 as KEY_2};
+
+    #[test]
+    fn envelope_encryption() {
+        let keys = KeyRing::parse(KEY_1).expect("Valid key");
+        let (wrapped, data_key) = keys.generate_data_key("pid", 1).expect("Generating key");
+        assert_eq!(wrapped.master_key_id, keys.current().id());
+
+        let ciphertext = data_key
+            .encrypt("secret", "doc:payload")
+            .expect("Encrypting");
+        assert_ne!(ciphertext, "secret");
+        let unwrapped = keys.unwrap(&wrapped).expect("Unwrapping");
+        assert_eq!(
+            unwrapped
+                .decrypt(&ciphertext, "doc:payload")
+                .expect("Decrypting"),
+            "secret"
+        );
+        // The ciphertext is bound to its field
+        assert!(unwrapped
+            .decrypt(&ciphertext, "doc:transfer_contract")
+            .is_err());
+
+        // Wrapped keys are bound to their process and version
+        let mut swapped = wrapped.clone();
+        swapped.pid = "other".to_string();
+        assert!(keys.unwrap(&swapped).is_err());
+
+        // Blind indexes are deterministic per data key
+        assert_eq!(
+            data_key.blind_index("secret").expect("Indexing"),
+            unwrapped.blind_index("secret").expect("Indexing")
+        );
+        assert_ne!(
+            data_key.blind_index("secret").expect("Indexing"),
+            data_key.blind_index("other").expect("Indexing")
+        );
+        let (_, other_key) = keys.generate_data_key("pid", 2).expect("Generating key");
+        assert_ne!(
+            data_key.blind_index("secret").expect("Indexing"),
+            other_key.blind_index("secret").expect("Indexing")
+        );
+
+        // Unknown master keys
+        assert!(KeyRing::parse(KEY_2)
+            .expect("Valid key")
+            .unwrap(&wrapped)
+            .is_err());
+    }
+
+    #[test]
+    fn master_key_rotation() {
+        let old = KeyRing::parse(KEY_1).expect("Valid key");
+        let (wrapped, data_key) = old.generate_data_key("pid", 1).expect("Generating key");
+        let ciphertext = data_key.encrypt("secret", "aad").expect("Encrypting");
+
+        let rotated = KeyRing::parse(&format!("{KEY_2},\n{KEY_1}")).expect("Valid keys");
+        assert!(rotated.unwrap(&wrapped).is_ok());
+        let rewrapped = rotated
+            .rewrap(&wrapped)
+            .expect("Rewrapping")
+            .expect("Key is wrapped by an old master key");
+        assert_eq!(rewrapped.master_key_id, rotated.current().id());
+        assert_eq!(rotated.rewrap(&rewrapped).expect("Rewrapping"), None);
+
+        // Documents remain readable with the new master key only
+        let new = KeyRing::parse(KEY_2).expect("Valid key");
+        assert_eq!(
+            new.unwrap(&rewrapped)
+                .expect("Unwrapping")
+                .decrypt(&ciphertext, "aad")
+                .expect("Decrypting"),
+            "secret"
+        );
+
+        assert!(KeyRing::parse("").is_err());
+        assert!(KeyRing::parse("c2hvcnQ=").is_err());
+    }
+}
@@ -1,6 +1,11 @@
 use chrono::Local;
 
-use crate::model::ids::message::IdsMessage;
+use crate::model::crypto::DataKey;
+use crate::model::ids::message::{IdsMessage, PayloadEncoding};
+use crate::model::ids::{InfoModelDateTime, InfoModelId};
+
+/// Hash used as `previous_hash` for the first document of a process
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub struct Document<T> {
@@ -12,6 +17,27 @@ pub struct Document<T> {
     pub ts: chrono::DateTime<Local>,
     /// Content of the document
     pub content: IdsMessage<T>,
+    /// Hash of the previous document in the same process
+    pub previous_hash: Option<String>,
+    /// Hash over `previous_hash`, the canonicalized header and `content_sha256` of this document
+    pub hash: Option<String>,
+    /// Hex encoded SHA-256 digest of the payload, the transfer contract and the raw header, which
+    /// stays when the payload is purged or encrypted
+    #[serde(default)]
+    pub content_sha256: Option<String>,
+    /// Set once the retention period is over and the payload was purged
+    #[serde(default)]
+    pub expired_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Set once the payload was moved to the archive
+    #[serde(default)]
+    pub archived_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Version of the data key of the process, the document is encrypted with
+    #[serde(default)]
+    pub key_version: Option<i32>,
+    /// Blind index of the transfer contract of an encrypted document, for filtering by the
+    /// transfer contract without decrypting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_contract_index: Option<String>,
 }
 
 /// Documents should have a globally unique id, setting the id manually is discouraged.
@@ -23,6 +49,553 @@ impl<T> Document<T> {
             pid,
             ts: Local::now(),
             content,
+            previous_hash: None,
+            hash: None,
+            content_sha256: None,
+            expired_at: None,
+            archived_at: None,
+            key_version: None,
+            transfer_contract_index: None,
+        }
+    }
+}
+
+impl Document<String> {
+    /// Computes the hash of this document chained to `previous_hash`.
+    ///
+    /// The hash is the hex encoded SHA-256 digest of `previous_hash` followed by the canonical JSON
+    /// representation of the header of the document and `content_sha256`, which is computed from
+    /// the content, if it is not stored yet.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the document cannot be serialized.
+    pub fn chain_hash(&self, previous_hash: &str) -> serde_json::Result<String> {
+        use sha2::Digest;
+
+        let content_sha256 = match &self.content_sha256 {
+            Some(content_sha256) => content_sha256.clone(),
+            None => self.content_digest()?,
+        };
+        let canonical = serde_json::to_vec(&CanonicalDocument::new(self, &content_sha256))?;
+
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(previous_hash.as_bytes());
+        hasher.update(&canonical);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Computes `content_sha256` of this document: the hex encoded SHA-256 digest of the canonical
+    /// JSON representation of its payload, transfer contract and raw header
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the content cannot be serialized.
+    pub fn content_digest(&self) -> serde_json::Result<String> {
+        use sha2::Digest;
+
+        let canonical = serde_json::to_vec(&CanonicalContent::from(self))?;
+        Ok(format!("{:x}", sha2::Sha256::digest(&canonical)))
+    }
+
+    /// Checks if this document is linked to `previous_hash`. The hash is always recomputed from
+    /// the header and `content_sha256`, which is checked against the content, if it is available,
+    /// i.e. not expired, archived or still encrypted (shredded).
+    #[must_use]
+    pub fn is_linked_to(&self, previous_hash: &str) -> bool {
+        let content_available =
+            self.expired_at.is_none() && self.archived_at.is_none() && self.key_version.is_none();
+
+        self.hash
+            .as_deref()
+            .is_some_and(|hash| self.chain_hash(previous_hash).is_ok_and(|c| c == hash))
+            && (!content_available || self.matches_content_digest())
+    }
+
+    /// Checks if the content of this document matches its stored hash. Documents logged before
+    /// the hash chain have no hash to check.
+    #[must_use]
+    pub fn matches_hash(&self) -> bool {
+        match (self.previous_hash.as_deref(), self.hash.as_deref()) {
+            (Some(previous_hash), Some(hash)) => {
+                self.chain_hash(previous_hash)
+                    .is_ok_and(|computed| computed == hash)
+                    && self.matches_content_digest()
+            }
+            (None, Some(_)) => false,
+            (_, None) => true,
+        }
+    }
+
+    /// Checks if the content matches `content_sha256`. Without stored digest, `chain_hash` computes
+    /// it from the content.
+    fn matches_content_digest(&self) -> bool {
+        self.content_sha256
+            .as_deref()
+            .is_none_or(|stored| self.content_digest().is_ok_and(|c| c == stored))
+    }
+
+    /// Checks if `other` contains the same message as this document: the same header apart from
+    /// tokens, which change between retries of a connector, and the same payload
+    #[must_use]
+    pub fn has_same_content(&self, other: &Self) -> bool {
+        let header = |doc: &Self| {
+            let mut header = doc.content.header.clone();
+            header.pid = None;
+            header.security_token = None;
+            header.authorization_token = None;
+            serde_json::to_value(header).ok()
+        };
+
+        header(self).is_some_and(|h| Some(h) == header(other))
+            && self.content.payload == other.content.payload
+            && self.content.payload_type == other.content.payload_type
+            && self.content.payload_encoding == other.content.payload_encoding
+    }
+
+    /// Removes the payload of this document after its retention period
+    pub fn expire(&mut self, expired_at: chrono::DateTime<chrono::Utc>) {
+        self.content.payload = None;
+        self.content.payload_type = None;
+        self.content.payload_encoding = None;
+        self.expired_at = Some(expired_at);
+    }
+
+    /// Removes the payload of this document after it was moved to the archive
+    pub fn archive(&mut self, archived_at: chrono::DateTime<chrono::Utc>) {
+        self.content.payload = None;
+        self.content.payload_type = None;
+        self.content.payload_encoding = None;
+        self.archived_at = Some(archived_at);
+    }
+
+    /// Encrypts the payload, the transfer contract and the raw header of this document with `key`.
+    /// The hash is computed over the plaintext, so the document must be linked before.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the document is encrypted already or the encryption fails.
+    pub fn encrypt(&mut self, key: &DataKey) -> anyhow::Result<()> {
+        if self.key_version.is_some() {
+            anyhow::bail!("Document '{}' is encrypted already", self.id);
         }
+
+        self.transfer_contract_index = self
+            .content
+            .header
+            .transfer_contract
+            .as_deref()
+            .map(|c| key.blind_index(c))
+            .transpose()?;
+        let id = self.id;
+        for (field, value) in self.sensitive_fields() {
+            if let Some(plaintext) = value {
+                *value = Some(key.encrypt(plaintext, &format!("{id}:{field}"))?);
+            }
+        }
+        self.key_version = Some(key.version());
+        Ok(())
+    }
+
+    /// Decrypts this document, which was encrypted with `key`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if `key` has the wrong version or the document was modified.
+    pub fn decrypt(&mut self, key: &DataKey) -> anyhow::Result<()> {
+        if self.key_version != Some(key.version()) {
+            anyhow::bail!(
+                "Document '{}' is not encrypted with data key {}",
+                self.id,
+                key.version()
+            );
+        }
+
+        let id = self.id;
+        for (field, value) in self.sensitive_fields() {
+            if let Some(ciphertext) = value {
+                *value = Some(key.decrypt(ciphertext, &format!("{id}:{field}"))?);
+            }
+        }
+        self.key_version = None;
+        self.transfer_contract_index = None;
+        Ok(())
+    }
+
+    /// Fields, which are encrypted at rest
+    fn sensitive_fields(&mut self) -> [(&'static str, &mut Option<String>); 3] {
+        [
+            ("payload", &mut self.content.payload),
+            (
+                "transfer_contract",
+                &mut self.content.header.transfer_contract,
+            ),
+            ("raw_header", &mut self.content.raw_header),
+        ]
+    }
+
+    /// Links this document to `previous_hash` and stores the resulting hash and the digest of its
+    /// content in the document.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the document cannot be serialized.
+    pub fn link_to(&mut self, previous_hash: &str) -> serde_json::Result<()> {
+        self.content_sha256 = Some(self.content_digest()?);
+        self.hash = Some(self.chain_hash(previous_hash)?);
+        self.previous_hash = Some(previous_hash.to_string());
+        Ok(())
+    }
+}
+
+/// Position after a document in a query result, from which the next page continues. Documents are
+/// ordered by their creation time and id, so documents logged while paging neither shift the
+/// following pages nor are returned twice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentCursor {
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub id: uuid::Uuid,
+}
+
+impl DocumentCursor {
+    /// Cursor continuing after `doc`
+    #[must_use]
+    pub fn after<T>(doc: &Document<T>) -> Self {
+        Self {
+            created_at: doc.ts.to_utc(),
+            id: doc.id,
+        }
+    }
+
+    /// Opaque representation of the cursor for clients
+    #[must_use]
+    pub fn encode(&self) -> String {
+        use base64::Engine;
+
+        let raw = format!("{}:{}", self.created_at.timestamp_micros(), self.id);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    /// Parses the output of `encode`
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if `encoded` is not a valid cursor.
+    pub fn decode(encoded: &str) -> anyhow::Result<Self> {
+        use base64::Engine;
+
+        let raw =
+            String::from_utf8(base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)?)?;
+        let (micros, id) = raw
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Cursor is malformed"))?;
+        let created_at = chrono::DateTime::from_timestamp_micros(micros.parse()?)
+            .ok_or_else(|| anyhow::anyhow!("Cursor timestamp is out of range"))?;
+
+        Ok(Self {
+            created_at,
+            id: id.parse()?,
+        })
+    }
+}
+
+/// Filters on the documents of a process, all given filters must match
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocumentFilter {
+    /// IDS message type (`@type`), e.g. `ids:LogMessage`
+    pub message_type: Option<String>,
+    pub issuer_connector: Option<String>,
+    pub sender_agent: Option<String>,
+    pub correlation_message: Option<String>,
+    pub transfer_contract: Option<String>,
+    pub payload_type: Option<String>,
+    /// Prefix of the message id (`@id`)
+    pub id_prefix: Option<String>,
+    /// Blind indexes of `transfer_contract` under the data keys of the process, which match
+    /// encrypted documents
+    pub transfer_contract_indexes: Vec<String>,
+}
+
+/// Name of an IDS message type, as it is stored, e.g. `ids:LogMessage`
+#[must_use]
+pub fn message_type_name(message_type: &crate::model::ids::MessageType) -> Option<String> {
+    match serde_json::to_value(message_type) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Canonical representation of the header of a `Document` used for hashing.
+///
+/// Only contains fields that survive a roundtrip through the database and are neither purged nor
+/// encrypted, normalized to a representation that is independent of the local timezone and
+/// timestamp precision. The content is covered by its digest.
+#[derive(serde::Serialize)]
+struct CanonicalDocument<'a> {
+    id: &'a uuid::Uuid,
+    pid: &'a str,
+    ts: i64,
+    message_id: Option<&'a str>,
+    message_type: Option<String>,
+    model_version: &'a str,
+    correlation_message: Option<&'a str>,
+    issued: (Option<&'a str>, String),
+    issuer_connector: &'a InfoModelId,
+    content_version: Option<&'a str>,
+    recipient_connector: Option<&'a Vec<InfoModelId>>,
+    sender_agent: String,
+    recipient_agent: Option<&'a Vec<InfoModelId>>,
+    content_sha256: &'a str,
+}
+
+impl<'a> CanonicalDocument<'a> {
+    fn new(doc: &'a Document<String>, content_sha256: &'a str) -> Self {
+        let header = &doc.content.header;
+
+        Self {
+            id: &doc.id,
+            pid: &doc.pid,
+            ts: doc.ts.timestamp_micros(),
+            message_id: header.id.as_deref(),
+            message_type: message_type_name(&header.type_message),
+            model_version: &header.model_version,
+            correlation_message: header.correlation_message.as_deref(),
+            issued: canonical_issued(&header.issued),
+            issuer_connector: &header.issuer_connector,
+            content_version: header.content_version.as_deref(),
+            recipient_connector: header.recipient_connector.as_ref(),
+            sender_agent: header.sender_agent.to_string(),
+            recipient_agent: header.recipient_agent.as_ref(),
+            content_sha256,
+        }
+    }
+}
+
+/// Canonical representation of the content of a `Document`, which is purged after the retention
+/// period or encrypted at rest
+#[derive(serde::Serialize)]
+struct CanonicalContent<'a> {
+    payload: Option<&'a str>,
+    payload_type: Option<&'a str>,
+    payload_encoding: Option<PayloadEncoding>,
+    transfer_contract: Option<&'a str>,
+    raw_header: Option<&'a str>,
+}
+
+impl<'a> From<&'a Document<String>> for CanonicalContent<'a> {
+    fn from(doc: &'a Document<String>) -> Self {
+        Self {
+            payload: doc.content.payload.as_deref(),
+            payload_type: doc.content.payload_type.as_deref(),
+            payload_encoding: doc.content.payload_encoding,
+            transfer_contract: doc.content.header.transfer_contract.as_deref(),
+            raw_header: doc.content.raw_header.as_deref(),
+        }
+    }
+}
+
+/// Format and timezone independent representation of the time a message was issued
+fn canonical_issued(issued: &InfoModelDateTime) -> (Option<&str>, String) {
+    match issued {
+        InfoModelDateTime::Time(t) => (None, canonical_time(t)),
+        InfoModelDateTime::ComplexTime(t) => (t.format.as_deref(), canonical_time(&t.value)),
+    }
+}
+
+/// Timezone independent representation of a timestamp
+fn canonical_time(t: &chrono::DateTime<Local>) -> String {
+    t.with_timezone(&chrono::Utc)
+        .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Document, DocumentCursor, GENESIS_HASH};
+    use crate::test_util::{key_ring, TEST_MASTER_KEY};
+
+    fn document(payload: &str) -> Document<String> {
+        crate::test_util::document("test", payload)
+    }
+
+    #[test]
+    fn chain_hash() {
+        let mut first = document("first");
+        first
+            .link_to(GENESIS_HASH)
+            .expect("Document is serializable");
+        assert_eq!(first.previous_hash.as_deref(), Some(GENESIS_HASH));
+
+        let first_hash = first.hash.clone().expect("Hash was set");
+        assert_eq!(first_hash.len(), 64);
+        assert_eq!(
+            first
+                .chain_hash(GENESIS_HASH)
+                .expect("Document is serializable"),
+            first_hash
+        );
+
+        assert!(first.is_linked_to(GENESIS_HASH));
+
+        // Modified content does not match its digest
+        let mut modified = first.clone();
+        modified.content.payload = Some("modified".to_string());
+        assert!(!modified.is_linked_to(GENESIS_HASH));
+        assert!(!modified.matches_hash());
+
+        // The raw header is covered by the digest
+        let mut with_raw_header = first.clone();
+        with_raw_header.content.raw_header = Some("{}".to_string());
+        assert!(!with_raw_header.is_linked_to(GENESIS_HASH));
+
+        // A modified digest results in a different hash
+        let mut modified = first.clone();
+        modified.content_sha256 = modified.content_digest().ok().map(|d| d.replace('0', "1"));
+        assert_ne!(
+            modified
+                .chain_hash(GENESIS_HASH)
+                .expect("Document is serializable"),
+            first_hash
+        );
+
+        // A different predecessor results in a different hash
+        let mut second = document("second");
+        second
+            .link_to(&first_hash)
+            .expect("Document is serializable");
+        assert_ne!(
+            second
+                .chain_hash(GENESIS_HASH)
+                .expect("Document is serializable"),
+            second.hash.clone().expect("Hash was set")
+        );
+    }
+
+    #[test]
+    fn chain_hash_of_purged_documents() {
+        let mut doc = document("payload");
+        doc.link_to(GENESIS_HASH).expect("Document is serializable");
+
+        // The hash of purged documents is recomputed from their header and content digest
+        let mut expired = doc.clone();
+        expired.expire(chrono::Utc::now());
+        assert!(expired.is_linked_to(GENESIS_HASH));
+        expired.content.header.correlation_message = Some("modified".to_string());
+        assert!(!expired.is_linked_to(GENESIS_HASH));
+
+        let mut archived = doc.clone();
+        archived.archive(chrono::Utc::now());
+        assert!(archived.is_linked_to(GENESIS_HASH));
+        archived.ts += chrono::TimeDelta::seconds(1);
+        assert!(!archived.is_linked_to(GENESIS_HASH));
+
+        // Without stored digest, it is computed from the content, which is purged
+        let mut without_digest = doc.clone();
+        without_digest.content_sha256 = None;
+        assert!(without_digest.is_linked_to(GENESIS_HASH));
+        without_digest.expire(chrono::Utc::now());
+        assert!(!without_digest.is_linked_to(GENESIS_HASH));
+    }
+
+    #[test]
+    fn encryption() {
+        let keys = key_ring(TEST_MASTER_KEY);
+        let (_, key) = keys.generate_data_key("test", 1).expect("Generating key");
+
+        let mut doc = document("payload");
+        doc.content.header.transfer_contract = Some("contract".to_string());
+        doc.content.raw_header = Some("{\"ids:transferContract\": \"contract\"}".to_string());
+        doc.link_to(GENESIS_HASH).expect("Document is serializable");
+
+        let mut encrypted = doc.clone();
+        encrypted.encrypt(&key).expect("Encrypting");
+        assert_eq!(encrypted.key_version, Some(1));
+        assert_ne!(encrypted.content.payload, doc.content.payload);
+        assert_ne!(
+            encrypted.content.header.transfer_contract,
+            doc.content.header.transfer_contract
+        );
+        assert_ne!(encrypted.content.raw_header, doc.content.raw_header);
+        assert!(encrypted.encrypt(&key).is_err());
+
+        // The hash of encrypted documents is recomputed without decrypting them
+        assert!(encrypted.is_linked_to(GENESIS_HASH));
+        let mut modified = encrypted.clone();
+        modified.content.header.content_version = Some("modified".to_string());
+        assert!(!modified.is_linked_to(GENESIS_HASH));
+
+        // Ciphertexts cannot be moved to other documents
+        let mut moved = document("other");
+        moved.content.payload = encrypted.content.payload.clone();
+        moved.key_version = Some(1);
+        assert!(moved.decrypt(&key).is_err());
+
+        encrypted.decrypt(&key).expect("Decrypting");
+        assert_eq!(encrypted.key_version, None);
+        assert_eq!(encrypted.content.payload, doc.content.payload);
+        assert!(encrypted.is_linked_to(GENESIS_HASH));
+    }
+
+    #[test]
+    fn chain_hash_ignores_timezone_and_precision() {
+        use chrono::SubsecRound;
+
+        let mut doc = document("payload");
+        doc.ts = doc.ts.trunc_subsecs(6);
+        let hash = doc
+            .chain_hash(GENESIS_HASH)
+            .expect("Document is serializable");
+
+        // Roundtrip as it happens in the database
+        let mut restored = doc.clone();
+        restored.ts = chrono::TimeZone::from_utc_datetime(&chrono::Local, &doc.ts.naive_utc());
+        assert_eq!(
+            restored
+                .chain_hash(GENESIS_HASH)
+                .expect("Document is serializable"),
+            hash
+        );
+    }
+
+    #[test]
+    fn cursor_roundtrip() {
+        use chrono::SubsecRound;
+
+        let mut doc = document("payload");
+        doc.ts = doc.ts.trunc_subsecs(6);
+        let cursor = DocumentCursor::after(&doc);
+        assert_eq!(
+            DocumentCursor::decode(&cursor.encode()).expect("Valid cursor"),
+            cursor
+        );
+        assert_eq!(cursor.created_at, doc.ts.to_utc());
+
+        assert!(DocumentCursor::decode("").is_err());
+        assert!(DocumentCursor::decode("not a cursor").is_err());
+        assert!(DocumentCursor::decode("MTIzOm5vLXV1aWQ").is_err());
+    }
+
+    #[test]
+    fn same_content() {
+        use crate::model::ids::{InfoModelId, MessageType, SecurityToken};
+
+        let doc = document("payload");
+        let mut retry = doc.clone();
+        retry.id = uuid::Uuid::new_v4();
+        retry.ts = chrono::Local::now();
+        retry.content.header.security_token = Some(SecurityToken {
+            type_message: MessageType::DAPSToken,
+            id: None,
+            token_format: None,
+            token_value: "token".to_string(),
+        });
+        retry.content.raw_header = Some("{}".to_string());
+        assert!(doc.has_same_content(&retry));
+
+        let mut other = doc.clone();
+        other.content.payload = Some("other payload".to_string());
+        assert!(!doc.has_same_content(&other));
+        let mut other = doc.clone();
+        other.content.header.sender_agent = InfoModelId::new("http://other".to_string());
+        assert!(!doc.has_same_content(&other));
     }
 }
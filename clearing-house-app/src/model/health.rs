@@ -0,0 +1,45 @@
+//! Health of the Clearing House and the components it depends on
+
+use std::collections::BTreeMap;
+
+/// Status of a component, ordered from healthy to unhealthy
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// The component works as expected
+    Up,
+    /// The component works, but needs attention, e.g. a cached value could not be refreshed
+    Degraded,
+    /// The component does not work
+    Down,
+}
+
+/// Health of a single component
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    /// Details explaining the status, e.g. expiry times or the last error
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub details: serde_json::Value,
+}
+
+/// Health of the Clearing House, whose status is the worst status of its components
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+impl HealthReport {
+    #[must_use]
+    pub fn new(components: BTreeMap<String, ComponentHealth>) -> Self {
+        let status = components
+            .values()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Up);
+        Self { status, components }
+    }
+}
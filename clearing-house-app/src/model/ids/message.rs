@@ -25,9 +25,9 @@ pub struct IdsHeader {
     pub model_version: String,
     /// Correlated message, e.g., response to a previous message. Value: URI of the correlatedMessage
     #[serde(
-    rename = "ids:correlationMessage",
-    alias = "correlationMessage",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:correlationMessage",
+        alias = "correlationMessage",
+        skip_serializing_if = "Option::is_none"
     )]
     pub correlation_message: Option<String>,
     /// Date of issuing the Message
@@ -41,51 +41,46 @@ pub struct IdsHeader {
     pub sender_agent: InfoModelId,
     /// Target Connector. Value: URI of target Connector. Can have multiple values at the same time.
     #[serde(
-    rename = "ids:recipientConnector",
-    alias = "recipientConnector",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:recipientConnector",
+        alias = "recipientConnector",
+        skip_serializing_if = "Option::is_none"
     )]
     pub recipient_connector: Option<Vec<InfoModelId>>,
     /// Agent, for which the message is intended. Value: URI of an instance of ids:Agent. Can have multiple values at the same time
     #[serde(
-    rename = "ids:recipientAgent",
-    alias = "recipientAgent",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:recipientAgent",
+        alias = "recipientAgent",
+        skip_serializing_if = "Option::is_none"
     )]
     pub recipient_agent: Option<Vec<InfoModelId>>,
     /// Contract which is (or will be) the legal basis of the data transfer. Value: Instance of class ids:Contract.
     #[serde(
-    rename = "ids:transferContract",
-    alias = "transferContract",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:transferContract",
+        alias = "transferContract",
+        skip_serializing_if = "Option::is_none"
     )]
     pub transfer_contract: Option<String>,
     /// Value describing the version of the content. Value: Version number of the content.
     #[serde(
-    rename = "ids:contentVersion",
-    alias = "contentVersion",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:contentVersion",
+        alias = "contentVersion",
+        skip_serializing_if = "Option::is_none"
     )]
     pub content_version: Option<String>,
     /// Token representing a claim, that the sender supports a certain security profile. Value: Instance of ids:DynamicAttributeToken.
-    #[serde(
-    rename = "ids:securityToken",
-    alias = "securityToken",
-    )]
+    #[serde(rename = "ids:securityToken", alias = "securityToken")]
     pub security_token: Option<SecurityToken>,
     /// An authorization token. The token can be issued from the Connector of the Data Provider (A) to the Connector of the
     /// Data Consumer (B). Can be used to avoid full authentication via DAPS, if Connector B wants to access the data of
     /// Connector A. Value: Instance of ids:Token
     #[serde(
-    rename = "ids:authorizationToken",
-    alias = "authorizationToken",
-    skip_serializing_if = "Option::is_none"
+        rename = "ids:authorizationToken",
+        alias = "authorizationToken",
+        skip_serializing_if = "Option::is_none"
     )]
     pub authorization_token: Option<String>,
 }
 
-
-
 /// Metadata describing payload exchanged by interacting Connectors.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct IdsMessage<T> {
@@ -93,6 +88,88 @@ pub struct IdsMessage<T> {
     pub payload: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload_type: Option<String>,
+    /// Encoding of a binary payload, missing if the payload is text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_encoding: Option<PayloadEncoding>,
+    /// Multipart `header` part exactly as it was received, including fields unknown to
+    /// `IdsHeader`. Only returned on request, as it contains the security token of the sender.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_header: Option<String>,
+}
+
+/// Encoding of a binary payload as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    /// Standard base64 with padding
+    Base64,
+}
+
+/// Payload of a multipart message exactly as it was received
+#[derive(Debug, Clone)]
+pub struct RawPayload {
+    /// `Content-Type` of the `payload` part
+    pub media_type: Option<String>,
+    pub data: bytes::Bytes,
+}
+
+impl IdsMessage<String> {
+    /// Sets the payload to `data` of the type `media_type`. Payloads of a textual type, which are
+    /// valid UTF-8, are kept as text, all others are encoded with base64. Payloads without type are
+    /// expected to be JSON.
+    pub fn set_payload(&mut self, media_type: Option<String>, data: &[u8]) {
+        use base64::Engine;
+
+        let text = if is_text(media_type.as_deref()) {
+            std::str::from_utf8(data).ok()
+        } else {
+            None
+        };
+        if let Some(text) = text {
+            self.payload = Some(text.to_string());
+            self.payload_encoding = None;
+        } else {
+            self.payload = Some(base64::engine::general_purpose::STANDARD.encode(data));
+            self.payload_encoding = Some(PayloadEncoding::Base64);
+        }
+        self.payload_type = media_type;
+    }
+
+    /// Bytes of the payload as they were received
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if a binary payload is not properly encoded.
+    pub fn payload_bytes(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        use base64::Engine;
+
+        let Some(payload) = &self.payload else {
+            return Ok(None);
+        };
+        Ok(Some(match self.payload_encoding {
+            None => payload.as_bytes().to_vec(),
+            Some(PayloadEncoding::Base64) => {
+                base64::engine::general_purpose::STANDARD.decode(payload)?
+            }
+        }))
+    }
+}
+
+/// Checks if `media_type` is a textual media type
+fn is_text(media_type: Option<&str>) -> bool {
+    let Some(media_type) = media_type else {
+        return true;
+    };
+    let essence = media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(essence.as_str(), "application/json" | "application/xml")
 }
 
 impl Default for IdsHeader {
@@ -112,7 +189,9 @@ impl Default for IdsHeader {
             correlation_message: None,
             issued: InfoModelDateTime::default(),
             issuer_connector: InfoModelId::new(String::new()),
-            sender_agent: InfoModelId::SimpleId("https://w3id.org/idsa/core/ClearingHouse".to_string()),
+            sender_agent: InfoModelId::SimpleId(
+                "https://w3id.org/idsa/core/ClearingHouse".to_string(),
+            ),
             recipient_connector: None,
             recipient_agent: None,
             transfer_contract: None,
@@ -132,6 +211,8 @@ impl Default for IdsHeader {
 ///
 /// metadata that we also need to store
 /// - `message_id`
+/// - `type_message`
+/// - `context`
 /// - `pid`
 /// - `model_version`
 /// - `correlation_message`
@@ -144,9 +225,14 @@ impl Default for IdsHeader {
 /// - `authorization_token`
 /// - `payload`
 /// - `payload_type`
+///
+/// The raw header is not converted, it contains the security token of the sender.
 impl<T: Clone> From<Document<T>> for IdsMessage<T> {
     fn from(doc: Document<T>) -> Self {
-        doc.content.clone()
+        Self {
+            raw_header: None,
+            ..doc.content
+        }
     }
 }
 
@@ -157,6 +243,8 @@ impl<T: Clone> From<Document<T>> for IdsMessage<T> {
 ///
 /// metadata that we also need to store
 /// - `message_id`
+/// - `type_message`
+/// - `context`
 /// - `pid`
 /// - `model_version`
 /// - `correlation_message`
@@ -169,6 +257,8 @@ impl<T: Clone> From<Document<T>> for IdsMessage<T> {
 /// - `authorization_token`
 /// - `payload`
 /// - `payload_type`
+/// - `payload_encoding`
+/// - `raw_header`
 impl<T: Clone> From<IdsMessage<T>> for Document<T> {
     fn from(value: IdsMessage<T>) -> Self {
         let mut m = value.clone();
@@ -191,3 +281,52 @@ fn autogen(message: &str) -> String {
         uuid::Uuid::new_v4()
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::{IdsHeader, IdsMessage, PayloadEncoding};
+
+    fn message(media_type: Option<&str>, data: &[u8]) -> IdsMessage<String> {
+        let mut message = IdsMessage {
+            header: IdsHeader::default(),
+            payload: None,
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        };
+        message.set_payload(media_type.map(ToString::to_string), data);
+        message
+    }
+
+    #[test]
+    fn payload_encoding() {
+        let json = message(None, br#"{ "b": 1, "a": 2 }"#);
+        assert_eq!(json.payload.as_deref(), Some(r#"{ "b": 1, "a": 2 }"#));
+        assert_eq!(json.payload_encoding, None);
+
+        let csv = message(Some("text/csv; charset=utf-8"), b"a;b\n1;2\n");
+        assert_eq!(csv.payload.as_deref(), Some("a;b\n1;2\n"));
+        assert_eq!(csv.payload_type.as_deref(), Some("text/csv; charset=utf-8"));
+        assert_eq!(csv.payload_encoding, None);
+
+        // Binary media types and text, which is not valid UTF-8, are encoded
+        for (media_type, data) in [
+            (Some("application/pdf"), b"%PDF-1.7".as_slice()),
+            (Some("text/csv; charset=iso-8859-1"), b"caf\xe9".as_slice()),
+            (None, b"\x00\xff".as_slice()),
+        ] {
+            let binary = message(media_type, data);
+            assert_eq!(binary.payload_encoding, Some(PayloadEncoding::Base64));
+            assert_eq!(
+                binary.payload_bytes().expect("Valid payload"),
+                Some(data.to_vec())
+            );
+        }
+        assert_eq!(
+            message(Some("application/pdf"), b"%PDF-1.7")
+                .payload
+                .as_deref(),
+            Some("JVBERi0xLjc=")
+        );
+    }
+}
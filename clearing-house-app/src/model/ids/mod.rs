@@ -162,6 +162,8 @@ impl<T> MessageProcessedNotificationMessage<T> {
                 header,
                 payload: Some(payload),
                 payload_type: None,
+                payload_encoding: None,
+                raw_header: None,
             },
         }
     }
@@ -197,7 +199,7 @@ where
 }
 
 pub struct ResultMessage<T> {
-    inner: IdsMessage<IdsQueryResult<T>>,
+    inner: IdsMessage<T>,
 }
 
 impl<T> ResultMessage<T> {
@@ -205,7 +207,7 @@ impl<T> ResultMessage<T> {
     pub fn new(
         clearinghouse_uri: &str,
         daps_token: &str,
-        payload: IdsQueryResult<T>,
+        payload: T,
         correlation_msg_id: Option<String>,
     ) -> Self {
         let header = IdsHeader {
@@ -229,6 +231,8 @@ impl<T> ResultMessage<T> {
                 header,
                 payload: Some(payload),
                 payload_type: None,
+                payload_encoding: None,
+                raw_header: None,
             },
         }
     }
@@ -296,7 +300,8 @@ impl RejectionMessage {
 
 impl axum::response::IntoResponse for RejectionMessage {
     fn into_response(self) -> axum::response::Response {
-        let header = serde_json::to_vec(&self.inner).expect("Header is serializable");
+        // The header includes the rejection reason
+        let header = serde_json::to_vec(&self).expect("Header is serializable");
 
         let form = axum_extra::response::multiple::MultipartForm::with_parts(vec![
             axum_extra::response::multiple::Part::raw_part(
@@ -558,6 +563,21 @@ pub struct IdsQueryResult<T> {
     pub size: i32,
     pub order: String,
     pub documents: Vec<IdsMessage<T>>,
+    /// Time (RFC 3339) at which the documents expire by their message id, documents without
+    /// entry are kept forever
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub expires_at: std::collections::BTreeMap<String, String>,
+    /// Number of documents matching the query
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub total_pages: u64,
+    /// Whether further pages follow this page
+    #[serde(default)]
+    pub has_next: bool,
+    /// Opaque cursor for requesting the next page, missing if there are no further documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl<T> IdsQueryResult<T> {
@@ -584,6 +604,8 @@ impl<T> IdsQueryResult<T> {
             .format("%Y-%m-%d %H:%M:%S")
             .to_string();
 
+        // A single page unless the page metadata is set otherwise
+        let total = u64::try_from(documents.len()).unwrap_or(u64::MAX);
         IdsQueryResult {
             date_from,
             date_to,
@@ -591,6 +613,11 @@ impl<T> IdsQueryResult<T> {
             size: size.unwrap_or(-1),
             order,
             documents,
+            expires_at: std::collections::BTreeMap::new(),
+            total,
+            total_pages: u64::from(total > 0),
+            has_next: false,
+            next_cursor: None,
         }
     }
 }
@@ -0,0 +1,253 @@
+//! Merkle trees over the documents of the Clearing House, following the construction of
+//! [RFC 9162](https://www.rfc-editor.org/rfc/rfc9162#section-2.1) (Certificate Transparency).
+//!
+//! Per-process trees use the document hashes of the hash chain (in chain order) as leaves. The
+//! global tree uses the latest root of every process (ordered by process id) as leaves.
+
+use sha2::{Digest, Sha256};
+
+type Hash = [u8; 32];
+
+/// Published Merkle root, either of a single process or over all processes (global root)
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleRoot {
+    /// Database id, `None` if not yet stored
+    pub id: Option<i32>,
+    /// Process id, `None` for the global root
+    pub pid: Option<String>,
+    /// Number of leaves of the tree
+    pub tree_size: u64,
+    /// Hex encoded root hash
+    pub root: String,
+    /// Global roots only: Highest id of the process roots covered by this root
+    pub covers_up_to: Option<i32>,
+    /// Signed `MerkleRootClaims` as JWS
+    pub signature: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Claims of a signed Merkle root
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MerkleRootClaims {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process_id: Option<String>,
+    pub tree_size: u64,
+    pub root: String,
+    pub timestamp: i64,
+    pub clearing_house_version: String,
+}
+
+impl MerkleRoot {
+    /// Creates a new Merkle root and signs it with the key of `cert_util`
+    ///
+    /// # Errors
+    ///
+    /// Only if issues with reading the key or signing the claims occur.
+    pub fn new_signed(
+        pid: Option<String>,
+        tree_size: u64,
+        root: String,
+        covers_up_to: Option<i32>,
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> anyhow::Result<Self> {
+        let created_at = chrono::Utc::now();
+        let claims = MerkleRootClaims {
+            process_id: pid.clone(),
+            tree_size,
+            root: root.clone(),
+            timestamp: created_at.timestamp(),
+            clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let signature = crate::model::claims::sign_jws(&claims, cert_util)?;
+
+        Ok(Self {
+            id: None,
+            pid,
+            tree_size,
+            root,
+            covers_up_to,
+            signature,
+            created_at,
+        })
+    }
+}
+
+/// Leaf of the global Merkle tree
+#[derive(serde::Serialize)]
+struct GlobalLeaf<'a> {
+    process_id: &'a str,
+    tree_size: u64,
+    root: &'a str,
+}
+
+/// Returns the leaf of the global tree for the process root `root`
+///
+/// # Panics
+///
+/// Never panics, as the leaf only consists of strings and numbers
+#[must_use]
+pub fn global_leaf(root: &MerkleRoot) -> Vec<u8> {
+    serde_json::to_vec(&GlobalLeaf {
+        process_id: root.pid.as_deref().unwrap_or_default(),
+        tree_size: root.tree_size,
+        root: &root.root,
+    })
+    .expect("Leaf is serializable")
+}
+
+fn leaf_hash(leaf: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(leaf);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Largest power of two smaller than `n`
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+fn tree_hash<L: AsRef<[u8]>>(leaves: &[L]) -> Hash {
+    match leaves {
+        [] => Sha256::digest([]).into(),
+        [leaf] => leaf_hash(leaf.as_ref()),
+        _ => {
+            let k = split(leaves.len());
+            node_hash(&tree_hash(&leaves[..k]), &tree_hash(&leaves[k..]))
+        }
+    }
+}
+
+fn path<L: AsRef<[u8]>>(index: usize, leaves: &[L]) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return vec![];
+    }
+
+    let k = split(leaves.len());
+    if index < k {
+        let mut p = path(index, &leaves[..k]);
+        p.push(tree_hash(&leaves[k..]));
+        p
+    } else {
+        let mut p = path(index - k, &leaves[k..]);
+        p.push(tree_hash(&leaves[..k]));
+        p
+    }
+}
+
+/// Computes the hex encoded Merkle tree hash over `leaves`
+#[must_use]
+pub fn root<L: AsRef<[u8]>>(leaves: &[L]) -> String {
+    hex::encode(tree_hash(leaves))
+}
+
+/// Computes the hex encoded audit path of the leaf at `index`. Returns `None` if `index` is out of
+/// range.
+#[must_use]
+pub fn audit_path<L: AsRef<[u8]>>(leaves: &[L], index: usize) -> Option<Vec<String>> {
+    (index < leaves.len()).then(|| path(index, leaves).iter().map(hex::encode).collect())
+}
+
+/// Verifies that `leaf` is included at `index` in the tree with `tree_size` leaves and the hex
+/// encoded root hash `root`
+#[must_use]
+pub fn verify_inclusion(
+    leaf: &[u8],
+    index: u64,
+    tree_size: u64,
+    audit_path: &[String],
+    root: &str,
+) -> bool {
+    if index >= tree_size {
+        return false;
+    }
+
+    let (mut f_n, mut s_n) = (index, tree_size - 1);
+    let mut r = leaf_hash(leaf);
+    for p in audit_path {
+        let Ok(Ok(p)) = hex::decode(p).map(Hash::try_from) else {
+            return false;
+        };
+        if s_n == 0 {
+            return false;
+        }
+
+        if f_n & 1 == 1 || f_n == s_n {
+            r = node_hash(&p, &r);
+            while f_n & 1 == 0 && f_n != 0 {
+                f_n >>= 1;
+                s_n >>= 1;
+            }
+        } else {
+            r = node_hash(&r, &p);
+        }
+        f_n >>= 1;
+        s_n >>= 1;
+    }
+
+    s_n == 0 && hex::encode(r) == root
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn inclusion_proofs() {
+        for size in 1..=17_usize {
+            let leaves: Vec<String> = (0..size).map(|i| format!("leaf {i}")).collect();
+            let root = super::root(&leaves);
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let path = super::audit_path(&leaves, index).expect("Index is in range");
+                assert!(super::verify_inclusion(
+                    leaf.as_bytes(),
+                    index as u64,
+                    size as u64,
+                    &path,
+                    &root
+                ));
+                // Wrong leaf or modified path must not verify
+                assert!(!super::verify_inclusion(
+                    b"other",
+                    index as u64,
+                    size as u64,
+                    &path,
+                    &root
+                ));
+                if let Some((first, rest)) = path.split_first() {
+                    let mut modified = vec![first.replace('0', "1").replace('a', "b")];
+                    modified.extend_from_slice(rest);
+                    assert!(!super::verify_inclusion(
+                        leaf.as_bytes(),
+                        index as u64,
+                        size as u64,
+                        &modified,
+                        &root
+                    ));
+                }
+            }
+            assert!(super::audit_path(&leaves, size).is_none());
+        }
+    }
+
+    #[test]
+    fn root_of_single_leaf() {
+        use sha2::Digest;
+
+        let mut hasher = sha2::Sha256::new();
+        hasher.update([0x00]);
+        hasher.update(b"leaf");
+        assert_eq!(super::root(&["leaf"]), hex::encode(hasher.finalize()));
+    }
+}
@@ -1,10 +1,17 @@
 use std::ops::Add;
 
+pub mod archive;
 pub mod claims;
 pub mod constants;
+pub mod crypto;
 pub(crate) mod document;
+pub mod health;
 pub mod ids;
+pub mod merkle;
 pub mod process;
+// The fallback search is only used by stores without a full-text index
+#[cfg_attr(not(any(feature = "sqlite", feature = "memory")), allow(dead_code))]
+pub(crate) mod search;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum SortingOrder {
@@ -23,18 +30,21 @@ const fn start_of_day() -> chrono::NaiveTime {
     }
 }
 
-/// Time 23:59:59; idiomatic way to create a `chrono::NaiveTime` object
+/// Time 23:59:59.999999, the last microsecond of a day; idiomatic way to create a
+/// `chrono::NaiveTime` object
 const fn end_of_day() -> chrono::NaiveTime {
-    if let Some(time) = chrono::NaiveTime::from_hms_opt(23, 59, 59) {
+    if let Some(time) = chrono::NaiveTime::from_hms_micro_opt(23, 59, 59, 999_999) {
         time
     } else {
-        panic!("23:59:59 is a valid time")
+        panic!("23:59:59.999999 is a valid time")
     }
 }
 
-/// Parses a date string into a `chrono::NaiveDateTime` object. If `to_date` is true, the time will be set to 23:59:59, otherwise it is 00:00:00.
-pub fn parse_date(date: Option<String>, to_date: bool) -> Option<chrono::NaiveDateTime> {
-    // If it is a to_date, we want to set the time to 23:59:59, otherwise it is 00:00:00
+/// Parses a date string into a UTC timestamp. Accepts RFC 3339 timestamps with offset, e.g.
+/// `2024-01-01T12:00:00+02:00`, and dates, e.g. `2024-01-01`, which denote a day in UTC. If
+/// `to_date` is true, the time of a date will be set to the end of the day, otherwise it is 00:00:00.
+pub fn parse_date(date: Option<String>, to_date: bool) -> Option<chrono::DateTime<chrono::Utc>> {
+    // If it is a to_date, we want to set the time to the end of the day, otherwise it is 00:00:00
     let time: chrono::NaiveTime = if to_date {
         end_of_day()
     } else {
@@ -44,8 +54,11 @@ pub fn parse_date(date: Option<String>, to_date: bool) -> Option<chrono::NaiveDa
     match date {
         Some(d) => {
             debug!("Parsing date: {}", &d);
+            if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(&restore_offset_sign(&d)) {
+                return Some(timestamp.to_utc());
+            }
             match chrono::NaiveDate::parse_from_str(&d, "%Y-%m-%d") {
-                Ok(date) => Some(date.and_time(time)),
+                Ok(date) => Some(date.and_time(time).and_utc()),
                 Err(e) => {
                     error!("Parsing date '{d}' failed: {:#?}", e);
                     None
@@ -56,25 +69,47 @@ pub fn parse_date(date: Option<String>, to_date: bool) -> Option<chrono::NaiveDa
     }
 }
 
-/// Validates the provided dates. `date_now` is optional and defaults to `chrono::Local::now().naive_local()`.
+/// Restores the '+' of a trailing ` hh:mm` offset of `date`, because an unencoded '+' arrives as
+/// space in query strings. Other spaces, e.g. separating date and time, are kept.
+fn restore_offset_sign(date: &str) -> std::borrow::Cow<'_, str> {
+    let bytes = date.as_bytes();
+    match bytes
+        .len()
+        .checked_sub(6)
+        .map(|split| (split, &bytes[split..]))
+    {
+        Some((split, [b' ', h1, h2, b':', m1, m2]))
+            if [h1, h2, m1, m2].iter().all(|c| c.is_ascii_digit()) =>
+        {
+            std::borrow::Cow::Owned(format!("{}+{}", &date[..split], &date[split + 1..]))
+        }
+        _ => std::borrow::Cow::Borrowed(date),
+    }
+}
+
+/// Validates the provided dates. `date_now` is optional and defaults to `chrono::Utc::now()`.
+/// The default window starts two weeks before the current day in UTC.
 ///
 /// # Errors
 ///
 /// Throws an error if `date_from` is `Option::None` and `date_to` is `Option::Some()`.
 pub fn validate_and_sanitize_dates(
-    date_from: Option<chrono::NaiveDateTime>,
-    date_to: Option<chrono::NaiveDateTime>,
-    date_now: Option<chrono::NaiveDateTime>,
-) -> anyhow::Result<(chrono::NaiveDateTime, chrono::NaiveDateTime)> {
-    let now = date_now.unwrap_or(chrono::Local::now().naive_local());
+    date_from: Option<chrono::DateTime<chrono::Utc>>,
+    date_to: Option<chrono::DateTime<chrono::Utc>>,
+    date_now: Option<chrono::DateTime<chrono::Utc>>,
+) -> anyhow::Result<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    let now = date_now.unwrap_or_else(chrono::Utc::now);
     debug!(
         "... validating dates: now: {:#?} , from: {:#?} , to: {:#?}",
         &now, &date_from, &date_to
     );
 
     let default_to_date = now.add(chrono::TimeDelta::seconds(1));
-    let default_from_date =
-        default_to_date.date().and_time(start_of_day()) - chrono::Duration::weeks(2);
+    let default_from_date = default_to_date
+        .date_naive()
+        .and_time(start_of_day())
+        .and_utc()
+        - chrono::Duration::weeks(2);
 
     match (date_from, date_to) {
         (Some(from), None) if from < now => Ok((from, default_to_date)),
@@ -92,8 +127,8 @@ mod test {
     #[test]
     fn validate_and_sanitize_dates() {
         // Setup dates for testing
-        let date_now = chrono::Local::now().naive_local();
-        let date_now_midnight = date_now.date().and_time(start_of_day());
+        let date_now = chrono::Utc::now();
+        let date_now_midnight = date_now.date_naive().and_time(start_of_day()).and_utc();
         let date_from = date_now_midnight
             - chrono::TimeDelta::try_weeks(2).expect("2 weeks is a valid duration");
         let date_to = date_now_midnight
@@ -167,11 +202,41 @@ mod test {
         assert!(super::parse_date(wrong_date, false).is_none());
         assert_eq!(
             super::parse_date(valid_date.clone(), false),
-            Some(valid_date_parsed.and_time(start_of_day()))
+            Some(valid_date_parsed.and_time(start_of_day()).and_utc())
         );
         assert_eq!(
             super::parse_date(valid_date, true),
-            Some(valid_date_parsed.and_time(end_of_day()))
+            Some(valid_date_parsed.and_time(end_of_day()).and_utc())
         );
+
+        // Timestamps with offset are converted to UTC
+        let utc = chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+            .and_then(|d| d.and_hms_milli_opt(10, 30, 0, 500))
+            .expect("This is valid")
+            .and_utc();
+        for timestamp in [
+            "2020-01-01T12:30:00.5+02:00",
+            "2020-01-01T12:30:00.5 02:00",
+            "2020-01-01T10:30:00.500Z",
+            "2020-01-01T05:30:00.5-05:00",
+        ] {
+            assert_eq!(
+                super::parse_date(Some(timestamp.to_string()), true),
+                Some(utc)
+            );
+        }
+        assert!(super::parse_date(Some("2020-01-01T12:30:00".to_string()), false).is_none());
+
+        // A space separating date and time is kept, only the sign of the offset is restored
+        for timestamp in [
+            "2020-01-01 10:30:00.5Z",
+            "2020-01-01 12:30:00.5+02:00",
+            "2020-01-01 12:30:00.5 02:00",
+        ] {
+            assert_eq!(
+                super::parse_date(Some(timestamp.to_string()), false),
+                Some(utc)
+            );
+        }
     }
 }
@@ -1,5 +1,3 @@
-use anyhow::anyhow;
-
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub struct Process {
     pub id: String,
@@ -18,6 +16,263 @@ impl Process {
     }
 }
 
+/// Overview of a process, as listed for its owners
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProcessInfo {
+    pub pid: String,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub owners: Vec<String>,
+    /// Number of documents logged in the process
+    pub document_count: u64,
+}
+
+/// Page of the processes owned by a client
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ProcessList {
+    pub page: u64,
+    pub size: u64,
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Signed record of an erased process, proving that the process existed and was erased
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Tombstone {
+    pub pid: String,
+    /// Owners of the process at the time of erasure
+    pub owners: Vec<String>,
+    /// Owners, who consented to the erasure
+    pub consents: Vec<String>,
+    /// Number of documents in the hash chain of the process
+    pub chain_length: u64,
+    /// Hash of the last document in the hash chain of the process
+    pub chain_head: Option<String>,
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+    /// Signed `TombstoneClaims` as JWS
+    pub signature: String,
+}
+
+/// Claims of a signed `Tombstone`
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TombstoneClaims {
+    pub process_id: String,
+    pub owners: Vec<String>,
+    pub consents: Vec<String>,
+    pub chain_length: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_head: Option<String>,
+    pub deleted_at: i64,
+    pub clearing_house_version: String,
+}
+
+impl Tombstone {
+    /// Creates a new tombstone and signs it with the key of `cert_util`
+    ///
+    /// # Errors
+    ///
+    /// Only if issues with reading the key or signing the claims occur.
+    pub fn new_signed(
+        pid: String,
+        owners: Vec<String>,
+        consents: Vec<String>,
+        (chain_length, chain_head): (u64, Option<String>),
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> anyhow::Result<Self> {
+        let deleted_at = chrono::Utc::now();
+        let claims = TombstoneClaims {
+            process_id: pid.clone(),
+            owners: owners.clone(),
+            consents: consents.clone(),
+            chain_length,
+            chain_head: chain_head.clone(),
+            deleted_at: deleted_at.timestamp(),
+            clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let signature = crate::model::claims::sign_jws(&claims, cert_util)?;
+
+        Ok(Self {
+            pid,
+            owners,
+            consents,
+            chain_length,
+            chain_head,
+            deleted_at,
+            signature,
+        })
+    }
+}
+
+/// Signed record of a shredded process. The data keys of the process were destroyed, so its
+/// encrypted documents cannot be decrypted anymore, while the hash chain stays verifiable.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ShredCertificate {
+    pub pid: String,
+    /// Owners of the process at the time of shredding
+    pub owners: Vec<String>,
+    /// Owners, who consented to the shredding
+    pub consents: Vec<String>,
+    /// Versions of the destroyed data keys
+    pub key_versions: Vec<i32>,
+    /// Number of documents in the hash chain of the process
+    pub chain_length: u64,
+    /// Hash of the last document in the hash chain of the process
+    pub chain_head: Option<String>,
+    pub shredded_at: chrono::DateTime<chrono::Utc>,
+    /// Signed `ShredCertificateClaims` as JWS
+    pub signature: String,
+}
+
+/// Claims of a signed `ShredCertificate`
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ShredCertificateClaims {
+    pub process_id: String,
+    pub owners: Vec<String>,
+    pub consents: Vec<String>,
+    pub key_versions: Vec<i32>,
+    pub chain_length: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_head: Option<String>,
+    pub shredded_at: i64,
+    pub clearing_house_version: String,
+}
+
+impl ShredCertificate {
+    /// Creates a new shred certificate and signs it with the key of `cert_util`
+    ///
+    /// # Errors
+    ///
+    /// Only if issues with reading the key or signing the claims occur.
+    pub fn new_signed(
+        pid: String,
+        (owners, consents): (Vec<String>, Vec<String>),
+        key_versions: Vec<i32>,
+        (chain_length, chain_head): (u64, Option<String>),
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> anyhow::Result<Self> {
+        let shredded_at = chrono::Utc::now();
+        let claims = ShredCertificateClaims {
+            process_id: pid.clone(),
+            owners: owners.clone(),
+            consents: consents.clone(),
+            key_versions: key_versions.clone(),
+            chain_length,
+            chain_head: chain_head.clone(),
+            shredded_at: shredded_at.timestamp(),
+            clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        let signature = crate::model::claims::sign_jws(&claims, cert_util)?;
+
+        Ok(Self {
+            pid,
+            owners,
+            consents,
+            key_versions,
+            chain_length,
+            chain_head,
+            shredded_at,
+            signature,
+        })
+    }
+}
+
+/// State of the erasure of a process
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeletionStatus {
+    /// Not enough owners consented to the erasure yet
+    Pending {
+        consents: Vec<String>,
+        required: usize,
+    },
+    /// The process was erased, the receipt contains the signed `TombstoneClaims`
+    Deleted { receipt: Receipt },
+    /// The data keys of the process were destroyed, the receipt contains the signed
+    /// `ShredCertificateClaims`
+    Shredded { receipt: Receipt },
+}
+
+/// Point in time from which the retention period of a document is counted
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionStart {
+    /// The document was logged
+    #[default]
+    Logged,
+    /// The process was closed, documents of open processes do not expire
+    Closed,
+}
+
+impl RetentionStart {
+    /// Representation in the database
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Logged => "logged",
+            Self::Closed => "closed",
+        }
+    }
+}
+
+impl std::str::FromStr for RetentionStart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "logged" => Ok(Self::Logged),
+            "closed" => Ok(Self::Closed),
+            _ => Err(anyhow::anyhow!("Unknown retention start '{s}'")),
+        }
+    }
+}
+
+/// Retention policy of a process or, if stored without process, of all processes without own
+/// policy
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RetentionPolicy {
+    /// Number of days the documents are kept
+    pub days: u32,
+    #[serde(default)]
+    pub start: RetentionStart,
+}
+
+impl RetentionPolicy {
+    /// Returns when a document logged at `logged` expires, if the process was closed at `closed`.
+    /// Documents logged after the process was closed expire `days` after they were logged.
+    #[must_use]
+    pub fn expires_at(
+        &self,
+        logged: chrono::DateTime<chrono::Utc>,
+        closed: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        let start = match self.start {
+            RetentionStart::Logged => logged,
+            RetentionStart::Closed => closed?.max(logged),
+        };
+        start.checked_add_signed(chrono::TimeDelta::days(i64::from(self.days)))
+    }
+}
+
+/// Retention of the documents of a process
+#[derive(Debug, Default, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Retention {
+    /// Policy of the process or the global policy, documents are kept forever without policy
+    pub policy: Option<RetentionPolicy>,
+    /// Set if the process was closed
+    pub closed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Retention {
+    /// Returns when a document logged at `logged` expires
+    #[must_use]
+    pub fn expires_at(
+        &self,
+        logged: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.policy
+            .as_ref()
+            .and_then(|p| p.expires_at(logged, self.closed_at))
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct TransactionCounter {
     pub tc: i64,
@@ -28,41 +283,357 @@ pub struct OwnerList {
     pub owners: Vec<String>,
 }
 
+/// Kind of change of the owners of a process
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnerAction {
+    /// Adds the requested owners
+    Add,
+    /// Removes the requested owners
+    Remove,
+    /// Adds the requested owners and removes the requesting owner
+    Transfer,
+}
+
+/// Change of the owners of a process, which is logged as document in the process
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OwnerChange {
+    pub action: OwnerAction,
+    /// Owners as requested
+    pub owners: Vec<String>,
+    /// Owner, who requested the change
+    pub changed_by: String,
+    /// Owners, which are not yet owners of the process
+    pub added: Vec<String>,
+    /// Owners, which are no longer owners of the process
+    pub removed: Vec<String>,
+    /// Owners of the process after the change
+    pub resulting_owners: Vec<String>,
+}
+
+impl OwnerChange {
+    /// Computes the change `action` of the `owners` requested by `changed_by` for a process with
+    /// the `current` owners
+    #[must_use]
+    pub fn new(
+        action: OwnerAction,
+        owners: Vec<String>,
+        changed_by: &str,
+        current: &[String],
+    ) -> Self {
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        for o in &owners {
+            let target = match action {
+                OwnerAction::Add | OwnerAction::Transfer if !current.contains(o) => &mut added,
+                OwnerAction::Remove if current.contains(o) => &mut removed,
+                _ => continue,
+            };
+            if !target.contains(o) {
+                target.push(o.clone());
+            }
+        }
+        if action == OwnerAction::Transfer && !owners.iter().any(|o| o == changed_by) {
+            removed.push(changed_by.to_string());
+        }
+
+        let resulting_owners = current
+            .iter()
+            .filter(|o| !removed.contains(o))
+            .chain(added.iter())
+            .cloned()
+            .collect();
+
+        Self {
+            action,
+            owners,
+            changed_by: changed_by.to_string(),
+            added,
+            removed,
+            resulting_owners,
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Receipt {
     pub data: String,
 }
 
+/// Content of the receipts issued for logged messages
+#[derive(Debug, Default, PartialEq, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptMode {
+    /// Legacy receipts, which contain the full payload
+    #[default]
+    Payload,
+    /// Receipts contain SHA-256 digests of header and payload
+    Sha256,
+    /// Receipts contain SHA-512 digests of header and payload
+    Sha512,
+}
+
+/// Digests of a logged message, which are contained in the receipt instead of the payload
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReceiptDigest {
+    /// Hash algorithm as in the IANA "Named Information Hash Algorithm Registry", e.g. `sha-256`
+    pub algorithm: String,
+    /// Hex encoded digest over the raw header as it was received, or over the header with sorted
+    /// keys and without security tokens, if the raw header is not available
+    pub header: String,
+    /// Hex encoded digest over the payload as it was received
+    pub payload: String,
+}
+
+impl ReceiptDigest {
+    /// Creates the digests of the header of `message` and the bytes `payload` of its payload for
+    /// the given `mode`. Returns `None` for `ReceiptMode::Payload`.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the header cannot be serialized.
+    pub fn new(
+        mode: ReceiptMode,
+        message: &crate::model::ids::message::IdsMessage<String>,
+        payload: &[u8],
+    ) -> serde_json::Result<Option<Self>> {
+        let header = match &message.raw_header {
+            Some(raw_header) => raw_header.as_bytes().to_vec(),
+            // serde_json::Value sorts object keys, which makes the serialization reproducible
+            None => serde_json::to_vec(&serde_json::to_value(&message.header)?)?,
+        };
+
+        Ok(match mode {
+            ReceiptMode::Payload => None,
+            ReceiptMode::Sha256 => Some(Self::digest::<sha2::Sha256>("sha-256", &header, payload)),
+            ReceiptMode::Sha512 => Some(Self::digest::<sha2::Sha512>("sha-512", &header, payload)),
+        })
+    }
+
+    fn digest<D: sha2::Digest>(algorithm: &str, header: &[u8], payload: &[u8]) -> Self {
+        Self {
+            algorithm: algorithm.to_string(),
+            header: hex::encode(D::digest(header)),
+            payload: hex::encode(D::digest(payload)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DataTransaction {
     pub timestamp: i64,
     pub process_id: String,
     pub document_id: String,
-    pub payload: String,
+    /// Logged payload, only contained in receipts of `ReceiptMode::Payload`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    /// Digests of the logged message, contained in receipts of all other `ReceiptMode`s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<ReceiptDigest>,
     pub client_id: String,
     pub clearing_house_version: String,
+    /// Hash of the document in the hash chain of the process, which is the leaf of the Merkle tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_hash: Option<String>,
 }
 
 impl DataTransaction {
     /// Signs a `DataTransaction` with a given key on the `key_path` and returns a `Receipt`.
-    /// 
+    ///
     /// # Errors
     /// Only if issues with reading the key or signing the `DataTransaction` occur.
     pub fn sign_jsonwebtoken(
         &self,
         cert_util: &ids_daps_cert::CertUtil,
     ) -> anyhow::Result<Receipt> {
-        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::PS512);
-        header.typ = None;
-        header.kid = cert_util.fingerprint().ok();
+        let data = crate::model::claims::sign_jws(self, cert_util)?;
 
-        let private_key_der = cert_util
-            .private_key_der()
-            .map_err(|e| anyhow!("Error getting private_key_der: {e}"))?;
-        let private_key = jsonwebtoken::EncodingKey::from_rsa_der(&private_key_der);
+        Ok(Receipt { data })
+    }
+}
 
-        let data = jsonwebtoken::encode(&header, self, &private_key)?;
+/// Messages to log in a single request, possibly for several processes
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct LogBatch {
+    pub messages: Vec<LogBatchEntry>,
+}
 
-        Ok(Receipt { data })
+/// Message of a `LogBatch`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LogBatchEntry {
+    pub pid: String,
+    /// Header of the message, which is kept as raw header
+    pub header: serde_json::Value,
+    /// JSON payload of the message
+    pub payload: serde_json::Value,
+}
+
+impl LogBatchEntry {
+    /// Id (`@id`) of the message, even if the header is invalid otherwise
+    #[must_use]
+    pub fn message_id(&self) -> Option<String> {
+        self.header
+            .get("@id")
+            .or_else(|| self.header.get("id"))
+            .and_then(serde_json::Value::as_str)
+            .map(ToString::to_string)
+    }
+
+    /// Message to log
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the header is not a valid `IdsHeader`.
+    pub fn to_message(&self) -> serde_json::Result<crate::model::ids::message::IdsMessage<String>> {
+        let mut message = crate::model::ids::message::IdsMessage {
+            header: serde_json::from_value(self.header.clone())?,
+            payload: None,
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: Some(self.header.to_string()),
+        };
+        message.set_payload(None, self.payload.to_string().as_bytes());
+        Ok(message)
+    }
+}
+
+/// Result of logging a message of a `LogBatch`: its receipt or the reason, why it was not logged
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BatchItemResult {
+    pub pid: String,
+    /// Id (`@id`) of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        OwnerAction, OwnerChange, ReceiptDigest, ReceiptMode, Retention, RetentionPolicy,
+        RetentionStart,
+    };
+    use crate::model::ids::message::{IdsHeader, IdsMessage};
+
+    fn owners(owners: &[&str]) -> Vec<String> {
+        owners.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn owner_change() {
+        let current = owners(&["a", "b"]);
+
+        let add = OwnerChange::new(OwnerAction::Add, owners(&["b", "c", "c"]), "a", &current);
+        assert_eq!(add.added, owners(&["c"]));
+        assert!(add.removed.is_empty());
+        assert_eq!(add.resulting_owners, owners(&["a", "b", "c"]));
+
+        let remove = OwnerChange::new(OwnerAction::Remove, owners(&["b", "c"]), "a", &current);
+        assert!(remove.added.is_empty());
+        assert_eq!(remove.removed, owners(&["b"]));
+        assert_eq!(remove.resulting_owners, owners(&["a"]));
+
+        let transfer = OwnerChange::new(OwnerAction::Transfer, owners(&["c"]), "a", &current);
+        assert_eq!(transfer.added, owners(&["c"]));
+        assert_eq!(transfer.removed, owners(&["a"]));
+        assert_eq!(transfer.resulting_owners, owners(&["b", "c"]));
+
+        assert!(OwnerChange::new(OwnerAction::Add, owners(&["a"]), "a", &current).is_empty());
+    }
+
+    #[test]
+    fn receipt_digest() {
+        let mut message = IdsMessage {
+            header: IdsHeader::default(),
+            payload: None,
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        };
+
+        assert!(
+            ReceiptDigest::new(ReceiptMode::Payload, &message, b"payload")
+                .expect("Header is serializable")
+                .is_none()
+        );
+
+        let sha256 = ReceiptDigest::new(ReceiptMode::Sha256, &message, b"payload")
+            .expect("Header is serializable")
+            .expect("Digest is created");
+        assert_eq!(sha256.algorithm, "sha-256");
+        assert_eq!(
+            sha256.payload,
+            "239f59ed55e737c77147cf55ad0c1b030b6d7ee748a7426952f9b852d5a935e5"
+        );
+        assert_eq!(sha256.header.len(), 64);
+
+        let sha512 = ReceiptDigest::new(ReceiptMode::Sha512, &message, b"payload")
+            .expect("Header is serializable")
+            .expect("Digest is created");
+        assert_eq!(sha512.algorithm, "sha-512");
+        assert_eq!(sha512.payload.len(), 128);
+
+        // Header digest is reproducible
+        assert_eq!(
+            ReceiptDigest::new(ReceiptMode::Sha256, &message, b"other")
+                .expect("Header is serializable")
+                .expect("Digest is created")
+                .header,
+            sha256.header
+        );
+
+        // The raw header is digested as it was received
+        message.raw_header = Some("{}".to_string());
+        assert_eq!(
+            ReceiptDigest::new(ReceiptMode::Sha256, &message, b"payload")
+                .expect("Header is serializable")
+                .expect("Digest is created")
+                .header,
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+    }
+
+    #[test]
+    fn retention_expiry() {
+        let logged = chrono::Utc::now();
+        let closed = logged + chrono::TimeDelta::days(5);
+        let policy = |start| RetentionPolicy { days: 10, start };
+
+        let retention = Retention {
+            policy: Some(policy(RetentionStart::Logged)),
+            closed_at: None,
+        };
+        assert_eq!(
+            retention.expires_at(logged),
+            Some(logged + chrono::TimeDelta::days(10))
+        );
+
+        // Documents of open processes do not expire
+        let mut retention = Retention {
+            policy: Some(policy(RetentionStart::Closed)),
+            closed_at: None,
+        };
+        assert_eq!(retention.expires_at(logged), None);
+        retention.closed_at = Some(closed);
+        assert_eq!(
+            retention.expires_at(logged),
+            Some(closed + chrono::TimeDelta::days(10))
+        );
+        // Documents logged after closing count from their logging time
+        let late = closed + chrono::TimeDelta::days(1);
+        assert_eq!(
+            retention.expires_at(late),
+            Some(late + chrono::TimeDelta::days(10))
+        );
+
+        assert_eq!(Retention::default().expires_at(logged), None);
     }
 }
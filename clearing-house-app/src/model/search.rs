@@ -0,0 +1,218 @@
+//! Full-text search over the payloads of logged messages.
+//!
+//! The Postgres store searches a full-text index. Stores without such an index use the simple
+//! fallback of this module: A document matches if its payload contains every term of the query,
+//! ignoring ASCII case, and is ranked by the number of occurrences of the terms.
+//!
+//! Payloads, which are encrypted at rest, and binary payloads are not searchable.
+
+use crate::model::document::Document;
+
+/// Marks the start of a matched term in a highlight
+pub const HIGHLIGHT_START: &str = "<b>";
+/// Marks the end of a matched term in a highlight
+pub const HIGHLIGHT_END: &str = "</b>";
+
+/// Number of bytes of the payload shown before and after the first match in a highlight
+const HIGHLIGHT_CONTEXT: usize = 60;
+
+/// Document, whose payload matches a search query
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchHit {
+    pub pid: String,
+    /// Document id
+    pub id: uuid::Uuid,
+    /// Id (`@id`) of the logged message
+    pub message_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Relevance of the hit, higher is better
+    pub rank: f32,
+    /// Excerpt of the payload with the matched terms enclosed in `HIGHLIGHT_START` and
+    /// `HIGHLIGHT_END`
+    pub highlight: String,
+}
+
+/// Page of search hits, ordered by rank
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SearchResult {
+    pub query: String,
+    pub page: u64,
+    pub size: u64,
+    pub hits: Vec<SearchHit>,
+}
+
+/// Terms of a search query for the fallback search, lowercase and without quotes
+#[must_use]
+pub fn terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = query
+        .split_whitespace()
+        .map(|t| t.trim_matches('"').to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// Fallback search: Returns the page `page` of the hits in `docs` for `terms`, ordered by rank
+/// and the newest first
+///
+/// # Errors
+///
+/// Throws an error if the page is out of range.
+pub fn fallback_search(
+    docs: impl IntoIterator<Item = Document<String>>,
+    terms: &[String],
+    (page, size): (u64, u64),
+) -> anyhow::Result<Vec<SearchHit>> {
+    let offset = usize::try_from((page - 1) * size)?;
+    let limit = usize::try_from(size)?;
+
+    let mut hits: Vec<_> = docs
+        .into_iter()
+        .filter_map(|doc| {
+            let payload = doc.content.payload.as_deref()?;
+            let matches = find_matches(payload, terms)?;
+            Some(SearchHit {
+                rank: f32::from(u16::try_from(matches.len()).unwrap_or(u16::MAX)),
+                highlight: highlight(payload, &matches),
+                pid: doc.pid,
+                id: doc.id,
+                message_id: doc.content.header.id,
+                created_at: doc.ts.to_utc(),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.rank
+            .total_cmp(&a.rank)
+            .then(b.created_at.cmp(&a.created_at))
+            .then(a.id.cmp(&b.id))
+    });
+
+    Ok(hits.into_iter().skip(offset).take(limit).collect())
+}
+
+/// Byte ranges of all occurrences of `terms` in `payload`, ordered and merged if they overlap.
+/// Returns `None` if a term does not occur.
+fn find_matches(payload: &str, terms: &[String]) -> Option<Vec<(usize, usize)>> {
+    // ASCII lowercase keeps the byte offsets of the payload
+    let lowercase = payload.to_ascii_lowercase();
+    let mut matches = vec![];
+    for term in terms {
+        let len = matches.len();
+        matches.extend(
+            lowercase
+                .match_indices(term.as_str())
+                .map(|(start, m)| (start, start + m.len())),
+        );
+        if matches.len() == len {
+            return None;
+        }
+    }
+
+    matches.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (start, end) in matches {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Some(merged)
+}
+
+/// Excerpt of `payload` around the first of the ordered `matches`, with the matches highlighted
+fn highlight(payload: &str, matches: &[(usize, usize)]) -> String {
+    let Some(&(first, _)) = matches.first() else {
+        return String::new();
+    };
+    let mut start = first.saturating_sub(HIGHLIGHT_CONTEXT);
+    while !payload.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (first + HIGHLIGHT_CONTEXT).min(payload.len());
+    while !payload.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut excerpt = String::new();
+    if start > 0 {
+        excerpt.push_str("...");
+    }
+    let mut position = start;
+    for &(match_start, match_end) in matches {
+        if match_start >= end {
+            break;
+        }
+        // Matches are complete, even if they extend beyond the excerpt
+        end = end.max(match_end);
+        excerpt.push_str(&payload[position..match_start]);
+        excerpt.push_str(HIGHLIGHT_START);
+        excerpt.push_str(&payload[match_start..match_end]);
+        excerpt.push_str(HIGHLIGHT_END);
+        position = match_end;
+    }
+    excerpt.push_str(&payload[position..end]);
+    if end < payload.len() {
+        excerpt.push_str("...");
+    }
+    excerpt
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fallback_search, terms};
+    use crate::model::document::Document;
+
+    fn document(payload: &str) -> Document<String> {
+        crate::test_util::document("pid", payload)
+    }
+
+    #[test]
+    fn search() {
+        let once = document("Delivery of Asset X to the consumer");
+        let twice = document(r#"{"asset": "asset-x", "status": "delivered"}"#);
+        let long = document(&format!(
+            "{} ASSET-X ünïcode {}",
+            "ä".repeat(50),
+            "o".repeat(100)
+        ));
+        let docs = vec![
+            once.clone(),
+            twice.clone(),
+            long.clone(),
+            document("unrelated"),
+        ];
+
+        let hits =
+            fallback_search(docs.clone(), &terms("asset \"deliver\""), (1, 10)).expect("Searching");
+        assert_eq!(
+            hits.iter().map(|h| h.id).collect::<Vec<_>>(),
+            vec![twice.id, once.id]
+        );
+        assert!((hits[0].rank - 3.0).abs() < f32::EPSILON);
+        assert_eq!(
+            hits[1].highlight,
+            "<b>Deliver</b>y of <b>Asset</b> X to the consumer"
+        );
+
+        // Highlights are excerpts around the first match
+        let hits =
+            fallback_search(docs.clone(), &terms("ünïcode ASSET-X"), (1, 10)).expect("Searching");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].highlight.starts_with("...ä"));
+        assert!(hits[0]
+            .highlight
+            .contains("<b>ASSET-X</b> <b>ünïcode</b> ooo"));
+        assert!(hits[0].highlight.ends_with("o..."));
+
+        assert_eq!(
+            fallback_search(docs, &terms("asset"), (2, 2))
+                .expect("Searching")
+                .len(),
+            1
+        );
+        assert!(terms(" \"\" ").is_empty());
+    }
+}
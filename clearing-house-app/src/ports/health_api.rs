@@ -0,0 +1,52 @@
+use crate::db::{DocumentStore, ProcessStore};
+use crate::model::health::{HealthReport, HealthStatus};
+use crate::services::health_service::check_database;
+use crate::AppState;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+
+/// Responds with the `report`, with `503 Service Unavailable` if a component is down
+fn respond(report: HealthReport) -> axum::response::Response {
+    let status = match report.status {
+        HealthStatus::Up | HealthStatus::Degraded => StatusCode::OK,
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, axum::Json(report)).into_response()
+}
+
+/// Liveness of the Clearing House, which does not depend on other components
+async fn live() -> axum::response::Response {
+    respond(HealthReport::new(std::collections::BTreeMap::new()))
+}
+
+/// Readiness of the Clearing House: the health of the database, the reachability of the DAPS,
+/// the certificate and the cached DAT
+async fn ready<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+) -> axum::response::Response {
+    let (database, daps_reachability) = tokio::join!(
+        check_database(state.logging_service.ping_database()),
+        state.health_service.daps_reachability()
+    );
+
+    respond(HealthReport::new(
+        [
+            ("database".to_string(), database),
+            ("daps_reachability".to_string(), daps_reachability),
+            (
+                "certificate".to_string(),
+                state.health_service.certificate(),
+            ),
+            ("dat".to_string(), state.dat_cache.health()),
+        ]
+        .into_iter()
+        .collect(),
+    ))
+}
+
+pub(crate) fn router<P: ProcessStore, D: DocumentStore>() -> axum::routing::Router<AppState<P, D>> {
+    axum::Router::new()
+        .route("/health", axum::routing::get(ready::<P, D>))
+        .route("/health/live", axum::routing::get(live))
+        .route("/health/ready", axum::routing::get(ready::<P, D>))
+}
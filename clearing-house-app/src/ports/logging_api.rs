@@ -1,37 +1,134 @@
+use crate::db::{DocumentStore, ProcessStore};
 use crate::model::claims::ExtractIdsMessage;
+use crate::model::document::DocumentFilter;
+use crate::model::ids::message::{IdsMessage, RawPayload};
 use crate::model::ids::{MessageProcessedNotificationMessage, RejectionMessage, ResultMessage};
+use crate::model::process::{DeletionStatus, LogBatch, OwnerAction, OwnerList, RetentionPolicy};
+use crate::services::document_service::DocumentServiceError;
+use crate::services::logging_service::LoggingServiceError;
 use crate::{model::claims::get_jwks, model::SortingOrder, AppState};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use crate::model::ids::message::IdsMessage;
-use crate::model::process::OwnerList;
 
-async fn log(
-    axum::extract::State(state): axum::extract::State<AppState>,
+/// Reason of the `RejectionMessage` for the error `e`. Documents of shredded processes are gone
+/// for good and conflicting message ids are the sender's fault, which is reported as is instead of
+/// as a failure of the clearing house.
+fn rejection_reason(context: &str, e: &LoggingServiceError) -> String {
+    match e {
+        LoggingServiceError::ProcessShredded { .. } | LoggingServiceError::DuplicateMessage(_) => {
+            e.to_string()
+        }
+        LoggingServiceError::DocumentServiceError(
+            inner @ DocumentServiceError::KeysShredded(_),
+        ) => inner.to_string(),
+        _ => format!("{context}: {e:?}"),
+    }
+}
+
+async fn log<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
     axum::extract::Path(pid): axum::extract::Path<String>,
     ExtractIdsMessage {
         ch_claims,
         ids_message,
-    }: ExtractIdsMessage<serde_json::Value>,
+    }: ExtractIdsMessage<RawPayload>,
 ) -> super::ApiResult {
     let correlation_id = ids_message.header.id.clone();
-    let daps_token = state.daps_client.request_dat().await
-        .map_err(|e| RejectionMessage::new(state.logging_service.issuer(), format!("DAPS error: {e:?}"), correlation_id.clone()))?;
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
 
-    let cloned_ids_message: IdsMessage<String> = IdsMessage { header: ids_message.header.clone(),
-        payload: ids_message.payload.map(|t| t.to_string()),
+    let mut cloned_ids_message: IdsMessage<String> = IdsMessage {
+        header: ids_message.header.clone(),
+        payload: None,
         payload_type: None,
+        payload_encoding: None,
+        raw_header: ids_message.raw_header,
     };
+    if let Some(payload) = ids_message.payload {
+        cloned_ids_message.set_payload(payload.media_type, &payload.data);
+    }
 
-    match state.logging_service.log(ch_claims, cloned_ids_message, pid).await {
+    match state
+        .logging_service
+        .log(ch_claims, cloned_ids_message, pid)
+        .await
+    {
         Ok(receipt) => Ok((
             StatusCode::CREATED,
-            MessageProcessedNotificationMessage::new(state.logging_service.issuer(), &daps_token, receipt, correlation_id),
+            MessageProcessedNotificationMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                receipt,
+                correlation_id,
+            ),
         )
             .into_response()),
         Err(e) => {
             error!("Error while logging: {:?}", e);
-            Err(RejectionMessage::new(state.logging_service.issuer(), format!("Error while logging: {e:?}"), correlation_id))
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while logging", &e),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn log_batch<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<LogBatch>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    let Some(batch) = ids_message.payload else {
+        return Err(RejectionMessage::new(
+            state.logging_service.issuer(),
+            "Missing batch in payload".to_string(),
+            correlation_id,
+        ));
+    };
+
+    match state.logging_service.log_batch(ch_claims, batch).await {
+        Ok(results) => {
+            // Results of messages, which were not logged, contain the reason
+            let status = if results.iter().all(|r| r.error.is_none()) {
+                StatusCode::CREATED
+            } else {
+                StatusCode::MULTI_STATUS
+            };
+            Ok((
+                status,
+                MessageProcessedNotificationMessage::new(
+                    state.logging_service.issuer(),
+                    &daps_token,
+                    results,
+                    correlation_id,
+                ),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            error!("Error while logging batch: {:?}", e);
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while logging batch", &e),
+                correlation_id,
+            ))
         }
     }
 }
@@ -41,8 +138,8 @@ struct CreateProcessResponse {
     pub pid: String,
 }
 
-async fn create_process(
-    axum::extract::State(state): axum::extract::State<AppState>,
+async fn create_process<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
     axum::extract::Path(pid): axum::extract::Path<String>,
     ExtractIdsMessage {
         ch_claims,
@@ -50,8 +147,13 @@ async fn create_process(
     }: ExtractIdsMessage<OwnerList>,
 ) -> super::ApiResult {
     let correlation_id = ids_message.header.id.clone();
-    let daps_token = state.daps_client.request_dat().await
-        .map_err(|e| RejectionMessage::new(state.logging_service.issuer(), format!("DAPS error: {e:?}"), correlation_id.clone()))?;
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
 
     match state
         .logging_service
@@ -60,12 +162,89 @@ async fn create_process(
     {
         Ok(id) => Ok((
             StatusCode::CREATED,
-            MessageProcessedNotificationMessage::new(state.logging_service.issuer(), &daps_token, CreateProcessResponse { pid: id }, correlation_id),
+            MessageProcessedNotificationMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                CreateProcessResponse { pid: id },
+                correlation_id,
+            ),
         )
             .into_response()),
         Err(e) => {
             error!("Error while creating process: {e:?}");
-            Err(RejectionMessage::new(state.logging_service.issuer(), format!("Error while creating process: {e:?}"), correlation_id))
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while creating process: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn add_owners<P: ProcessStore, D: DocumentStore>(
+    state: axum::extract::State<AppState<P, D>>,
+    pid: axum::extract::Path<String>,
+    message: ExtractIdsMessage<OwnerList>,
+) -> super::ApiResult {
+    change_owners(state, pid, message, OwnerAction::Add).await
+}
+
+async fn remove_owners<P: ProcessStore, D: DocumentStore>(
+    state: axum::extract::State<AppState<P, D>>,
+    pid: axum::extract::Path<String>,
+    message: ExtractIdsMessage<OwnerList>,
+) -> super::ApiResult {
+    change_owners(state, pid, message, OwnerAction::Remove).await
+}
+
+async fn transfer_ownership<P: ProcessStore, D: DocumentStore>(
+    state: axum::extract::State<AppState<P, D>>,
+    pid: axum::extract::Path<String>,
+    message: ExtractIdsMessage<OwnerList>,
+) -> super::ApiResult {
+    change_owners(state, pid, message, OwnerAction::Transfer).await
+}
+
+async fn change_owners<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<OwnerList>,
+    action: OwnerAction,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state
+        .logging_service
+        .change_owners(ch_claims, ids_message, pid, action)
+        .await
+    {
+        Ok(receipt) => Ok((
+            StatusCode::OK,
+            MessageProcessedNotificationMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                receipt,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while changing owners: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while changing owners: {e:?}"),
+                correlation_id,
+            ))
         }
     }
 }
@@ -73,14 +252,376 @@ async fn create_process(
 #[derive(serde::Deserialize)]
 struct QueryParams {
     pub page: Option<u64>,
+    /// Opaque cursor from `next_cursor` of the previous page, takes precedence over `page`
+    pub cursor: Option<String>,
     pub size: Option<u64>,
     pub sort: Option<SortingOrder>,
     pub date_to: Option<String>,
     pub date_from: Option<String>,
+    /// IDS message type, e.g. `ids:LogMessage`
+    #[serde(rename = "type")]
+    pub message_type: Option<String>,
+    pub issuer_connector: Option<String>,
+    pub sender_agent: Option<String>,
+    pub correlation_message: Option<String>,
+    pub transfer_contract: Option<String>,
+    pub payload_type: Option<String>,
+    /// Prefix of the message id
+    pub id_prefix: Option<String>,
+}
+
+async fn delete_process<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.delete_process(ch_claims, pid).await {
+        Ok(status) => {
+            let status_code = match status {
+                DeletionStatus::Pending { .. } => StatusCode::ACCEPTED,
+                DeletionStatus::Deleted { .. } | DeletionStatus::Shredded { .. } => StatusCode::OK,
+            };
+            Ok((
+                status_code,
+                ResultMessage::new(
+                    state.logging_service.issuer(),
+                    &daps_token,
+                    status,
+                    correlation_id,
+                ),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            error!("Error while deleting process: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while deleting process: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn shred_process<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.shred_process(ch_claims, pid).await {
+        Ok(status) => {
+            let status_code = match status {
+                DeletionStatus::Pending { .. } => StatusCode::ACCEPTED,
+                DeletionStatus::Deleted { .. } | DeletionStatus::Shredded { .. } => StatusCode::OK,
+            };
+            Ok((
+                status_code,
+                ResultMessage::new(
+                    state.logging_service.issuer(),
+                    &daps_token,
+                    status,
+                    correlation_id,
+                ),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            error!("Error while shredding process: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while shredding process", &e),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn get_tombstone<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.get_tombstone(ch_claims, pid).await {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while querying tombstone: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while querying tombstone: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn set_retention_policy<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<RetentionPolicy>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state
+        .logging_service
+        .set_retention_policy(ch_claims, ids_message, pid)
+        .await
+    {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while setting retention policy: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while setting retention policy: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn close_process<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.close_process(ch_claims, pid).await {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while closing process: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while closing process: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn rotate_data_key<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.rotate_data_key(ch_claims, pid).await {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while rotating data key: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while rotating data key", &e),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PageParams {
+    pub page: Option<u64>,
+    pub size: Option<u64>,
+}
+
+async fn list_processes<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Query(params): axum::extract::Query<PageParams>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state
+        .logging_service
+        .list_processes(ch_claims, params.page, params.size)
+        .await
+    {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while listing processes: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while listing processes: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SearchParams {
+    /// Search query, Postgres supports the syntax of `websearch_to_tsquery`
+    pub q: String,
+    pub page: Option<u64>,
+    pub size: Option<u64>,
 }
 
-async fn query_pid(
-    axum::extract::State(state): axum::extract::State<AppState>,
+async fn search_messages<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Query(params): axum::extract::Query<SearchParams>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state
+        .logging_service
+        .search_messages(ch_claims, params.q, params.page, params.size)
+        .await
+    {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while searching messages: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while searching messages", &e),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn query_pid<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
     axum::extract::Query(params): axum::extract::Query<QueryParams>,
     axum::extract::Path(pid): axum::extract::Path<String>,
     ExtractIdsMessage {
@@ -89,80 +630,282 @@ async fn query_pid(
     }: ExtractIdsMessage<()>,
 ) -> super::ApiResult {
     let correlation_id = ids_message.header.id.clone();
-    let daps_token = state.daps_client.request_dat().await
-        .map_err(|e| RejectionMessage::new(state.logging_service.issuer(), format!("DAPS error: {e:?}"), correlation_id.clone()))?;
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
 
+    let filter = DocumentFilter {
+        message_type: params.message_type,
+        issuer_connector: params.issuer_connector,
+        sender_agent: params.sender_agent,
+        correlation_message: params.correlation_message,
+        transfer_contract: params.transfer_contract,
+        payload_type: params.payload_type,
+        id_prefix: params.id_prefix,
+        transfer_contract_indexes: vec![],
+    };
     match state
         .logging_service
         .query_pid(
             ch_claims,
-            params.page,
-            params.size,
+            (params.page, params.size, params.cursor),
             params.sort,
             (params.date_to, params.date_from),
             pid,
+            filter,
         )
         .await
     {
         Ok(result) => Ok((
             StatusCode::OK,
-            ResultMessage::new(state.logging_service.issuer(), &daps_token, result, correlation_id),
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
         )
             .into_response()),
         Err(e) => {
             error!("Error while querying: {e:?}");
-            Err(RejectionMessage::new(state.logging_service.issuer(), format!("Error while querying: {e:?}"), correlation_id))
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while querying", &e),
+                correlation_id,
+            ))
         }
     }
 }
 
-async fn query_id(
-    axum::extract::State(state): axum::extract::State<AppState>,
-    axum::extract::Path(pid): axum::extract::Path<String>,
-    axum::extract::Path(id): axum::extract::Path<String>,
+#[derive(serde::Deserialize)]
+struct QueryIdParams {
+    /// Whether to return the header of the message as it was received
+    #[serde(default)]
+    pub raw_header: bool,
+}
+
+async fn query_id<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path((pid, id)): axum::extract::Path<(String, String)>,
+    axum::extract::Query(params): axum::extract::Query<QueryIdParams>,
     ExtractIdsMessage {
         ch_claims,
         ids_message,
     }: ExtractIdsMessage<()>,
 ) -> super::ApiResult {
     let correlation_id = ids_message.header.id.clone();
-    let daps_token = state.daps_client.request_dat().await
-        .map_err(|e| RejectionMessage::new(state.logging_service.issuer(), format!("DAPS error: {e:?}"), correlation_id.clone()))?;
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
 
     match state
         .logging_service
-        .query_id(ch_claims, pid, id, ids_message)
+        .query_id(ch_claims, pid, id, ids_message, params.raw_header)
         .await
     {
         Ok(result) => Ok((
             StatusCode::OK,
-            ResultMessage::new(state.logging_service.issuer(), &daps_token, result, correlation_id),
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
         )
             .into_response()),
         Err(e) => {
             error!("Error while querying: {:?}", e);
-            Err(RejectionMessage::new(state.logging_service.issuer(), format!("Error while querying: {e:?}"), correlation_id))
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                rejection_reason("Error while querying", &e),
+                correlation_id,
+            ))
         }
     }
 }
 
-async fn get_public_sign_key(
-    axum::extract::State(state): axum::extract::State<AppState>,
+async fn verify_chain<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path(pid): axum::extract::Path<String>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state.logging_service.verify_chain(ch_claims, pid).await {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while verifying: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while verifying: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn inclusion_proof<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
+    axum::extract::Path((pid, id)): axum::extract::Path<(String, String)>,
+    ExtractIdsMessage {
+        ch_claims,
+        ids_message,
+    }: ExtractIdsMessage<()>,
+) -> super::ApiResult {
+    let correlation_id = ids_message.header.id.clone();
+    let daps_token = state.dat_cache.get().await.map_err(|e| {
+        RejectionMessage::new(
+            state.logging_service.issuer(),
+            format!("DAPS error: {e:?}"),
+            correlation_id.clone(),
+        )
+    })?;
+
+    match state
+        .logging_service
+        .inclusion_proof(ch_claims, pid, id)
+        .await
+    {
+        Ok(result) => Ok((
+            StatusCode::OK,
+            ResultMessage::new(
+                state.logging_service.issuer(),
+                &daps_token,
+                result,
+                correlation_id,
+            ),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Error while creating proof: {e:?}");
+            Err(RejectionMessage::new(
+                state.logging_service.issuer(),
+                format!("Error while creating proof: {e:?}"),
+                correlation_id,
+            ))
+        }
+    }
+}
+
+async fn get_public_sign_key<P: ProcessStore, D: DocumentStore>(
+    axum::extract::State(state): axum::extract::State<AppState<P, D>>,
 ) -> super::ApiResult {
     match get_jwks(&state.cert_util) {
         Some(jwks) => Ok((StatusCode::OK, axum::Json(jwks)).into_response()),
-        None => Err(RejectionMessage::new(state.logging_service.issuer(), "Error reading signing key".to_string(), None)),
+        None => Err(RejectionMessage::new(
+            state.logging_service.issuer(),
+            "Error reading signing key".to_string(),
+            None,
+        )),
     }
 }
 
-pub(crate) fn router() -> axum::routing::Router<AppState> {
+pub(crate) fn router<P: ProcessStore, D: DocumentStore>(
+    limits: super::BodyLimits,
+) -> axum::routing::Router<AppState<P, D>> {
     axum::Router::new()
-        .route("/messages/log/{pid}", axum::routing::post(log))
-        .route("/process/{pid}", axum::routing::post(create_process))
-        .route("/messages/query/{pid}", axum::routing::post(query_pid))
-        .route("/messages/query/{pid}/{id}", axum::routing::post(query_id))
+        .route(
+            "/messages/log/{pid}",
+            axum::routing::post(log::<P, D>)
+                .layer(axum::extract::DefaultBodyLimit::max(limits.log)),
+        )
+        .route(
+            "/messages/log",
+            axum::routing::post(log_batch::<P, D>)
+                .layer(axum::extract::DefaultBodyLimit::max(limits.log)),
+        )
+        .route("/processes", axum::routing::post(list_processes::<P, D>))
+        .route(
+            "/process/{pid}",
+            axum::routing::post(create_process::<P, D>),
+        )
+        .route(
+            "/process/{pid}/owners/add",
+            axum::routing::post(add_owners::<P, D>),
+        )
+        .route(
+            "/process/{pid}/owners/remove",
+            axum::routing::post(remove_owners::<P, D>),
+        )
+        .route(
+            "/process/{pid}/owners/transfer",
+            axum::routing::post(transfer_ownership::<P, D>),
+        )
+        .route(
+            "/process/{pid}/delete",
+            axum::routing::post(delete_process::<P, D>),
+        )
+        .route(
+            "/process/{pid}/tombstone",
+            axum::routing::post(get_tombstone::<P, D>),
+        )
+        .route(
+            "/process/{pid}/shred",
+            axum::routing::post(shred_process::<P, D>),
+        )
+        .route(
+            "/process/{pid}/retention",
+            axum::routing::post(set_retention_policy::<P, D>),
+        )
+        .route(
+            "/process/{pid}/close",
+            axum::routing::post(close_process::<P, D>),
+        )
+        .route(
+            "/process/{pid}/keys/rotate",
+            axum::routing::post(rotate_data_key::<P, D>),
+        )
+        .route(
+            "/messages/search",
+            axum::routing::post(search_messages::<P, D>),
+        )
+        .route(
+            "/messages/query/{pid}",
+            axum::routing::post(query_pid::<P, D>),
+        )
+        .route(
+            "/messages/query/{pid}/{id}",
+            axum::routing::post(query_id::<P, D>),
+        )
+        .route(
+            "/messages/query/{pid}/{id}/proof",
+            axum::routing::post(inclusion_proof::<P, D>),
+        )
+        .route(
+            "/messages/verify/{pid}",
+            axum::routing::post(verify_chain::<P, D>),
+        )
         .route(
             "/.well-known/jwks.json",
-            axum::routing::get(get_public_sign_key),
+            axum::routing::get(get_public_sign_key::<P, D>),
         )
+        // The route specific limit of the log route takes precedence
+        .layer(axum::extract::DefaultBodyLimit::max(limits.other))
 }
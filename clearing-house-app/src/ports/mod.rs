@@ -4,14 +4,29 @@
 //! services. In this case, the logging service implements REST-API endpoints to provide access to
 //! the logging service.
 
-use crate::AppState;
+use crate::db::{DocumentStore, ProcessStore};
 use crate::model::ids::RejectionMessage;
+use crate::AppState;
 
+pub(crate) mod health_api;
 pub(crate) mod logging_api;
 
+/// Maximum sizes of request bodies in bytes
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BodyLimits {
+    /// Limit of requests logging a message
+    pub(crate) log: usize,
+    /// Limit of all other requests
+    pub(crate) other: usize,
+}
+
 /// Router for the logging service
-pub(crate) fn router() -> axum::routing::Router<AppState> {
-    axum::Router::new().merge(logging_api::router())
+pub(crate) fn router<P: ProcessStore, D: DocumentStore>(
+    limits: BodyLimits,
+) -> axum::routing::Router<AppState<P, D>> {
+    axum::Router::new()
+        .merge(logging_api::router(limits))
+        .merge(health_api::router())
 }
 
 /// Result type alias for the API
@@ -0,0 +1,270 @@
+//! Cache of the Clearing House's own DAT (Dynamic Attribute Token), which is sent with every
+//! response. A DAT is reused until shortly before it expires and refreshed in the background, so
+//! requests neither wait for the DAPS nor fail while the DAPS is briefly unavailable.
+
+use crate::model::claims::token_expiry;
+use crate::model::constants::DAPS_RETRY_INTERVAL;
+use crate::model::health::{ComponentHealth, HealthStatus};
+use std::sync::Arc;
+
+/// Issuer of DATs for the Clearing House
+pub(crate) trait DatSource: Send + Sync + 'static {
+    fn request_dat(
+        &self,
+    ) -> impl std::future::Future<Output = Result<String, ids_daps_client::DapsError>> + Send;
+}
+
+impl DatSource for ids_daps_client::ReqwestDapsClient {
+    fn request_dat(
+        &self,
+    ) -> impl std::future::Future<Output = Result<String, ids_daps_client::DapsError>> + Send {
+        ids_daps_client::ReqwestDapsClient::request_dat(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedDat {
+    token: String,
+    /// Expiry of the token, tokens without readable expiry are expired right away
+    expires_at: chrono::DateTime<chrono::Utc>,
+    refreshed_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    dat: Option<CachedDat>,
+    /// Time and error of the last refresh, if it failed
+    last_error: Option<(chrono::DateTime<chrono::Utc>, String)>,
+}
+
+pub(crate) struct DatCache<S = ids_daps_client::ReqwestDapsClient> {
+    source: Arc<S>,
+    /// Time before the expiry of a DAT, from which it is refreshed
+    refresh_margin: chrono::Duration,
+    state: std::sync::RwLock<CacheState>,
+    /// Serializes refreshes, so concurrent requests wait for a single request to the DAPS
+    refresh: tokio::sync::Mutex<()>,
+}
+
+impl<S: DatSource> DatCache<S> {
+    /// Creates an empty cache, which refreshes DATs `refresh_margin` seconds before they expire
+    pub(crate) fn new(source: Arc<S>, refresh_margin: u64) -> Self {
+        Self {
+            source,
+            refresh_margin: chrono::Duration::seconds(
+                i64::try_from(refresh_margin).unwrap_or(i64::MAX),
+            ),
+            state: std::sync::RwLock::default(),
+            refresh: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Returns the cached DAT, which is refreshed first if it is about to expire. If the refresh
+    /// fails, the cached DAT is returned as long as it is valid.
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if no valid DAT is cached and the DAPS does not issue a new one.
+    pub(crate) async fn get(&self) -> Result<String, ids_daps_client::DapsError> {
+        if let Some(token) = self.usable(chrono::Utc::now()) {
+            return Ok(token);
+        }
+
+        let _refresh = self.refresh.lock().await;
+        // Another request might have refreshed the DAT in the meantime
+        if let Some(token) = self.usable(chrono::Utc::now()) {
+            return Ok(token);
+        }
+        let result = self.refresh().await;
+        match result {
+            Ok(token) => Ok(token),
+            Err(e) => match self.read().dat.clone() {
+                Some(dat) if chrono::Utc::now() < dat.expires_at => {
+                    warn!("Refreshing DAT failed, using cached DAT: {e}");
+                    Ok(dat.token)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Periodically refreshes the DAT shortly before it expires, starting right away
+    pub(crate) fn spawn_refresher(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                let refresh = self.refresh.lock().await;
+                let result = self.refresh().await;
+                drop(refresh);
+                if let Err(e) = result {
+                    error!("Error while refreshing DAT: {e}");
+                }
+
+                let now = chrono::Utc::now();
+                let next = self
+                    .read()
+                    .dat
+                    .as_ref()
+                    .map_or(now, |dat| dat.expires_at - self.refresh_margin)
+                    .max(now + chrono::Duration::seconds(DAPS_RETRY_INTERVAL));
+                tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+            }
+        });
+    }
+
+    /// Health of the cache: up with a valid DAT, degraded if the DAT is valid but its last refresh
+    /// failed, down without a valid DAT
+    pub(crate) fn health(&self) -> ComponentHealth {
+        let now = chrono::Utc::now();
+        let state = self.read();
+        let valid = state.dat.as_ref().is_some_and(|dat| now < dat.expires_at);
+        let status = match (valid, &state.last_error) {
+            (false, _) => HealthStatus::Down,
+            (true, Some(_)) => HealthStatus::Degraded,
+            (true, None) => HealthStatus::Up,
+        };
+
+        ComponentHealth {
+            status,
+            details: serde_json::json!({
+                "cached": valid,
+                "expires_at": state.dat.as_ref().map(|dat| dat.expires_at),
+                "refreshed_at": state.dat.as_ref().map(|dat| dat.refreshed_at),
+                "last_error": state.last_error.as_ref().map(|(_, e)| e),
+                "last_error_at": state.last_error.as_ref().map(|(at, _)| at),
+            }),
+        }
+    }
+
+    /// Returns the cached DAT, if it is not about to expire or, after a recently failed refresh,
+    /// still valid
+    fn usable(&self, now: chrono::DateTime<chrono::Utc>) -> Option<String> {
+        let state = self.read();
+        let dat = state.dat.as_ref()?;
+        let retry_later = state.last_error.as_ref().is_some_and(|(at, _)| {
+            now < *at + chrono::Duration::seconds(DAPS_RETRY_INTERVAL) && now < dat.expires_at
+        });
+        (now < dat.expires_at - self.refresh_margin || retry_later).then(|| dat.token.clone())
+    }
+
+    /// Requests a new DAT from the DAPS, the caller holds the refresh lock
+    async fn refresh(&self) -> Result<String, ids_daps_client::DapsError> {
+        debug!("Requesting DAT");
+        let result = self.source.request_dat().await;
+
+        let now = chrono::Utc::now();
+        let mut state = self
+            .state
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match &result {
+            Ok(token) => {
+                state.dat = Some(CachedDat {
+                    token: token.clone(),
+                    expires_at: token_expiry(token).unwrap_or(now),
+                    refreshed_at: now,
+                });
+                state.last_error = None;
+            }
+            Err(e) => state.last_error = Some((now, e.to_string())),
+        }
+        result
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, CacheState> {
+        self.state
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DatCache, DatSource};
+    use crate::model::health::HealthStatus;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Issues DATs valid for `lifetime` seconds, until it is switched off
+    struct FakeDaps {
+        lifetime: i64,
+        available: std::sync::atomic::AtomicBool,
+        requests: AtomicUsize,
+    }
+
+    impl FakeDaps {
+        fn new(lifetime: i64) -> Arc<Self> {
+            Arc::new(Self {
+                lifetime,
+                available: true.into(),
+                requests: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    impl DatSource for FakeDaps {
+        async fn request_dat(&self) -> Result<String, ids_daps_client::DapsError> {
+            let request = self.requests.fetch_add(1, Ordering::SeqCst);
+            if !self.available.load(Ordering::SeqCst) {
+                return Err(ids_daps_client::DapsError::InvalidToken);
+            }
+
+            let claims = serde_json::json!({
+                "sub": format!("dat {request}"),
+                "exp": chrono::Utc::now().timestamp() + self.lifetime,
+            });
+            Ok(jsonwebtoken::encode(
+                &jsonwebtoken::Header::default(),
+                &claims,
+                &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+            )
+            .expect("Encoding DAT"))
+        }
+    }
+
+    #[tokio::test]
+    async fn cached_dat() {
+        let daps = FakeDaps::new(3600);
+        let cache = DatCache::new(daps.clone(), 60);
+        assert_eq!(cache.health().status, HealthStatus::Down);
+
+        let dat = cache.get().await.expect("Requesting DAT");
+        assert_eq!(cache.get().await.expect("Requesting DAT"), dat);
+        assert_eq!(daps.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.health().status, HealthStatus::Up);
+
+        // The valid DAT is used while the DAPS is unavailable
+        daps.available.store(false, Ordering::SeqCst);
+        let cache = DatCache::new(daps.clone(), 3600);
+        assert!(cache.get().await.is_err());
+        daps.available.store(true, Ordering::SeqCst);
+        let dat = cache.get().await.expect("Requesting DAT");
+        daps.available.store(false, Ordering::SeqCst);
+        assert_eq!(cache.get().await.expect("Using cached DAT"), dat);
+        let health = cache.health();
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert!(health.details["last_error"].is_string());
+
+        // Failed refreshes are not retried by every request
+        let requests = daps.requests.load(Ordering::SeqCst);
+        assert_eq!(cache.get().await.expect("Using cached DAT"), dat);
+        assert_eq!(daps.requests.load(Ordering::SeqCst), requests);
+    }
+
+    #[tokio::test]
+    async fn expiring_dat() {
+        // DATs within the refresh margin are refreshed on use
+        let daps = FakeDaps::new(30);
+        let cache = DatCache::new(daps.clone(), 60);
+        let dat = cache.get().await.expect("Requesting DAT");
+        assert_ne!(cache.get().await.expect("Requesting DAT"), dat);
+        assert_eq!(daps.requests.load(Ordering::SeqCst), 2);
+
+        // Expired DATs are not used
+        let daps = FakeDaps::new(-1);
+        let cache = DatCache::new(daps.clone(), 0);
+        cache.get().await.expect("Requesting DAT");
+        daps.available.store(false, Ordering::SeqCst);
+        assert!(cache.get().await.is_err());
+        assert_eq!(cache.health().status, HealthStatus::Down);
+    }
+}
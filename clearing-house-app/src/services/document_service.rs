@@ -1,10 +1,22 @@
+use crate::db::object_store::{LocalObjectStore, ObjectStore};
 use crate::db::DocumentStore;
+use crate::model::archive::{self, ArchiveManifest};
 use crate::model::claims::ChClaims;
 use crate::model::constants::{DEFAULT_NUM_RESPONSE_ENTRIES, MAX_NUM_RESPONSE_ENTRIES};
-use crate::model::document::Document;
+use crate::model::crypto::{DataKey, DataKeyInfo, KeyRing, WrappedDataKey};
+use crate::model::document::{Document, DocumentCursor, DocumentFilter, GENESIS_HASH};
+use crate::model::merkle::{self, MerkleRoot};
+use crate::model::search::SearchResult;
 use crate::model::{parse_date, validate_and_sanitize_dates, SortingOrder};
-use crate::services::{DocumentReceipt, QueryResult};
+use crate::services::{
+    Appended, ChainBreak, ChainVerification, DocumentReceipt, GlobalInclusionProof, InclusionProof,
+    QueryResult,
+};
 use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Number of attempts to append a document to the hash chain of a process, before giving up
+const MAX_CHAIN_ATTEMPTS: usize = 3;
 
 /// Error type for `DocumentService`
 #[derive(thiserror::Error, Debug)]
@@ -20,17 +32,48 @@ pub enum DocumentServiceError {
     },
     #[error("Invalid dates in query!")]
     InvalidDates,
+    #[error("Invalid cursor in query!")]
+    InvalidCursor,
+    #[error("Search query is empty!")]
+    EmptySearchQuery,
     #[error("Document not found!")]
     NotFound,
+    #[error("Error while hashing document: {0}")]
+    HashingError(#[from] serde_json::Error),
+    #[error("Document is not yet included in a published Merkle root!")]
+    NotYetPublished,
+    #[error("Integrity violation: {0}")]
+    IntegrityError(String),
+    #[error("Error while signing: {0}")]
+    SigningError(String),
+    #[error("Error while accessing the archive: {0}")]
+    ArchiveError(String),
+    #[error("Encryption at rest is not enabled!")]
+    EncryptionDisabled,
+    #[error("Error during encryption: {0}")]
+    EncryptionError(String),
+    #[error("Process '{0}' contains unencrypted documents, which cannot be shredded!")]
+    UnencryptedDocuments(String),
+    #[error(
+        "Data keys of process '{0}' were shredded, its documents cannot be decrypted anymore!"
+    )]
+    KeysShredded(String),
 }
 
 impl axum::response::IntoResponse for DocumentServiceError {
     fn into_response(self) -> axum::response::Response {
         use axum::http::StatusCode;
         match self {
-            Self::DocumentAlreadyExists | Self::MissingPayload | Self::InvalidDates => {
+            Self::DocumentAlreadyExists
+            | Self::MissingPayload
+            | Self::InvalidDates
+            | Self::InvalidCursor
+            | Self::EmptySearchQuery
+            | Self::EncryptionDisabled
+            | Self::UnencryptedDocuments(_) => {
                 (StatusCode::BAD_REQUEST, self.to_string()).into_response()
             }
+            Self::KeysShredded(_) => (StatusCode::GONE, self.to_string()).into_response(),
             Self::DatabaseError {
                 source,
                 description,
@@ -39,52 +82,994 @@ impl axum::response::IntoResponse for DocumentServiceError {
                 format!("{description}: {source}"),
             )
                 .into_response(),
-            Self::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            Self::NotFound | Self::NotYetPublished => {
+                (StatusCode::NOT_FOUND, self.to_string()).into_response()
+            }
+            Self::HashingError(_)
+            | Self::IntegrityError(_)
+            | Self::SigningError(_)
+            | Self::ArchiveError(_)
+            | Self::EncryptionError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct DocumentService<T> {
-    db: T,
-}
+/// Cold archive of documents and the key, which verifies the signed manifests of its bundles
+#[derive(Clone)]
+pub struct Archive<O> {
+    store: O,
+    manifest_key: jsonwebtoken::DecodingKey,
+}
+
+impl<O: ObjectStore> Archive<O> {
+    /// Archive in `store`, whose manifests are signed with the key of `cert_util`
+    ///
+    /// # Errors
+    ///
+    /// Only if issues with reading the key of `cert_util` occur.
+    pub(crate) fn new(store: O, cert_util: &ids_daps_cert::CertUtil) -> anyhow::Result<Self> {
+        Ok(Self {
+            store,
+            manifest_key: crate::model::claims::verifying_key(cert_util)?,
+        })
+    }
+}
+
+impl<O: std::fmt::Debug> std::fmt::Debug for Archive<O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Archive")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DocumentService<T, O = LocalObjectStore> {
+    db: T,
+    /// Cold archive for old documents, documents are not archived if `None`
+    archive: Option<Archive<O>>,
+    /// Master keys for encryption at rest, documents are stored unencrypted if `None`
+    keys: Option<KeyRing>,
+    /// Number of chained documents of processes, whose hash chain was found broken when
+    /// publishing Merkle roots. They are not checked again until new documents are logged.
+    broken_chains: Arc<std::sync::Mutex<std::collections::HashMap<String, u64>>>,
+}
+
+impl<T: DocumentStore, O: ObjectStore> DocumentService<T, O> {
+    pub fn new(db: T, archive: Option<Archive<O>>, keys: Option<KeyRing>) -> Self {
+        Self {
+            db,
+            archive,
+            keys,
+            broken_chains: Arc::default(),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn create_enc_document(
+        &self,
+        ch_claims: ChClaims,
+        doc: Document<String>,
+    ) -> Result<DocumentReceipt, DocumentServiceError> {
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        match self.append_document(doc, false).await? {
+            Appended::Created(receipt) => Ok(receipt),
+            Appended::Duplicate(_) => Err(DocumentServiceError::DocumentAlreadyExists),
+        }
+    }
+
+    /// Stores `doc` like `create_enc_document`, unless its message id (`@id`) was logged before in
+    /// the process. Then the document logged before is returned instead.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn create_enc_document_once(
+        &self,
+        ch_claims: ChClaims,
+        doc: Document<String>,
+    ) -> Result<Appended, DocumentServiceError> {
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        self.append_document(doc, true).await
+    }
+
+    /// Appends `doc` to the hash chain of its process. If `once` is set, documents with a message
+    /// id logged before in the process are not stored.
+    async fn append_document(
+        &self,
+        mut doc: Document<String>,
+        once: bool,
+    ) -> Result<Appended, DocumentServiceError> {
+        use chrono::SubsecRound;
+
+        // data validation
+        if doc.content.payload.is_none() {
+            return Err(DocumentServiceError::MissingPayload);
+        }
+
+        // check if doc id already exists
+        if let Ok(true) = self.db.exists_document(&doc.id).await {
+            warn!("Document exists already!");
+            return Err(DocumentServiceError::DocumentAlreadyExists);
+        }
+
+        // The database stores timestamps with microsecond precision, which is part of the hash
+        doc.ts = doc.ts.trunc_subsecs(6);
+
+        let data_key = self.get_current_data_key(&doc.pid).await?;
+
+        let mut attempt = 1;
+        loop {
+            // A concurrent retry of the same message fails to append to the same chain head, so
+            // it finds the first one here on its next attempt
+            if once {
+                if let Some(logged) = self.find_logged(&doc).await? {
+                    return Ok(Appended::Duplicate(Box::new(logged)));
+                }
+            }
+
+            // append document to the hash chain of the process
+            let previous_hash = self.get_chain_head(&doc.pid).await?;
+            doc.link_to(&previous_hash)?;
+
+            // The hash covers the plaintext, so receipts stay verifiable for the sender
+            let mut stored = doc.clone();
+            if let Some(key) = &data_key {
+                stored.encrypt(key).map_err(encryption_error)?;
+            }
+
+            trace!("storing document ....");
+            // store document
+            match self.db.add_document(stored).await {
+                Ok(_b) => {
+                    // prepare the success result message
+                    return Ok(Appended::Created(DocumentReceipt::new(
+                        doc.ts,
+                        &doc.pid,
+                        &doc.id.to_string(),
+                        doc.hash.as_deref(),
+                    )));
+                }
+                Err(e) => {
+                    // Another document might have been appended to the chain in the meantime
+                    if attempt < MAX_CHAIN_ATTEMPTS
+                        && self.get_chain_head(&doc.pid).await? != previous_hash
+                    {
+                        warn!("Chain head of pid '{}' moved, retrying...", &doc.pid);
+                        attempt += 1;
+                        continue;
+                    }
+
+                    error!("Error while adding: {:?}", e);
+                    return Err(DocumentServiceError::DatabaseError {
+                        source: e.into(),
+                        description: "Error while adding document".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Appends `docs` to the hash chains of their processes and stores them in a single
+    /// transaction. Documents of the same process are chained in the given order. Documents with a
+    /// message id (`@id`) logged before in the process, or earlier in `docs`, are not stored.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn create_enc_documents(
+        &self,
+        ch_claims: ChClaims,
+        mut docs: Vec<Document<String>>,
+    ) -> Result<Vec<Appended>, DocumentServiceError> {
+        use chrono::SubsecRound;
+
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        // data validation
+        if docs.iter().any(|doc| doc.content.payload.is_none()) {
+            return Err(DocumentServiceError::MissingPayload);
+        }
+
+        let mut data_keys = std::collections::HashMap::new();
+        for doc in &mut docs {
+            // The database stores timestamps with microsecond precision, which is part of the hash
+            doc.ts = doc.ts.trunc_subsecs(6);
+            if !data_keys.contains_key(&doc.pid) {
+                let data_key = self.get_current_data_key(&doc.pid).await?;
+                data_keys.insert(doc.pid.clone(), data_key);
+            }
+        }
+        let repeated = repeated_messages(&docs);
+
+        let mut attempt = 1;
+        loop {
+            // append the documents to the hash chains of their processes
+            let mut previous_hashes = std::collections::HashMap::new();
+            for pid in data_keys.keys() {
+                previous_hashes.insert(pid.clone(), self.get_chain_head(pid).await?);
+            }
+            let mut heads = previous_hashes.clone();
+            let mut logged = Vec::with_capacity(docs.len());
+            let mut stored = Vec::with_capacity(docs.len());
+            for (doc, repeated) in docs.iter_mut().zip(&repeated) {
+                if repeated.is_some() {
+                    logged.push(None);
+                    continue;
+                }
+                if let Some(found) = self.find_logged(doc).await? {
+                    logged.push(Some(found));
+                    continue;
+                }
+                logged.push(None);
+
+                let head = heads.entry(doc.pid.clone()).or_default();
+                doc.link_to(head)?;
+                if let Some(hash) = &doc.hash {
+                    head.clone_from(hash);
+                }
+
+                // The hash covers the plaintext, so receipts stay verifiable for the sender
+                let mut encrypted = doc.clone();
+                if let Some(Some(key)) = data_keys.get(&doc.pid) {
+                    encrypted.encrypt(key).map_err(encryption_error)?;
+                }
+                stored.push(encrypted);
+            }
+
+            trace!("storing {} documents ....", stored.len());
+            let result = self.db.add_documents(stored).await;
+            match result {
+                Ok(()) => {
+                    // Repeated messages are duplicates of the first message with their id
+                    let first = |i: usize| match &logged[i] {
+                        Some(found) => Appended::Duplicate(Box::new(found.clone())),
+                        None => Appended::Duplicate(Box::new(docs[i].clone())),
+                    };
+                    return Ok(docs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, doc)| match (repeated[i], &logged[i]) {
+                            (Some(j), _) => first(j),
+                            (None, Some(found)) => Appended::Duplicate(Box::new(found.clone())),
+                            (None, None) => Appended::Created(DocumentReceipt::new(
+                                doc.ts,
+                                &doc.pid,
+                                &doc.id.to_string(),
+                                doc.hash.as_deref(),
+                            )),
+                        })
+                        .collect());
+                }
+                Err(e) => {
+                    // Other documents might have been appended to the chains in the meantime
+                    let mut moved = false;
+                    for (pid, previous_hash) in &previous_hashes {
+                        moved |= self.get_chain_head(pid).await? != *previous_hash;
+                    }
+                    if attempt < MAX_CHAIN_ATTEMPTS && moved {
+                        warn!("Chain heads moved, retrying...");
+                        attempt += 1;
+                        continue;
+                    }
+
+                    error!("Error while adding: {:?}", e);
+                    return Err(DocumentServiceError::DatabaseError {
+                        source: e.into(),
+                        description: "Error while adding documents".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the decrypted document, which was logged before `doc` in its process with the same
+    /// message id (`@id`)
+    async fn find_logged(
+        &self,
+        doc: &Document<String>,
+    ) -> Result<Option<Document<String>>, DocumentServiceError> {
+        let Some(message_id) = &doc.content.header.id else {
+            return Ok(None);
+        };
+        let Some(mut logged) = self
+            .db
+            .get_document_by_message_id(&doc.pid, message_id)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving document"))?
+        else {
+            return Ok(None);
+        };
+
+        self.decrypt_documents(&doc.pid, std::slice::from_mut(&mut logged))
+            .await?;
+        Ok(Some(logged))
+    }
+
+    /// Walks the hash chain of the process `pid` and reports the first broken link
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn verify_chain(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<ChainVerification, DocumentServiceError> {
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        debug!("Verifying hash chain of pid '{pid}'...");
+
+        let docs = self.get_chain(&pid).await?;
+
+        Ok(verify_documents(&docs))
+    }
+
+    /// Publishes signed Merkle roots for all processes with new documents and a global root over
+    /// the latest roots of all processes
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn publish_merkle_roots(
+        &self,
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> Result<(), DocumentServiceError> {
+        let pids = self
+            .db
+            .get_unpublished_pids()
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving unpublished processes"))?;
+
+        let mut published = false;
+        for (pid, chained) in pids {
+            if self.broken_chains().get(&pid) == Some(&chained) {
+                trace!("Hash chain of pid '{pid}' is broken, skipping Merkle root");
+                continue;
+            }
+
+            let links = self.get_chain_links(&pid).await?;
+            let leaves = link_leaves(&links);
+
+            // A broken chain does not grow anymore
+            let latest = self
+                .db
+                .get_latest_merkle_root(Some(&pid), None)
+                .await
+                .map_err(|e| db_error(e, "Error while retrieving Merkle root"))?;
+            if leaves.is_empty() || latest.is_some_and(|r| r.tree_size == leaves.len() as u64) {
+                if self.broken_chains().insert(pid.clone(), chained).is_none() {
+                    warn!("Hash chain of pid '{pid}' is broken after {} documents, skipping Merkle roots", leaves.len());
+                } else {
+                    debug!("Hash chain of pid '{pid}' is still broken, skipping Merkle root");
+                }
+                continue;
+            }
+            self.broken_chains().remove(&pid);
+
+            debug!(
+                "Publishing Merkle root of pid '{pid}' over {} documents",
+                leaves.len()
+            );
+            self.add_merkle_root(Some(pid), &leaves, None, cert_util)
+                .await?;
+            published = true;
+        }
+
+        let latest_global = self
+            .db
+            .get_latest_merkle_root(None, None)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving Merkle root"))?;
+        if published || latest_global.is_none() {
+            let roots = self
+                .db
+                .get_latest_process_roots(None)
+                .await
+                .map_err(|e| db_error(e, "Error while retrieving Merkle roots"))?;
+            if roots.is_empty() {
+                return Ok(());
+            }
+
+            debug!(
+                "Publishing global Merkle root over {} processes",
+                roots.len()
+            );
+            let covers_up_to = roots.iter().filter_map(|r| r.id).max();
+            let leaves = global_leaves(roots);
+            self.add_merkle_root(None, &leaves, covers_up_to, cert_util)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn broken_chains(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<String, u64>> {
+        self.broken_chains
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    async fn add_merkle_root<L: AsRef<[u8]>>(
+        &self,
+        pid: Option<String>,
+        leaves: &[L],
+        covers_up_to: Option<i32>,
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> Result<i32, DocumentServiceError> {
+        let root = MerkleRoot::new_signed(
+            pid,
+            leaves.len() as u64,
+            merkle::root(leaves),
+            covers_up_to,
+            cert_util,
+        )
+        .map_err(|e| DocumentServiceError::SigningError(e.to_string()))?;
+
+        self.db
+            .add_merkle_root(&root)
+            .await
+            .map_err(|e| db_error(e, "Error while storing Merkle root"))
+    }
+
+    /// Creates a proof that the document `id` is included in the latest published Merkle root of
+    /// the process `pid` and, if available, that this root is included in the global root
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn get_inclusion_proof(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+        id: String,
+    ) -> Result<InclusionProof, DocumentServiceError> {
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        debug!("Creating inclusion proof for document '{id}' of pid '{pid}'...");
+
+        let docs = self.get_chain(&pid).await?;
+        let chain = chain_order(&docs);
+        let leaf_index = chain
+            .iter()
+            .position(|d| d.id.to_string() == id)
+            .ok_or(DocumentServiceError::NotFound)?;
+
+        // Prefer the process root, which is covered by the latest global root
+        let mut global = self
+            .db
+            .get_latest_merkle_root(None, None)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving Merkle root"))?;
+        let mut process_root = match &global {
+            Some(g) => self.get_latest_merkle_root(&pid, g.covers_up_to).await?,
+            None => None,
+        };
+        if process_root
+            .as_ref()
+            .is_none_or(|r| r.tree_size <= leaf_index as u64)
+        {
+            // The document is newer than the global root
+            global = None;
+            process_root = self.get_latest_merkle_root(&pid, None).await?;
+        }
+        let process_root = match process_root {
+            Some(r) if r.tree_size > leaf_index as u64 => r,
+            _ => return Err(DocumentServiceError::NotYetPublished),
+        };
+
+        let tree_size = usize::try_from(process_root.tree_size)
+            .map_err(|e| DocumentServiceError::IntegrityError(e.to_string()))?;
+        let leaves = chain_leaves(&docs);
+        let leaves = leaves.get(..tree_size).ok_or_else(|| {
+            DocumentServiceError::IntegrityError(
+                "Hash chain is shorter than Merkle tree".to_string(),
+            )
+        })?;
+        if merkle::root(leaves) != process_root.root {
+            return Err(DocumentServiceError::IntegrityError(format!(
+                "Documents of pid '{pid}' do not match the published Merkle root"
+            )));
+        }
+
+        let global = match global {
+            Some(g) => Some(self.get_global_inclusion_proof(&process_root, g).await?),
+            None => None,
+        };
+
+        Ok(InclusionProof {
+            doc_id: id,
+            leaf: leaves[leaf_index].clone(),
+            leaf_index: leaf_index as u64,
+            tree_size: process_root.tree_size,
+            audit_path: merkle::audit_path(leaves, leaf_index).unwrap_or_default(),
+            root: process_root.root,
+            signed_root: process_root.signature,
+            global,
+        })
+    }
+
+    async fn get_global_inclusion_proof(
+        &self,
+        process_root: &MerkleRoot,
+        global: MerkleRoot,
+    ) -> Result<GlobalInclusionProof, DocumentServiceError> {
+        let roots = self
+            .db
+            .get_latest_process_roots(global.covers_up_to)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving Merkle roots"))?;
+        let leaves = global_leaves(roots);
+        let leaf = merkle::global_leaf(process_root);
+        let leaf_index = leaves.iter().position(|l| *l == leaf).ok_or_else(|| {
+            DocumentServiceError::IntegrityError(
+                "Process root is not part of global root".to_string(),
+            )
+        })?;
+        if merkle::root(&leaves) != global.root {
+            return Err(DocumentServiceError::IntegrityError(
+                "Process roots do not match the published global Merkle root".to_string(),
+            ));
+        }
+
+        Ok(GlobalInclusionProof {
+            leaf_index: leaf_index as u64,
+            tree_size: global.tree_size,
+            audit_path: merkle::audit_path(&leaves, leaf_index).unwrap_or_default(),
+            root: global.root,
+            signed_root: global.signature,
+        })
+    }
+
+    async fn get_latest_merkle_root(
+        &self,
+        pid: &str,
+        max_id: Option<i32>,
+    ) -> Result<Option<MerkleRoot>, DocumentServiceError> {
+        self.db
+            .get_latest_merkle_root(Some(pid), max_id)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving Merkle root"))
+    }
+
+    /// Purges the payload of all documents, whose retention period is over, and returns the
+    /// number of expired documents
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn expire_documents(&self) -> Result<u64, DocumentServiceError> {
+        self.db
+            .expire_documents(chrono::Utc::now())
+            .await
+            .map_err(|e| db_error(e, "Error while expiring documents"))
+    }
+
+    /// Moves the payload of all documents logged in the months ending at least `older_than_days`
+    /// ago to the archive and returns the number of archived documents
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn archive_documents(
+        &self,
+        older_than_days: u32,
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> Result<u64, DocumentServiceError> {
+        use std::collections::BTreeMap;
+
+        let Some(store) = &self.archive else {
+            return Ok(0);
+        };
+        let before = archive::start_of_month(
+            chrono::Utc::now() - chrono::TimeDelta::days(i64::from(older_than_days)),
+        );
+
+        let pids = self
+            .db
+            .get_archivable_pids(before)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving archivable processes"))?;
+
+        let mut archived = 0;
+        for pid in pids {
+            let docs = self
+                .db
+                .get_archivable_documents(&pid, before)
+                .await
+                .map_err(|e| db_error(e, "Error while retrieving documents"))?;
+            let mut months: BTreeMap<String, Vec<Document<String>>> = BTreeMap::new();
+            for doc in docs {
+                months.entry(archive::month(&doc)).or_default().push(doc);
+            }
+
+            for (month, docs) in months {
+                debug!(
+                    "Archiving {} documents of pid '{pid}' for {month}",
+                    docs.len()
+                );
+                let ids: Vec<uuid::Uuid> = docs.iter().map(|d| d.id).collect();
+                write_bundle(store, &pid, &month, docs, cert_util).await?;
+
+                // The payload is only purged once the bundle is stored
+                self.db
+                    .archive_documents(&ids, chrono::Utc::now())
+                    .await
+                    .map_err(|e| db_error(e, "Error while archiving documents"))?;
+                archived += ids.len() as u64;
+            }
+        }
+
+        Ok(archived)
+    }
+
+    /// Deletes all archived documents of the process `pid`
+    pub(crate) async fn delete_archive(&self, pid: &str) -> Result<(), DocumentServiceError> {
+        match &self.archive {
+            Some(archive) => archive
+                .store
+                .delete_all(&archive::process_prefix(pid))
+                .await
+                .map_err(archive_error),
+            None => Ok(()),
+        }
+    }
+
+    /// Restores the content of the archived documents in `docs` of the process `pid`. Archived
+    /// content, which does not match the hash of its document, is rejected.
+    async fn restore_archived(
+        &self,
+        pid: &str,
+        docs: &mut [Document<String>],
+    ) -> Result<(), DocumentServiceError> {
+        use std::collections::BTreeMap;
+
+        let Some(store) = &self.archive else {
+            return Ok(());
+        };
+
+        // Expired documents are not restored
+        let mut bundles: BTreeMap<String, Vec<Document<String>>> = BTreeMap::new();
+        let mut restored = vec![];
+        for (i, doc) in docs
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.archived_at.is_some() && d.expired_at.is_none())
+        {
+            let month = archive::month(doc);
+            if !bundles.contains_key(&month) {
+                let bundle = read_bundle(store, pid, &month).await?;
+                bundles.insert(month.clone(), bundle);
+            }
+
+            if let Some(archived) = bundles[&month].iter().find(|a| a.id == doc.id) {
+                let mut doc = doc.clone();
+                doc.content = archived.content.clone();
+                restored.push((i, doc));
+            } else {
+                warn!("Document '{}' is missing in the archive", doc.id);
+            }
+        }
+
+        // The hash covers the plaintext, documents with shredded data keys cannot be checked
+        let mut plaintext: Vec<_> = restored.iter().map(|(_, d)| d.clone()).collect();
+        self.decrypt_available(pid, &mut plaintext).await?;
+        if let Some(doc) = plaintext
+            .iter()
+            .find(|d| d.key_version.is_none() && !d.matches_hash())
+        {
+            error!("Archived document '{}' does not match its hash", doc.id);
+            return Err(DocumentServiceError::IntegrityError(format!(
+                "Archived document '{}' does not match its hash",
+                doc.id
+            )));
+        }
+
+        for (i, doc) in restored {
+            docs[i].content = doc.content;
+        }
+
+        Ok(())
+    }
+
+    /// Searches the document `id` in all archived bundles of the process `pid`. An archived
+    /// document, which does not match its hash, is rejected.
+    async fn find_archived(
+        &self,
+        pid: &str,
+        id: &str,
+    ) -> Result<Option<Document<String>>, DocumentServiceError> {
+        let Some(store) = &self.archive else {
+            return Ok(None);
+        };
+
+        let keys = store
+            .store
+            .list(&archive::process_prefix(pid))
+            .await
+            .map_err(archive_error)?;
+        for month in keys.iter().filter_map(|k| {
+            std::path::Path::new(k)
+                .file_name()?
+                .to_str()?
+                .strip_suffix(".jsonl")
+        }) {
+            let bundle = read_bundle(store, pid, month).await?;
+            let Some(doc) = bundle.into_iter().find(|d| d.id.to_string() == id) else {
+                continue;
+            };
+
+            // The hash covers the plaintext, documents with shredded data keys cannot be checked
+            let mut plaintext = doc.clone();
+            self.decrypt_available(pid, std::slice::from_mut(&mut plaintext))
+                .await?;
+            if plaintext.key_version.is_none() && !plaintext.matches_hash() {
+                error!("Archived document '{}' does not match its hash", doc.id);
+                return Err(DocumentServiceError::IntegrityError(format!(
+                    "Archived document '{}' does not match its hash",
+                    doc.id
+                )));
+            }
+            return Ok(Some(doc));
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the documents of the hash chain of the process `pid`, restored from the archive and
+    /// decrypted as far as their data keys were not shredded
+    async fn get_chain(&self, pid: &str) -> Result<Vec<Document<String>>, DocumentServiceError> {
+        let mut docs = self
+            .db
+            .get_chain_for_pid(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving documents"))?;
+        self.restore_archived(pid, &mut docs).await?;
+        self.decrypt_available(pid, &mut docs).await?;
+
+        Ok(docs)
+    }
+
+    /// Returns the id, previous hash and hash of the documents of the hash chain of the process
+    /// `pid`, without reading their content
+    async fn get_chain_links(
+        &self,
+        pid: &str,
+    ) -> Result<Vec<(uuid::Uuid, Option<String>, String)>, DocumentServiceError> {
+        self.db
+            .get_chain_links(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving chain links"))
+    }
+
+    /// Returns the latest data key of the process `pid` for encrypting new documents. The first
+    /// data key of a process is generated with its first document.
+    async fn get_current_data_key(
+        &self,
+        pid: &str,
+    ) -> Result<Option<DataKey>, DocumentServiceError> {
+        let Some(keys) = &self.keys else {
+            return Ok(None);
+        };
+
+        let stored = self
+            .db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?;
+        match stored.last() {
+            Some(latest) => keys.unwrap(latest).map(Some).map_err(encryption_error),
+            None => self
+                .add_data_key(keys, pid, 1)
+                .await
+                .map(|(_, key)| Some(key)),
+        }
+    }
+
+    /// Generates and stores the data key `version` of the process `pid`. If another key with the
+    /// same version was stored in the meantime, that key is returned instead.
+    async fn add_data_key(
+        &self,
+        keys: &KeyRing,
+        pid: &str,
+        version: i32,
+    ) -> Result<(WrappedDataKey, DataKey), DocumentServiceError> {
+        let (wrapped, key) = keys
+            .generate_data_key(pid, version)
+            .map_err(encryption_error)?;
+        let added = self
+            .db
+            .add_data_key(&wrapped)
+            .await
+            .map_err(|e| db_error(e, "Error while storing data key"))?;
+        if added {
+            return Ok((wrapped, key));
+        }
+
+        let stored = self
+            .db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?
+            .into_iter()
+            .find(|k| k.version == version)
+            .ok_or_else(|| encryption_error(format!("Data key of pid '{pid}' was not stored")))?;
+        let key = keys.unwrap(&stored).map_err(encryption_error)?;
+        Ok((stored, key))
+    }
+
+    /// Decrypts the encrypted documents in `docs` of the process `pid`
+    async fn decrypt_documents(
+        &self,
+        pid: &str,
+        docs: &mut [Document<String>],
+    ) -> Result<(), DocumentServiceError> {
+        if self.decrypt_available(pid, docs).await? {
+            Ok(())
+        } else {
+            Err(DocumentServiceError::KeysShredded(pid.to_string()))
+        }
+    }
+
+    /// Decrypts the encrypted documents in `docs` of the process `pid`, whose data keys still
+    /// exist. Returns `false` if documents remain encrypted, because their data keys were shredded.
+    async fn decrypt_available(
+        &self,
+        pid: &str,
+        docs: &mut [Document<String>],
+    ) -> Result<bool, DocumentServiceError> {
+        use std::collections::BTreeMap;
+
+        if docs.iter().all(|d| d.key_version.is_none()) {
+            return Ok(true);
+        }
+        let Some(keys) = &self.keys else {
+            return Err(encryption_error(format!(
+                "Documents of pid '{pid}' are encrypted, but no master key is configured"
+            )));
+        };
+
+        let mut data_keys = BTreeMap::new();
+        for wrapped in self
+            .db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?
+            .iter()
+            .filter(|k| docs.iter().any(|d| d.key_version == Some(k.version)))
+        {
+            let key = keys.unwrap(wrapped).map_err(encryption_error)?;
+            data_keys.insert(wrapped.version, key);
+        }
+
+        let mut complete = true;
+        for doc in docs.iter_mut() {
+            if let Some(version) = doc.key_version {
+                match data_keys.get(&version) {
+                    Some(key) => doc.decrypt(key).map_err(encryption_error)?,
+                    None => complete = false,
+                }
+            }
+        }
+
+        Ok(complete)
+    }
+
+    /// Returns the blind indexes of `transfer_contract` under all data keys of the process `pid`,
+    /// which find the encrypted documents referring to this contract
+    async fn get_transfer_contract_indexes(
+        &self,
+        pid: &str,
+        transfer_contract: &str,
+    ) -> Result<Vec<String>, DocumentServiceError> {
+        let Some(keys) = &self.keys else {
+            return Ok(vec![]);
+        };
+
+        self.db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?
+            .iter()
+            .map(|wrapped| {
+                keys.unwrap(wrapped)
+                    .and_then(|key| key.blind_index(transfer_contract))
+                    .map_err(encryption_error)
+            })
+            .collect()
+    }
+
+    /// Returns the versions of the data keys of the process `pid`, which are destroyed when the
+    /// process is shredded
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if encryption at rest is disabled or the process contains documents, whose
+    /// content is stored unencrypted.
+    pub(crate) async fn get_shreddable_key_versions(
+        &self,
+        pid: &str,
+    ) -> Result<Vec<i32>, DocumentServiceError> {
+        if self.keys.is_none() {
+            return Err(DocumentServiceError::EncryptionDisabled);
+        }
+
+        let docs = self
+            .db
+            .get_chain_for_pid(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving documents"))?;
+        if docs
+            .iter()
+            .any(|d| d.key_version.is_none() && d.expired_at.is_none())
+        {
+            return Err(DocumentServiceError::UnencryptedDocuments(pid.to_string()));
+        }
+
+        Ok(self
+            .db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?
+            .iter()
+            .map(|k| k.version)
+            .collect())
+    }
+
+    /// Generates a new data key for the process `pid`, which encrypts all further documents.
+    /// Existing documents remain encrypted with their data key.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn rotate_data_key(
+        &self,
+        pid: &str,
+    ) -> Result<DataKeyInfo, DocumentServiceError> {
+        let Some(keys) = &self.keys else {
+            return Err(DocumentServiceError::EncryptionDisabled);
+        };
+
+        let latest = self
+            .db
+            .get_data_keys(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?
+            .last()
+            .map_or(0, |k| k.version);
+        let (wrapped, _) = self.add_data_key(keys, pid, latest + 1).await?;
+
+        Ok(DataKeyInfo::from(&wrapped))
+    }
+
+    /// Wraps all data keys, which are wrapped by a previous master key, with the current master
+    /// key and returns the number of rewrapped keys
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn rewrap_data_keys(&self) -> Result<u64, DocumentServiceError> {
+        let Some(keys) = &self.keys else {
+            return Ok(0);
+        };
+
+        let stale = self
+            .db
+            .get_data_keys_not_wrapped_by(keys.current().id())
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving data keys"))?;
+
+        let mut rewrapped = 0;
+        for wrapped in stale {
+            if let Some(key) = keys.rewrap(&wrapped).map_err(encryption_error)? {
+                self.db
+                    .update_data_key(&key)
+                    .await
+                    .map_err(|e| db_error(e, "Error while storing data key"))?;
+                rewrapped += 1;
+            }
+        }
 
-impl<T: DocumentStore> DocumentService<T> {
-    pub fn new(db: T) -> Self {
-        Self { db }
+        Ok(rewrapped)
     }
 
-    #[tracing::instrument(skip_all)]
-    pub(crate) async fn create_enc_document(
+    /// Returns the length and the head of the hash chain of the process `pid`
+    pub(crate) async fn get_chain_state(
         &self,
-        ch_claims: ChClaims,
-        doc: Document<String>,
-    ) -> Result<DocumentReceipt, DocumentServiceError> {
-        trace!("...user '{:?}'", &ch_claims.client_id);
-        // data validation
-        if doc.content.payload.is_none() {
-            return Err(DocumentServiceError::MissingPayload);
-        }
+        pid: &str,
+    ) -> Result<(u64, Option<String>), DocumentServiceError> {
+        let links = self.get_chain_links(pid).await?;
+        let head = self
+            .db
+            .get_chain_head(pid)
+            .await
+            .map_err(|e| db_error(e, "Error while retrieving chain head"))?;
 
-        // check if doc id already exists
-        if let Ok(true) = self.db.exists_document(&doc.id).await {
-            warn!("Document exists already!");
-            Err(DocumentServiceError::DocumentAlreadyExists)
-        } else {
-            // prepare the success result message
-            let receipt = DocumentReceipt::new(doc.ts, &doc.pid, &doc.id.to_string());
+        Ok((links.len() as u64, head))
+    }
 
-            trace!("storing document ....");
-            // store document
-            match self.db.add_document(doc).await {
-                Ok(_b) => Ok(receipt),
-                Err(e) => {
-                    error!("Error while adding: {:?}", e);
-                    Err(DocumentServiceError::DatabaseError {
-                        source: e.into(),
-                        description: "Error while adding document".to_string(),
-                    })
-                }
+    /// Returns the hash of the last document of the process `pid`
+    async fn get_chain_head(&self, pid: &str) -> Result<String, DocumentServiceError> {
+        match self.db.get_chain_head(pid).await {
+            Ok(head) => Ok(head.unwrap_or_else(|| GENESIS_HASH.to_string())),
+            Err(e) => {
+                error!("Error while retrieving chain head: {:?}", e);
+                Err(DocumentServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Error while retrieving chain head".to_string(),
+                })
             }
         }
     }
@@ -93,15 +1078,16 @@ impl<T: DocumentStore> DocumentService<T> {
     pub(crate) async fn get_enc_documents_for_pid(
         &self,
         ch_claims: ChClaims,
-        page: Option<u64>,
-        size: Option<u64>,
+        (page, size, cursor): (Option<u64>, Option<u64>, Option<String>),
         sort: Option<SortingOrder>,
         (date_from, date_to): (Option<String>, Option<String>),
         pid: String,
+        mut filter: DocumentFilter,
     ) -> Result<QueryResult, DocumentServiceError> {
         debug!("Trying to retrieve documents for pid '{pid}'...");
         trace!("...user '{:?}'", &ch_claims.client_id);
-        debug!("...page: {page:?}, size:{size:?} and sort:{sort:?}");
+        debug!("...page: {page:?}, cursor: {cursor:?}, size:{size:?} and sort:{sort:?}");
+        debug!("...filter: {filter:?}");
 
         let sanitized_page = Self::sanitize_page(page);
         let sanitized_size = Self::sanitize_size(size);
@@ -109,6 +1095,16 @@ impl<T: DocumentStore> DocumentService<T> {
         // Sorting order is already validated and defaults to descending
         let sanitized_sort = sort.unwrap_or(SortingOrder::Descending);
 
+        // A cursor continues after the previous page and takes precedence over the page number
+        let parsed_cursor = cursor
+            .as_deref()
+            .map(DocumentCursor::decode)
+            .transpose()
+            .map_err(|e| {
+                debug!("cursor validation failed: {e}");
+                DocumentServiceError::InvalidCursor
+            })?;
+
         // Parsing the dates for duration queries
         let parsed_date_from = parse_date(date_from, false);
         let parsed_date_to = parse_date(date_to, true);
@@ -128,14 +1124,33 @@ impl<T: DocumentStore> DocumentService<T> {
             sanitized_page, sanitized_size, &sanitized_sort
         );
 
-        let docs = match self
+        // The transfer contract of encrypted documents is only comparable by its blind index
+        if let Some(transfer_contract) = &filter.transfer_contract {
+            filter.transfer_contract_indexes = self
+                .get_transfer_contract_indexes(&pid, transfer_contract)
+                .await?;
+        }
+
+        let total = self
+            .db
+            .count_documents_for_pid(&pid, (&sanitized_date_from, &sanitized_date_to), &filter)
+            .await
+            .map_err(|e| db_error(e, "Error while counting documents"))?;
+
+        // After a cursor, one more document is fetched to find out if another page follows
+        let limit = match parsed_cursor {
+            Some(_) => sanitized_size + 1,
+            None => sanitized_size,
+        };
+        let mut docs = match self
             .db
             .get_documents_for_pid(
                 &pid,
-                sanitized_page,
-                sanitized_size,
+                &filter,
+                (sanitized_page, limit),
                 &sanitized_sort,
                 (&sanitized_date_from, &sanitized_date_to),
+                parsed_cursor.as_ref(),
             )
             .await
         {
@@ -157,13 +1172,26 @@ impl<T: DocumentStore> DocumentService<T> {
         };
 
         let mut result = QueryResult::new(
-            sanitized_date_from.and_utc().timestamp(),
-            sanitized_date_to.and_utc().timestamp(),
+            sanitized_date_from.timestamp(),
+            sanitized_date_to.timestamp(),
             result_page,
             result_size,
             result_sort,
             vec![],
         );
+        result.total = total;
+        result.total_pages = total.div_ceil(sanitized_size);
+        result.has_next = match parsed_cursor {
+            Some(_) => u64::try_from(docs.len()).is_ok_and(|len| len > sanitized_size),
+            None => sanitized_page.saturating_mul(sanitized_size) < total,
+        };
+        docs.truncate(usize::try_from(sanitized_size).unwrap_or(usize::MAX));
+        if result.has_next {
+            result.next_cursor = docs.last().map(|d| DocumentCursor::after(d).encode());
+        }
+
+        self.restore_archived(&pid, &mut docs).await?;
+        self.decrypt_documents(&pid, &mut docs).await?;
 
         // The db might contain no documents in which case we get an empty vector
         if docs.is_empty() {
@@ -189,20 +1217,68 @@ impl<T: DocumentStore> DocumentService<T> {
             debug!("integrity check with hash: {}", hash);
         }
 
-        match self.db.get_document(&id, &pid).await {
-            Ok(Some(ct)) => Ok(ct),
+        let mut ct = match self.db.get_document(&id, &pid).await {
+            Ok(Some(mut ct)) => {
+                self.restore_archived(&pid, std::slice::from_mut(&mut ct))
+                    .await?;
+                ct
+            }
             Ok(None) => {
-                debug!("Nothing found in db!");
-                Err(DocumentServiceError::NotFound) // NotFound
+                debug!("Nothing found in db, searching the archive...");
+                self.find_archived(&pid, &id)
+                    .await?
+                    .ok_or(DocumentServiceError::NotFound)? // NotFound
             }
             Err(e) => {
                 error!("Error while retrieving document: {:?}", e);
-                Err(DocumentServiceError::DatabaseError {
+                return Err(DocumentServiceError::DatabaseError {
                     source: e.into(),
                     description: "Error while retrieving document".to_string(),
-                })
+                });
             }
+        };
+
+        self.decrypt_documents(&pid, std::slice::from_mut(&mut ct))
+            .await?;
+        Ok(ct)
+    }
+
+    /// Full-text search over the payloads of the documents in all processes owned by the user.
+    /// Payloads, which are encrypted at rest, are not searchable.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn search_documents(
+        &self,
+        ch_claims: ChClaims,
+        query: String,
+        page: Option<u64>,
+        size: Option<u64>,
+    ) -> Result<SearchResult, DocumentServiceError> {
+        trace!("...user '{:?}'", &ch_claims.client_id);
+        debug!("Searching documents for '{query}', page: {page:?}, size: {size:?}");
+
+        let query = query.trim().to_string();
+        if query.is_empty() {
+            return Err(DocumentServiceError::EmptySearchQuery);
         }
+        let sanitized_page = Self::sanitize_page(page);
+        let sanitized_size = Self::sanitize_size(size);
+
+        let hits = self
+            .db
+            .search_documents(
+                &ch_claims.client_id,
+                &query,
+                (sanitized_page, sanitized_size),
+            )
+            .await
+            .map_err(|e| db_error(e, "Error while searching documents"))?;
+
+        Ok(SearchResult {
+            query,
+            page: sanitized_page,
+            size: sanitized_size,
+            hits,
+        })
     }
 
     #[inline]
@@ -238,3 +1314,947 @@ impl<T: DocumentStore> DocumentService<T> {
         }
     }
 }
+
+/// Wraps a database error into a `DocumentServiceError`
+fn db_error(e: anyhow::Error, description: &str) -> DocumentServiceError {
+    error!("{description}: {e:?}");
+    DocumentServiceError::DatabaseError {
+        source: e.into(),
+        description: description.to_string(),
+    }
+}
+
+/// Wraps an archive error into a `DocumentServiceError`
+fn archive_error(e: impl std::fmt::Display) -> DocumentServiceError {
+    error!("Error while accessing the archive: {e}");
+    DocumentServiceError::ArchiveError(e.to_string())
+}
+
+/// Wraps an encryption error into a `DocumentServiceError`
+fn encryption_error(e: impl std::fmt::Display) -> DocumentServiceError {
+    error!("Error during encryption: {e}");
+    DocumentServiceError::EncryptionError(e.to_string())
+}
+
+/// Reads the documents of the bundle of the process `pid` for `month`, which are empty if the
+/// bundle does not exist. The bundle is only read if it matches its signed manifest.
+async fn read_bundle<O: ObjectStore>(
+    archive: &Archive<O>,
+    pid: &str,
+    month: &str,
+) -> Result<Vec<Document<String>>, DocumentServiceError> {
+    let integrity_error = |reason: String| {
+        error!("Archive bundle of pid '{pid}' for {month} is rejected: {reason}");
+        DocumentServiceError::IntegrityError(format!(
+            "Archive bundle of pid '{pid}' for {month} is rejected: {reason}"
+        ))
+    };
+
+    let Some(bundle) = archive
+        .store
+        .get(&archive::bundle_key(pid, month))
+        .await
+        .map_err(archive_error)?
+    else {
+        return Ok(vec![]);
+    };
+    let manifest = archive
+        .store
+        .get(&archive::manifest_key(pid, month))
+        .await
+        .map_err(archive_error)?
+        .ok_or_else(|| integrity_error("Manifest is missing".to_string()))?;
+    let manifest: ArchiveManifest = serde_json::from_slice(&manifest).map_err(archive_error)?;
+    manifest
+        .verify(pid, month, &bundle, &archive.manifest_key)
+        .map_err(|e| integrity_error(e.to_string()))?;
+
+    let docs = archive::decode_bundle(&bundle).map_err(archive_error)?;
+    if docs.len() as u64 != manifest.document_count {
+        return Err(integrity_error(
+            "Number of documents does not match the manifest".to_string(),
+        ));
+    }
+    Ok(docs)
+}
+
+/// Writes the bundle of `docs` of the process `pid` for `month` and its signed manifest. Documents
+/// of an existing bundle, e.g. from an interrupted run, are kept.
+async fn write_bundle<O: ObjectStore>(
+    archive: &Archive<O>,
+    pid: &str,
+    month: &str,
+    mut docs: Vec<Document<String>>,
+    cert_util: &ids_daps_cert::CertUtil,
+) -> Result<(), DocumentServiceError> {
+    let existing: Vec<_> = read_bundle(archive, pid, month)
+        .await?
+        .into_iter()
+        .filter(|e| !docs.iter().any(|d| d.id == e.id))
+        .collect();
+    docs.extend(existing);
+    docs.sort_by_key(|d| d.ts);
+
+    let bundle = archive::encode_bundle(&docs).map_err(archive_error)?;
+    let manifest = ArchiveManifest::new_signed(
+        pid.to_string(),
+        month.to_string(),
+        docs.len() as u64,
+        &bundle,
+        cert_util,
+    )
+    .map_err(|e| DocumentServiceError::SigningError(e.to_string()))?;
+    let manifest = serde_json::to_vec(&manifest).map_err(archive_error)?;
+
+    archive
+        .store
+        .put(&archive::bundle_key(pid, month), bundle)
+        .await
+        .map_err(archive_error)?;
+    archive
+        .store
+        .put(&archive::manifest_key(pid, month), manifest)
+        .await
+        .map_err(archive_error)
+}
+
+/// Orders `docs` along their hash chain, starting at the first document of the process. Stops at
+/// the first broken link.
+fn chain_order(docs: &[Document<String>]) -> Vec<&Document<String>> {
+    use std::collections::HashMap;
+
+    let successors: HashMap<&str, &Document<String>> = docs
+        .iter()
+        .filter_map(|d| d.previous_hash.as_deref().map(|p| (p, d)))
+        .collect();
+
+    let mut chain = Vec::new();
+    let mut current = GENESIS_HASH;
+    while let Some(doc) = successors.get(current) {
+        match doc.hash.as_deref() {
+            Some(stored) if doc.is_linked_to(current) => {
+                chain.push(*doc);
+                current = stored;
+            }
+            _ => break,
+        }
+    }
+    chain
+}
+
+/// Index of the first document in `docs` with the same process and message id (`@id`), for each
+/// document repeating the message id of an earlier one
+fn repeated_messages(docs: &[Document<String>]) -> Vec<Option<usize>> {
+    let mut first = std::collections::HashMap::new();
+    docs.iter()
+        .enumerate()
+        .map(|(i, doc)| {
+            let message_id = doc.content.header.id.as_deref()?;
+            match first.entry((doc.pid.as_str(), message_id)) {
+                std::collections::hash_map::Entry::Occupied(e) => Some(*e.get()),
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    e.insert(i);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Leaves of the Merkle tree of a process
+fn chain_leaves(docs: &[Document<String>]) -> Vec<String> {
+    chain_order(docs)
+        .into_iter()
+        .filter_map(|d| d.hash.clone())
+        .collect()
+}
+
+/// Leaves of the Merkle tree of a process from the `links` of its documents, following them from
+/// the first document of the process up to the first broken link
+fn link_leaves(links: &[(uuid::Uuid, Option<String>, String)]) -> Vec<String> {
+    use std::collections::HashMap;
+
+    let successors: HashMap<&str, &str> = links
+        .iter()
+        .filter_map(|(_, previous, hash)| Some((previous.as_deref()?, hash.as_str())))
+        .collect();
+
+    let mut leaves = Vec::new();
+    let mut current = GENESIS_HASH;
+    while let Some(hash) = successors.get(current) {
+        leaves.push((*hash).to_string());
+        current = hash;
+    }
+    leaves
+}
+
+/// Leaves of the global Merkle tree
+fn global_leaves(mut roots: Vec<MerkleRoot>) -> Vec<Vec<u8>> {
+    roots.sort_by(|a, b| a.pid.cmp(&b.pid));
+    roots.iter().map(merkle::global_leaf).collect()
+}
+
+/// Verifies that `docs` (ordered by creation time) form a single, unmodified hash chain
+fn verify_documents(docs: &[Document<String>]) -> ChainVerification {
+    use std::collections::HashMap;
+
+    // Index documents by the hash they are linked to
+    let mut successors: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, doc) in docs.iter().enumerate() {
+        successors
+            .entry(doc.previous_hash.as_deref().unwrap_or_default())
+            .or_default()
+            .push(i);
+    }
+
+    let mut visited = vec![false; docs.len()];
+    let mut current = GENESIS_HASH;
+    let mut length = 0;
+    while let Some(next) = successors.get(current) {
+        let doc = &docs[next[0]];
+        if next.len() > 1 {
+            return ChainVerification::Broken {
+                position: length,
+                doc_id: docs[next[1]].id.to_string(),
+                reason: ChainBreak::Fork,
+            };
+        }
+
+        if !doc.is_linked_to(current) {
+            return ChainVerification::Broken {
+                position: length,
+                doc_id: doc.id.to_string(),
+                reason: ChainBreak::ContentModified,
+            };
+        }
+
+        visited[next[0]] = true;
+        current = doc.hash.as_deref().unwrap_or_default();
+        length += 1;
+    }
+
+    // Documents, which are not reachable from the start of the chain, lost their predecessor
+    if let Some(i) = visited.iter().position(|v| !v) {
+        return ChainVerification::Broken {
+            position: length,
+            doc_id: docs[i].id.to_string(),
+            reason: ChainBreak::MissingPredecessor,
+        };
+    }
+
+    ChainVerification::Intact {
+        length,
+        head: (length > 0).then(|| current.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::verify_documents;
+    use crate::model::document::{Document, GENESIS_HASH};
+    use crate::services::{ChainBreak, ChainVerification};
+    use crate::test_util::document;
+    #[cfg(feature = "memory")]
+    use crate::test_util::{key_ring, memory_db, service, TEST_MASTER_KEY};
+
+    fn chain(len: usize) -> Vec<Document<String>> {
+        let mut previous_hash = GENESIS_HASH.to_string();
+        (0..len)
+            .map(|i| {
+                let mut doc = document("test", &format!("payload {i}"));
+                doc.link_to(&previous_hash)
+                    .expect("Document is serializable");
+                previous_hash = doc.hash.clone().expect("Hash was set");
+                doc
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verify_intact_chain() {
+        let docs = chain(3);
+        assert_eq!(
+            verify_documents(&docs),
+            ChainVerification::Intact {
+                length: 3,
+                head: docs[2].hash.clone()
+            }
+        );
+        assert_eq!(
+            verify_documents(&[]),
+            ChainVerification::Intact {
+                length: 0,
+                head: None
+            }
+        );
+
+        // Expired documents only keep their link to the chain
+        let mut docs = chain(3);
+        docs[1].expire(chrono::Utc::now());
+        assert_eq!(
+            verify_documents(&docs),
+            ChainVerification::Intact {
+                length: 3,
+                head: docs[2].hash.clone()
+            }
+        );
+    }
+
+    #[test]
+    fn verify_broken_chain() {
+        // Modified content
+        let mut docs = chain(3);
+        docs[1].content.payload = Some("modified".to_string());
+        assert_eq!(
+            verify_documents(&docs),
+            ChainVerification::Broken {
+                position: 1,
+                doc_id: docs[1].id.to_string(),
+                reason: ChainBreak::ContentModified
+            }
+        );
+
+        // Deleted document
+        let mut docs = chain(3);
+        docs.remove(1);
+        assert_eq!(
+            verify_documents(&docs),
+            ChainVerification::Broken {
+                position: 1,
+                doc_id: docs[1].id.to_string(),
+                reason: ChainBreak::MissingPredecessor
+            }
+        );
+
+        // Reordered documents are still verified along the chain
+        let mut docs = chain(3);
+        docs.swap(0, 2);
+        assert!(matches!(
+            verify_documents(&docs),
+            ChainVerification::Intact { length: 3, .. }
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn archived_documents() {
+        use super::{Archive, DocumentService};
+        use crate::db::memory_store::MemoryDocumentStore;
+        use crate::db::object_store::{LocalObjectStore, ObjectStore};
+        use crate::db::DocumentStore;
+        use crate::model::archive::{self, ArchiveManifest};
+        use crate::model::claims::ChClaims;
+        use crate::services::document_service::DocumentServiceError;
+        use crate::test_util::cert_util;
+
+        let doc_store = MemoryDocumentStore::new(memory_db(&["test"]).await);
+        let mut docs = chain(3);
+        let missing = docs.pop().expect("Chain is not empty");
+        for doc in &docs {
+            doc_store
+                .add_document(doc.clone())
+                .await
+                .expect("Storing document");
+        }
+
+        // Archive the first document and a document, which is not in the database anymore
+        let cert_util = cert_util();
+        let dir = tempfile::tempdir().expect("Creating tempdir");
+        let store = LocalObjectStore::new(dir.path());
+        let month = archive::month(&docs[0]);
+        let put = |key: String, data: Vec<u8>| {
+            let store = store.clone();
+            async move { store.put(&key, data).await.expect("Storing object") }
+        };
+        let put_bundle = |bundle: Vec<u8>, signed: &[u8]| {
+            let manifest = ArchiveManifest::new_signed(
+                "test".to_string(),
+                month.clone(),
+                2,
+                signed,
+                &cert_util,
+            )
+            .expect("Signing manifest");
+            let manifest = serde_json::to_vec(&manifest).expect("Encoding manifest");
+            async {
+                put(archive::bundle_key("test", &month), bundle).await;
+                put(archive::manifest_key("test", &month), manifest).await;
+            }
+        };
+        let bundle = archive::encode_bundle(&[docs[0].clone(), missing.clone()]).expect("Encoding");
+        put_bundle(bundle.clone(), &bundle).await;
+        doc_store
+            .archive_documents(&[docs[0].id], chrono::Utc::now())
+            .await
+            .expect("Archiving");
+        let service = DocumentService::new(
+            doc_store,
+            Some(Archive::new(store.clone(), &cert_util).expect("Reading key")),
+            None,
+        );
+
+        let get = |id: uuid::Uuid| {
+            service.get_enc_document(
+                ChClaims::new("owner"),
+                "test".to_string(),
+                id.to_string(),
+                None,
+            )
+        };
+        let restored = get(docs[0].id).await.expect("Document is restored");
+        assert_eq!(restored.content.payload, docs[0].content.payload);
+        assert!(restored.archived_at.is_some());
+        assert_eq!(
+            get(missing.id)
+                .await
+                .expect("Document is found in the archive")
+                .content
+                .payload,
+            missing.content.payload
+        );
+        assert_eq!(
+            get(docs[1].id)
+                .await
+                .expect("Document is found")
+                .content
+                .payload,
+            docs[1].content.payload
+        );
+
+        // Archived documents remain part of the hash chain
+        assert!(matches!(
+            service
+                .verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Ok(ChainVerification::Intact { length: 2, .. })
+        ));
+
+        // Tampered bundles do not match their manifest
+        let tamper = |doc: &Document<String>| {
+            let mut tampered = doc.clone();
+            tampered.content.payload = Some("tampered".to_string());
+            tampered
+        };
+        let is_rejected = |result: Result<Document<String>, DocumentServiceError>| {
+            matches!(result, Err(DocumentServiceError::IntegrityError(_)))
+        };
+        let tampered_first =
+            archive::encode_bundle(&[tamper(&docs[0]), missing.clone()]).expect("Encoding");
+        put_bundle(tampered_first.clone(), &bundle).await;
+        assert!(is_rejected(get(docs[0].id).await));
+        assert!(is_rejected(get(missing.id).await));
+        assert!(matches!(
+            service
+                .verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Err(DocumentServiceError::IntegrityError(_))
+        ));
+
+        // Tampered documents do not match their hash, even in a bundle matching its manifest
+        put_bundle(tampered_first.clone(), &tampered_first).await;
+        assert!(is_rejected(get(docs[0].id).await));
+        assert!(!is_rejected(get(missing.id).await));
+        let tampered_missing =
+            archive::encode_bundle(&[docs[0].clone(), tamper(&missing)]).expect("Encoding");
+        put_bundle(tampered_missing.clone(), &tampered_missing).await;
+        assert!(!is_rejected(get(docs[0].id).await));
+        assert!(is_rejected(get(missing.id).await));
+
+        // Manifests must be signed
+        put_bundle(bundle.clone(), &bundle).await;
+        let mut manifest: ArchiveManifest = serde_json::from_slice(
+            &store
+                .get(&archive::manifest_key("test", &month))
+                .await
+                .expect("Reading manifest")
+                .expect("Manifest exists"),
+        )
+        .expect("Decoding manifest");
+        manifest.document_count = 1;
+        put(
+            archive::manifest_key("test", &month),
+            serde_json::to_vec(&manifest).expect("Encoding manifest"),
+        )
+        .await;
+        assert!(matches!(
+            get(missing.id).await,
+            Err(DocumentServiceError::IntegrityError(_))
+        ));
+        put(archive::manifest_key("test", &month), b"{}".to_vec()).await;
+        assert!(matches!(
+            get(missing.id).await,
+            Err(DocumentServiceError::ArchiveError(_))
+        ));
+        put_bundle(bundle.clone(), &bundle).await;
+        assert!(matches!(
+            service
+                .verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Ok(ChainVerification::Intact { length: 2, .. })
+        ));
+
+        service
+            .delete_archive("test")
+            .await
+            .expect("Deleting archive");
+        assert!(matches!(
+            get(missing.id).await,
+            Err(DocumentServiceError::NotFound)
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn encrypted_documents() {
+        use crate::db::memory_store::MemoryDocumentStore;
+        use crate::db::DocumentStore;
+        use crate::model::claims::ChClaims;
+        use crate::test_util::TEST_MASTER_KEY_2;
+
+        let db = memory_db(&["test"]).await;
+        let service = |keys: &str| service(&db, Some(key_ring(keys)));
+        let doc = |i: usize| {
+            let mut doc = document("test", &format!("payload {i}"));
+            doc.content.header.transfer_contract = Some(format!("contract {i}"));
+            doc
+        };
+
+        let first = doc(1);
+        let old = service(TEST_MASTER_KEY);
+        old.create_enc_document(ChClaims::new("owner"), first.clone())
+            .await
+            .expect("Storing document");
+        let key = old
+            .rotate_data_key("test")
+            .await
+            .expect("Rotating data key");
+        assert_eq!(key.version, 2);
+        let second = doc(2);
+        old.create_enc_document(ChClaims::new("owner"), second.clone())
+            .await
+            .expect("Storing document");
+
+        // Documents are encrypted at rest with the latest data key
+        let store = MemoryDocumentStore::new(db.clone());
+        for (doc, version) in [(&first, 1), (&second, 2)] {
+            let stored = store
+                .get_document(&doc.id.to_string(), "test")
+                .await
+                .expect("Reading")
+                .expect("Document exists");
+            assert_eq!(stored.key_version, Some(version));
+            assert_ne!(stored.content.payload, doc.content.payload);
+            assert_ne!(
+                stored.content.header.transfer_contract,
+                doc.content.header.transfer_contract
+            );
+        }
+
+        // Rotate the master key
+        let rotated = service(&format!("{TEST_MASTER_KEY_2},{TEST_MASTER_KEY}"));
+        assert_eq!(rotated.rewrap_data_keys().await.expect("Rewrapping"), 2);
+        assert_eq!(rotated.rewrap_data_keys().await.expect("Rewrapping"), 0);
+
+        let new = service(TEST_MASTER_KEY_2);
+        for doc in [&first, &second] {
+            let decrypted = new
+                .get_enc_document(
+                    ChClaims::new("owner"),
+                    "test".to_string(),
+                    doc.id.to_string(),
+                    None,
+                )
+                .await
+                .expect("Document is decrypted");
+            assert_eq!(decrypted.key_version, None);
+            assert_eq!(decrypted.content.payload, doc.content.payload);
+            assert_eq!(
+                decrypted.content.header.transfer_contract,
+                doc.content.header.transfer_contract
+            );
+        }
+        assert!(matches!(
+            new.verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Ok(ChainVerification::Intact { length: 2, .. })
+        ));
+
+        // The old master key cannot decrypt the documents anymore
+        assert!(matches!(
+            old.verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Err(super::DocumentServiceError::EncryptionError(_))
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn shredded_documents() {
+        use super::DocumentServiceError;
+        use crate::db::memory_store::MemoryProcessStore;
+        use crate::db::ProcessStore;
+        use crate::model::claims::ChClaims;
+        use crate::model::process::ShredCertificate;
+
+        let db = memory_db(&["test"]).await;
+        let process_store = MemoryProcessStore::new(db.clone());
+        let unencrypted = service(&db, None);
+        let service = service(&db, Some(key_ring(TEST_MASTER_KEY)));
+
+        let doc = document("test", "payload");
+        service
+            .create_enc_document(ChClaims::new("owner"), doc.clone())
+            .await
+            .expect("Storing document");
+        let key_versions = service
+            .get_shreddable_key_versions("test")
+            .await
+            .expect("Process can be shredded");
+        assert_eq!(key_versions, vec![1]);
+
+        let certificate = ShredCertificate {
+            pid: "test".to_string(),
+            owners: vec!["owner".to_string()],
+            consents: vec!["owner".to_string()],
+            key_versions,
+            chain_length: 1,
+            chain_head: None,
+            shredded_at: chrono::Utc::now(),
+            signature: "signature".to_string(),
+        };
+        assert!(process_store
+            .shred_process("test", &certificate)
+            .await
+            .expect("Shredding"));
+
+        // The documents cannot be decrypted anymore, but the hash chain stays verifiable
+        assert!(matches!(
+            service
+                .get_enc_document(
+                    ChClaims::new("owner"),
+                    "test".to_string(),
+                    doc.id.to_string(),
+                    None
+                )
+                .await,
+            Err(DocumentServiceError::KeysShredded(_))
+        ));
+        assert!(matches!(
+            service
+                .verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Ok(ChainVerification::Intact { length: 1, .. })
+        ));
+
+        // Shredding requires encryption at rest
+        assert!(matches!(
+            unencrypted.get_shreddable_key_versions("test").await,
+            Err(DocumentServiceError::EncryptionDisabled)
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn filtered_documents() {
+        use crate::model::claims::ChClaims;
+        use crate::model::document::DocumentFilter;
+        use crate::model::SortingOrder;
+
+        let service = service(&memory_db(&["test"]).await, Some(key_ring(TEST_MASTER_KEY)));
+
+        // Documents referring to the same contract are encrypted with different data keys
+        let mut expected = vec![];
+        for contract in ["contract 1", "contract 2", "contract 1"] {
+            let mut doc = document("test", "payload");
+            doc.content.header.transfer_contract = Some(contract.to_string());
+            if contract == "contract 1" {
+                expected.push(doc.id);
+            }
+            service
+                .create_enc_document(ChClaims::new("owner"), doc)
+                .await
+                .expect("Storing document");
+            service
+                .rotate_data_key("test")
+                .await
+                .expect("Rotating data key");
+        }
+
+        let result = service
+            .get_enc_documents_for_pid(
+                ChClaims::new("owner"),
+                (None, None, None),
+                Some(SortingOrder::Ascending),
+                (None, None),
+                "test".to_string(),
+                DocumentFilter {
+                    transfer_contract: Some("contract 1".to_string()),
+                    ..DocumentFilter::default()
+                },
+            )
+            .await
+            .expect("Querying");
+        assert_eq!(result.total, 2);
+        assert_eq!(
+            result.documents.iter().map(|d| d.id).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(result
+            .documents
+            .iter()
+            .all(|d| { d.content.header.transfer_contract.as_deref() == Some("contract 1") }));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn paginated_documents() {
+        use crate::model::claims::ChClaims;
+        use crate::model::document::DocumentFilter;
+
+        let service = service(&memory_db(&["test"]).await, None);
+        for i in 0..5 {
+            service
+                .create_enc_document(
+                    ChClaims::new("owner"),
+                    document("test", &format!("payload {i}")),
+                )
+                .await
+                .expect("Storing document");
+        }
+        let query = |page: Option<u64>, cursor: Option<String>| {
+            service.get_enc_documents_for_pid(
+                ChClaims::new("owner"),
+                (page, Some(2), cursor),
+                None,
+                (None, None),
+                "test".to_string(),
+                DocumentFilter::default(),
+            )
+        };
+
+        // Page metadata
+        let first = query(Some(1), None).await.expect("Querying");
+        assert_eq!(first.documents.len(), 2);
+        assert_eq!(
+            (first.total, first.total_pages, first.has_next),
+            (5, 3, true)
+        );
+        let last = query(Some(3), None).await.expect("Querying");
+        assert_eq!(last.documents.len(), 1);
+        assert!(!last.has_next);
+        assert_eq!(last.next_cursor, None);
+
+        // Following the cursors returns every document exactly once
+        let mut ids: Vec<_> = first.documents.iter().map(|d| d.id).collect();
+        let mut cursor = first.next_cursor;
+        while let Some(next) = cursor {
+            let page = query(None, Some(next)).await.expect("Querying");
+            assert_eq!(page.total, 5);
+            ids.extend(page.documents.iter().map(|d| d.id));
+            cursor = page.next_cursor;
+        }
+        assert_eq!(ids.len(), 5);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+
+        assert!(matches!(
+            query(None, Some("invalid".to_string())).await,
+            Err(super::DocumentServiceError::InvalidCursor)
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn batch_documents() {
+        use crate::model::claims::ChClaims;
+        use crate::services::Appended;
+
+        let service = service(
+            &memory_db(&["a", "b"]).await,
+            Some(key_ring(TEST_MASTER_KEY)),
+        );
+        let doc = |pid: &str, i: usize| document(pid, &format!("payload {i}"));
+        let length = |pid: &str| {
+            let service = &service;
+            let pid = pid.to_string();
+            async move {
+                match service.verify_chain(ChClaims::new("owner"), pid).await {
+                    Ok(ChainVerification::Intact { length, .. }) => length,
+                    other => panic!("Chain is not intact: {other:?}"),
+                }
+            }
+        };
+
+        service
+            .create_enc_document(ChClaims::new("owner"), doc("a", 0))
+            .await
+            .expect("Storing document");
+        let batch = vec![doc("a", 1), doc("b", 2), doc("a", 3)];
+        let appended = service
+            .create_enc_documents(ChClaims::new("owner"), batch.clone())
+            .await
+            .expect("Storing documents");
+
+        // Documents of the same process are chained in the order of the batch
+        let doc_ids = appended
+            .iter()
+            .map(|a| match a {
+                Appended::Created(receipt) => receipt.doc_id.clone(),
+                Appended::Duplicate(doc) => panic!("Unexpected duplicate {}", doc.id),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            doc_ids,
+            batch.iter().map(|d| d.id.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(length("a").await, 3);
+        assert_eq!(length("b").await, 1);
+
+        // A batch is stored completely or not at all
+        let result = service
+            .create_enc_documents(ChClaims::new("owner"), vec![doc("b", 4), doc("unknown", 5)])
+            .await;
+        assert!(matches!(
+            result,
+            Err(super::DocumentServiceError::DatabaseError { .. })
+        ));
+        assert_eq!(length("b").await, 1);
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn documents_logged_once() {
+        use crate::model::claims::ChClaims;
+        use crate::services::Appended;
+
+        let service = service(&memory_db(&["test"]).await, Some(key_ring(TEST_MASTER_KEY)));
+        let doc = |message_id: Option<&str>| {
+            let mut doc = document("test", "payload");
+            doc.content.header.id = message_id.map(ToString::to_string);
+            doc
+        };
+        let duplicate_of = |appended: &Appended| match appended {
+            Appended::Duplicate(logged) => Some(logged.id),
+            Appended::Created(_) => None,
+        };
+
+        let first = doc(Some("msg-1"));
+        let appended = service
+            .create_enc_document_once(ChClaims::new("owner"), first.clone())
+            .await
+            .expect("Storing document");
+        assert!(matches!(appended, Appended::Created(_)));
+
+        // A retry returns the decrypted document logged first
+        match service
+            .create_enc_document_once(ChClaims::new("owner"), doc(Some("msg-1")))
+            .await
+            .expect("Storing document")
+        {
+            Appended::Duplicate(logged) => {
+                assert_eq!(logged.id, first.id);
+                assert_eq!(logged.key_version, None);
+                assert_eq!(logged.content.payload, first.content.payload);
+            }
+            Appended::Created(_) => panic!("Retry was stored again"),
+        }
+
+        // Message ids repeated in a batch are logged once as well
+        let second = doc(Some("msg-2"));
+        let batch = vec![
+            doc(Some("msg-1")),
+            second.clone(),
+            doc(Some("msg-2")),
+            doc(None),
+        ];
+        let appended = service
+            .create_enc_documents(ChClaims::new("owner"), batch)
+            .await
+            .expect("Storing documents");
+        assert_eq!(
+            appended.iter().map(duplicate_of).collect::<Vec<_>>(),
+            vec![Some(first.id), None, Some(second.id), None]
+        );
+        assert!(matches!(
+            service
+                .verify_chain(ChClaims::new("owner"), "test".to_string())
+                .await,
+            Ok(ChainVerification::Intact { length: 3, .. })
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn broken_chain_roots() {
+        use crate::db::memory_store::MemoryDocumentStore;
+        use crate::db::DocumentStore;
+        use crate::model::claims::ChClaims;
+
+        let cert_util = crate::test_util::cert_util();
+        let db = memory_db(&["test"]).await;
+        let service = service(&db, None);
+        for i in 0..2 {
+            service
+                .create_enc_document(
+                    ChClaims::new("owner"),
+                    document("test", &format!("payload {i}")),
+                )
+                .await
+                .expect("Storing document");
+        }
+        service
+            .publish_merkle_roots(&cert_util)
+            .await
+            .expect("Publishing");
+
+        // A document, which is not linked to the chain, is not covered by the Merkle roots
+        let mut unlinked = document("test", "unlinked");
+        unlinked
+            .link_to("unknown")
+            .expect("Document is serializable");
+        let store = MemoryDocumentStore::new(db);
+        store
+            .add_document(unlinked)
+            .await
+            .expect("Storing document");
+        service
+            .publish_merkle_roots(&cert_util)
+            .await
+            .expect("Publishing");
+        service
+            .publish_merkle_roots(&cert_util)
+            .await
+            .expect("Publishing");
+
+        let root = store
+            .get_latest_merkle_root(Some("test"), None)
+            .await
+            .expect("Reading")
+            .expect("Root was published");
+        assert_eq!(root.tree_size, 2);
+        assert_eq!(service.broken_chains().get("test"), Some(&3));
+        assert_eq!(
+            store.get_unpublished_pids().await.expect("Reading"),
+            vec![("test".to_string(), 3)]
+        );
+
+        // The chain is checked again for new documents, which are linked to the unlinked one
+        service
+            .create_enc_document(ChClaims::new("owner"), document("test", "payload 2"))
+            .await
+            .expect("Storing document");
+        service
+            .publish_merkle_roots(&cert_util)
+            .await
+            .expect("Publishing");
+        let root = store
+            .get_latest_merkle_root(Some("test"), None)
+            .await
+            .expect("Reading")
+            .expect("Root was published");
+        assert_eq!(root.tree_size, 2);
+        assert_eq!(service.broken_chains().get("test"), Some(&4));
+    }
+}
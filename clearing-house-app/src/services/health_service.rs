@@ -0,0 +1,377 @@
+//! Checks of the components, which the Clearing House needs to serve requests: the database, the
+//! reachability of the DAPS for validating DATs and the certificate for signing receipts
+
+use crate::model::constants::DAPS_RETRY_INTERVAL;
+use crate::model::health::{ComponentHealth, HealthStatus};
+
+/// Timeout of a single check
+const CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// Number of days before the certificate expires, from which its health is degraded
+const CERT_EXPIRY_WARNING_DAYS: i64 = 30;
+
+pub(crate) struct HealthService {
+    certificate: CertificateCheck,
+    daps: DapsReachabilityCheck,
+}
+
+impl HealthService {
+    /// Checks the certificate at `p12_path` with the key of `cert_util` once and the JWKS endpoint
+    /// `jwks_url` of the DAPS at most every `jwks_ttl` seconds
+    ///
+    /// # Errors
+    ///
+    /// Throws an error if the HTTP client cannot be created.
+    pub(crate) fn new(
+        p12_path: &std::path::Path,
+        p12_password: &str,
+        cert_util: &ids_daps_cert::CertUtil,
+        jwks_url: &str,
+        jwks_ttl: u64,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            certificate: CertificateCheck::new(p12_path, p12_password, cert_util),
+            daps: DapsReachabilityCheck::new(jwks_url, jwks_ttl)?,
+        })
+    }
+
+    pub(crate) fn certificate(&self) -> ComponentHealth {
+        self.certificate.health(chrono::Utc::now())
+    }
+
+    pub(crate) async fn daps_reachability(&self) -> ComponentHealth {
+        self.daps.health().await
+    }
+}
+
+/// Health of the database: down if `ping` fails or times out
+pub(crate) async fn check_database(
+    ping: impl std::future::Future<Output = anyhow::Result<()>>,
+) -> ComponentHealth {
+    let start = std::time::Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, ping).await;
+    let latency_ms = start.elapsed().as_millis();
+
+    match result {
+        Ok(Ok(())) => ComponentHealth {
+            status: HealthStatus::Up,
+            details: serde_json::json!({ "latency_ms": latency_ms }),
+        },
+        Ok(Err(e)) => ComponentHealth {
+            status: HealthStatus::Down,
+            details: serde_json::json!({ "error": e.to_string() }),
+        },
+        Err(_) => ComponentHealth {
+            status: HealthStatus::Down,
+            details: serde_json::json!({ "error": "Timed out" }),
+        },
+    }
+}
+
+/// Validity period and private key of the certificate, which do not change at runtime
+struct CertificateCheck {
+    /// Validity period (not before, not after) of the certificate or the error reading it
+    validity: Result<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>), String>,
+    /// Error signing with the private key, `None` if the key is usable
+    key_error: Option<String>,
+}
+
+impl CertificateCheck {
+    fn new(
+        p12_path: &std::path::Path,
+        p12_password: &str,
+        cert_util: &ids_daps_cert::CertUtil,
+    ) -> Self {
+        Self {
+            validity: read_validity(p12_path, p12_password).map_err(|e| e.to_string()),
+            key_error: check_key(cert_util).err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Down if the key is not usable or the certificate is not valid at `now`, degraded if it
+    /// expires within `CERT_EXPIRY_WARNING_DAYS`
+    fn health(&self, now: chrono::DateTime<chrono::Utc>) -> ComponentHealth {
+        let (not_before, not_after) = match &self.validity {
+            Ok(validity) => *validity,
+            Err(e) => {
+                return ComponentHealth {
+                    status: HealthStatus::Down,
+                    details: serde_json::json!({ "error": e, "key_error": self.key_error }),
+                }
+            }
+        };
+
+        let status = if self.key_error.is_some() || now < not_before || not_after <= now {
+            HealthStatus::Down
+        } else if not_after - now < chrono::Duration::days(CERT_EXPIRY_WARNING_DAYS) {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Up
+        };
+
+        ComponentHealth {
+            status,
+            details: serde_json::json!({
+                "not_before": not_before,
+                "not_after": not_after,
+                "expires_in_days": (not_after - now).num_days(),
+                "key_error": self.key_error,
+            }),
+        }
+    }
+}
+
+/// Reads the validity period of the certificate in the PKCS #12 archive at `path`. `CertUtil`
+/// does not expose the certificate, so the archive is read a second time.
+fn read_validity(
+    path: &std::path::Path,
+    password: &str,
+) -> anyhow::Result<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    let der = std::fs::read(path)?;
+    let cert = openssl::pkcs12::Pkcs12::from_der(&der)?
+        .parse2(password)?
+        .cert
+        .ok_or_else(|| anyhow::anyhow!("Archive contains no certificate"))?;
+
+    Ok((
+        to_datetime(cert.not_before())?,
+        to_datetime(cert.not_after())?,
+    ))
+}
+
+fn to_datetime(time: &openssl::asn1::Asn1TimeRef) -> anyhow::Result<chrono::DateTime<chrono::Utc>> {
+    let diff = openssl::asn1::Asn1Time::from_unix(0)?.diff(time)?;
+    chrono::DateTime::from_timestamp(i64::from(diff.days) * 86400 + i64::from(diff.secs), 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid time {time}"))
+}
+
+/// Signs a JWS with the private key of `cert_util` and verifies it with its public key
+fn check_key(cert_util: &ids_daps_cert::CertUtil) -> anyhow::Result<()> {
+    let jws = crate::model::claims::sign_jws(&serde_json::json!({ "sub": "health" }), cert_util)?;
+    let key = crate::model::claims::verifying_key(cert_util)?;
+    crate::model::claims::verify_jws::<serde_json::Value>(&jws, &key)?;
+
+    Ok(())
+}
+
+/// Fetches the JWKS of the DAPS to check that the DAPS is reachable. The DAPS client, which
+/// validates DATs, keeps the JWKS in its own cache for `ttl`. Its state is not exposed, so this
+/// check cannot tell whether the client currently holds the keys.
+struct DapsReachabilityCheck {
+    client: reqwest::Client,
+    url: String,
+    ttl: chrono::Duration,
+    /// Serializes fetches, so concurrent checks wait for a single request to the DAPS
+    state: tokio::sync::Mutex<DapsState>,
+}
+
+#[derive(Default)]
+struct DapsState {
+    /// Time and number of keys or error of the last fetch
+    last_fetch: Option<(chrono::DateTime<chrono::Utc>, Result<usize, String>)>,
+    last_success: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DapsReachabilityCheck {
+    fn new(url: &str, ttl: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?,
+            url: url.to_string(),
+            ttl: chrono::Duration::seconds(i64::try_from(ttl)?),
+            state: tokio::sync::Mutex::default(),
+        })
+    }
+
+    /// Up if the JWKS was fetched, degraded if fetching failed, but succeeded within `ttl`, down
+    /// otherwise. The result is reused for `ttl` or, after a failed fetch, `DAPS_RETRY_INTERVAL`.
+    async fn health(&self) -> ComponentHealth {
+        let mut state = self.state.lock().await;
+        let now = chrono::Utc::now();
+        let fresh = state.last_fetch.as_ref().is_some_and(|(at, result)| {
+            let max_age = if result.is_ok() {
+                self.ttl
+            } else {
+                chrono::Duration::seconds(DAPS_RETRY_INTERVAL)
+            };
+            now < *at + max_age
+        });
+        if !fresh {
+            let result = self.fetch().await.map_err(|e| e.to_string());
+            let now = chrono::Utc::now();
+            if result.is_ok() {
+                state.last_success = Some(now);
+            }
+            state.last_fetch = Some((now, result));
+        }
+
+        let now = chrono::Utc::now();
+        let recent = state.last_success.is_some_and(|at| now < at + self.ttl);
+        let (status, keys, error) = match &state.last_fetch {
+            Some((_, Ok(keys))) => (HealthStatus::Up, Some(*keys), None),
+            Some((_, Err(e))) if recent => (HealthStatus::Degraded, None, Some(e)),
+            Some((_, Err(e))) => (HealthStatus::Down, None, Some(e)),
+            None => (HealthStatus::Down, None, None),
+        };
+
+        ComponentHealth {
+            status,
+            details: serde_json::json!({
+                "url": self.url,
+                "keys": keys,
+                "checked_at": state.last_fetch.as_ref().map(|(at, _)| at),
+                "last_success": state.last_success,
+                "error": error,
+            }),
+        }
+    }
+
+    /// Fetches the JWKS and returns its number of keys
+    async fn fetch(&self) -> anyhow::Result<usize> {
+        debug!("Fetching JWKS from '{}'", self.url);
+        let response = self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let jwks = response.json::<jsonwebtoken::jwk::JwkSet>().await?;
+        if jwks.keys.is_empty() {
+            anyhow::bail!("JWKS contains no keys");
+        }
+        Ok(jwks.keys.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_database, read_validity, CertificateCheck, DapsReachabilityCheck};
+    use crate::model::health::HealthStatus;
+
+    /// Writes a PKCS #12 archive with a self-signed certificate valid from `not_before` for `days`
+    fn write_p12(path: &std::path::Path, not_before: i64, days: u32) {
+        let key = openssl::pkey::PKey::from_rsa(
+            openssl::rsa::Rsa::generate(2048).expect("Generating key"),
+        )
+        .expect("Generating key");
+        let mut name = openssl::x509::X509NameBuilder::new().expect("Building name");
+        name.append_entry_by_text("CN", "clearing-house")
+            .expect("Building name");
+        let name = name.build();
+
+        let mut cert = openssl::x509::X509Builder::new().expect("Building certificate");
+        cert.set_subject_name(&name).expect("Building certificate");
+        cert.set_issuer_name(&name).expect("Building certificate");
+        cert.set_pubkey(&key).expect("Building certificate");
+        let not_before =
+            openssl::asn1::Asn1Time::from_unix(not_before).expect("Building certificate");
+        cert.set_not_before(&not_before)
+            .expect("Building certificate");
+        let not_after = openssl::asn1::Asn1Time::days_from_now(days).expect("Building certificate");
+        cert.set_not_after(&not_after)
+            .expect("Building certificate");
+        cert.sign(&key, openssl::hash::MessageDigest::sha256())
+            .expect("Signing certificate");
+
+        let p12 = openssl::pkcs12::Pkcs12::builder()
+            .name("clearing-house")
+            .pkey(&key)
+            .cert(&cert.build())
+            .build2("password")
+            .expect("Building archive");
+        std::fs::write(path, p12.to_der().expect("Encoding archive")).expect("Writing archive");
+    }
+
+    #[test]
+    fn certificate() {
+        let dir = tempfile::tempdir().expect("Creating directory");
+        let path = dir.path().join("cert.p12");
+        // Certificates have a precision of seconds
+        let now = chrono::DateTime::from_timestamp(chrono::Utc::now().timestamp(), 0)
+            .expect("Valid time");
+        write_p12(&path, now.timestamp() - 3600, 365);
+
+        let (not_before, not_after) =
+            read_validity(&path, "password").expect("Reading certificate");
+        assert_eq!(not_before.timestamp(), now.timestamp() - 3600);
+        assert_eq!((not_after - now).num_days(), 365);
+        assert!(read_validity(&path, "wrong").is_err());
+
+        let check = CertificateCheck {
+            validity: Ok((not_before, not_after)),
+            key_error: None,
+        };
+        let health = check.health(now);
+        assert_eq!(health.status, HealthStatus::Up);
+        assert_eq!(health.details["expires_in_days"], 365);
+        assert_eq!(
+            check.health(not_after - chrono::Duration::days(7)).status,
+            HealthStatus::Degraded
+        );
+        assert_eq!(check.health(not_after).status, HealthStatus::Down);
+        assert_eq!(
+            check
+                .health(not_before - chrono::Duration::seconds(1))
+                .status,
+            HealthStatus::Down
+        );
+
+        let check = CertificateCheck {
+            validity: Ok((not_before, not_after)),
+            key_error: Some("Invalid key".to_string()),
+        };
+        assert_eq!(check.health(now).status, HealthStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn database() {
+        let health = check_database(async { Ok(()) }).await;
+        assert_eq!(health.status, HealthStatus::Up);
+        assert!(health.details["latency_ms"].is_u64());
+
+        let health = check_database(async { Err(anyhow::anyhow!("Connection refused")) }).await;
+        assert_eq!(health.status, HealthStatus::Down);
+        assert_eq!(health.details["error"], "Connection refused");
+    }
+
+    #[tokio::test]
+    async fn daps_reachability() {
+        let jwks = serde_json::json!({ "keys": [{ "kty": "RSA", "n": "AQAB", "e": "AQAB" }] });
+        let available = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let router = axum::Router::new().route(
+            "/jwks.json",
+            axum::routing::get({
+                let available = available.clone();
+                move || async move {
+                    if available.load(std::sync::atomic::Ordering::SeqCst) {
+                        Ok(axum::Json(jwks))
+                    } else {
+                        Err(axum::http::StatusCode::SERVICE_UNAVAILABLE)
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Binding port");
+        let url = format!(
+            "http://{}/jwks.json",
+            listener.local_addr().expect("Reading address")
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let check = DapsReachabilityCheck::new(&url, 300).expect("Creating check");
+        let health = check.health().await;
+        assert_eq!(health.status, HealthStatus::Up);
+        assert_eq!(health.details["keys"], 1);
+
+        // The result of the last fetch is reused, when the DAPS becomes unavailable
+        available.store(false, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(check.health().await.status, HealthStatus::Up);
+        check.state.lock().await.last_fetch = None;
+        let health = check.health().await;
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert!(health.details["error"].is_string());
+
+        let check = DapsReachabilityCheck::new(&url, 300).expect("Creating check");
+        assert_eq!(check.health().await.status, HealthStatus::Down);
+    }
+}
@@ -1,14 +1,27 @@
 use crate::db::{DocumentStore, ProcessStore};
 use crate::model::{
     claims::ChClaims,
-    constants::{DEFAULT_NUM_RESPONSE_ENTRIES, DEFAULT_PROCESS_ID, MAX_NUM_RESPONSE_ENTRIES},
-    {document::Document, process::Process, SortingOrder},
+    constants::{
+        DEFAULT_NUM_RESPONSE_ENTRIES, DEFAULT_PROCESS_ID, MAX_BATCH_SIZE, MAX_NUM_RESPONSE_ENTRIES,
+    },
+    {
+        document::{Document, DocumentFilter},
+        process::Process,
+        SortingOrder,
+    },
 };
 use crate::model::{
+    crypto::DataKeyInfo,
     ids::{message::IdsMessage, IdsQueryResult},
-    process::{DataTransaction, OwnerList, Receipt},
+    process::{
+        BatchItemResult, DataTransaction, DeletionStatus, LogBatch, OwnerAction, OwnerChange,
+        OwnerList, ProcessList, Receipt, ReceiptDigest, ReceiptMode, Retention, RetentionPolicy,
+        ShredCertificate, Tombstone,
+    },
+    search::SearchResult,
 };
 use crate::services::document_service::DocumentService;
+use crate::services::{Appended, ChainVerification, DocumentReceipt, InclusionProof};
 use std::sync::Arc;
 
 /// Error type for `LoggingService`
@@ -16,6 +29,8 @@ use std::sync::Arc;
 pub enum LoggingServiceError {
     #[error("Received empty payload, which cannot be logged!")]
     EmptyPayloadReceived,
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
     #[error("Accessing default PID is not allowed!")]
     AttemptedAccessToDefaultPid,
     #[error("Error during database operation: {description}: {source}")]
@@ -29,6 +44,23 @@ pub enum LoggingServiceError {
     ProcessAlreadyExists,
     #[error("Process '{0}' does not exist!")]
     ProcessDoesNotExist(String),
+    #[error(
+        "Process '{pid}' was shredded at {shredded_at}, its documents cannot be read anymore!"
+    )]
+    ProcessShredded {
+        pid: String,
+        shredded_at: chrono::DateTime<chrono::Utc>,
+    },
+    #[error("Invalid change of process owners: {0}")]
+    InvalidOwnerChange(String),
+    #[error("Invalid retention policy: {0}")]
+    InvalidRetentionPolicy(String),
+    #[error("{0}")]
+    DuplicateMessage(String),
+    #[error("Invalid batch: {0}")]
+    InvalidBatch(String),
+    #[error("Raw header is not available: {0}")]
+    RawHeaderUnavailable(String),
     #[error("Parsing error in {0}")]
     ParsingError(#[from] serde_json::Error),
     #[error("DocumentService error in {0}")]
@@ -46,8 +78,12 @@ impl axum::response::IntoResponse for LoggingServiceError {
         //RejectionMessage::new()
         match self {
             Self::EmptyPayloadReceived
+            | Self::InvalidPayload(_)
             | Self::AttemptedAccessToDefaultPid
             | Self::ProcessAlreadyExists
+            | Self::InvalidOwnerChange(_)
+            | Self::InvalidRetentionPolicy(_)
+            | Self::InvalidBatch(_)
             | Self::ParsingError(_) => (StatusCode::BAD_REQUEST, self.to_string()).into_response(),
             Self::DatabaseError {
                 source,
@@ -58,9 +94,11 @@ impl axum::response::IntoResponse for LoggingServiceError {
             )
                 .into_response(),
             Self::UserNotAuthorized => (StatusCode::FORBIDDEN, self.to_string()).into_response(),
-            Self::ProcessDoesNotExist(_) => {
+            Self::DuplicateMessage(_) => (StatusCode::CONFLICT, self.to_string()).into_response(),
+            Self::ProcessDoesNotExist(_) | Self::RawHeaderUnavailable(_) => {
                 (StatusCode::NOT_FOUND, self.to_string()).into_response()
             }
+            Self::ProcessShredded { .. } => (StatusCode::GONE, self.to_string()).into_response(),
             Self::DocumentServiceError(e) => e.into_response(),
             Self::CertUtilError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
             Self::DapsError(e) => match e {
@@ -83,24 +121,31 @@ pub(crate) struct LoggingService<T, S> {
     cert_util: Arc<ids_daps_cert::CertUtil>,
     static_process_owner: Option<String>,
     issuer: String,
+    receipt_mode: ReceiptMode,
+    deletion_quorum: Option<usize>,
     doc_api: Arc<DocumentService<S>>,
 }
 
 impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingService<T, S>
-    where
-        Self: Send + Sync {
+where
+    Self: Send + Sync,
+{
     pub fn new(
         db: T,
         doc_api: Arc<DocumentService<S>>,
         cert_util: Arc<ids_daps_cert::CertUtil>,
         issuer: String,
         static_process_owner: Option<String>,
+        receipt_mode: ReceiptMode,
+        deletion_quorum: Option<usize>,
     ) -> LoggingService<T, S> {
         LoggingService {
             db,
             cert_util,
             static_process_owner,
             issuer,
+            receipt_mode,
+            deletion_quorum,
             doc_api,
         }
     }
@@ -109,6 +154,11 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         &self.issuer
     }
 
+    /// Checks that the database of the stores is usable
+    pub(crate) async fn ping_database(&self) -> anyhow::Result<()> {
+        self.db.ping().await
+    }
+
     pub async fn log(
         &self,
         ch_claims: ChClaims,
@@ -117,6 +167,116 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
     ) -> Result<Receipt, LoggingServiceError> {
         trace!("...user '{}'", &ch_claims.client_id);
         let user = &ch_claims.client_id;
+        let (doc, payload, digest) = self.prepare_document(user, msg, pid).await?;
+
+        debug!("Storing document...");
+        match self
+            .doc_api
+            .create_enc_document_once(ChClaims::new(user), doc.clone())
+            .await
+        {
+            Ok(Appended::Created(doc_receipt)) => self.sign_receipt(doc_receipt, payload, digest),
+            Ok(Appended::Duplicate(logged)) => self.sign_duplicate_receipt(&doc, &logged),
+            Err(e) => {
+                error!("Error while creating document: {:?}", e);
+                Err(LoggingServiceError::DocumentServiceError(e))
+            }
+        }
+    }
+
+    /// Logs the messages of `batch` and returns a result per message. Messages, which cannot be
+    /// logged, are reported in their result, all others are stored in a single transaction.
+    pub async fn log_batch(
+        &self,
+        ch_claims: ChClaims,
+        batch: LogBatch,
+    ) -> Result<Vec<BatchItemResult>, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+        if batch.messages.is_empty() || batch.messages.len() > MAX_BATCH_SIZE {
+            return Err(LoggingServiceError::InvalidBatch(format!(
+                "A batch must contain 1 to {MAX_BATCH_SIZE} messages"
+            )));
+        }
+
+        let mut results = Vec::with_capacity(batch.messages.len());
+        let mut indexes = vec![];
+        let mut docs = vec![];
+        let mut receipt_contents = vec![];
+        for (index, entry) in batch.messages.into_iter().enumerate() {
+            results.push(BatchItemResult {
+                pid: entry.pid.clone(),
+                message_id: entry.message_id(),
+                receipt: None,
+                error: None,
+            });
+
+            let prepared = match entry.to_message() {
+                Ok(msg) => self.prepare_document(user, msg, entry.pid).await,
+                Err(e) => Err(e.into()),
+            };
+            match prepared {
+                Ok((doc, payload, digest)) => {
+                    indexes.push(index);
+                    docs.push(doc);
+                    receipt_contents.push((payload, digest));
+                }
+                Err(e) => {
+                    warn!("Message {index} of batch cannot be logged: {:?}", e);
+                    results[index].error = Some(e.to_string());
+                }
+            }
+        }
+        if docs.is_empty() {
+            return Ok(results);
+        }
+
+        debug!("Storing {} documents...", docs.len());
+        match self
+            .doc_api
+            .create_enc_documents(ChClaims::new(user), docs.clone())
+            .await
+        {
+            Ok(appended) => {
+                for (((index, (payload, digest)), doc), appended) in indexes
+                    .into_iter()
+                    .zip(receipt_contents)
+                    .zip(&docs)
+                    .zip(appended)
+                {
+                    let receipt = match appended {
+                        Appended::Created(doc_receipt) => {
+                            self.sign_receipt(doc_receipt, payload, digest)
+                        }
+                        Appended::Duplicate(logged) => self.sign_duplicate_receipt(doc, &logged),
+                    };
+                    match receipt {
+                        Ok(receipt) => results[index].receipt = Some(receipt),
+                        Err(e) => results[index].error = Some(e.to_string()),
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error while creating documents: {:?}", e);
+                let e = LoggingServiceError::DocumentServiceError(e).to_string();
+                for index in indexes {
+                    results[index].error = Some(e.clone());
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Validates the message `msg` of `user` for the process `pid`, which is created if it does
+    /// not exist, and transforms it into a document. Returns the document with the payload and
+    /// digest for its receipt.
+    async fn prepare_document(
+        &self,
+        user: &str,
+        msg: IdsMessage<String>,
+        pid: String,
+    ) -> Result<(Document<String>, String, Option<ReceiptDigest>), LoggingServiceError> {
         // Add non-InfoModel information to IdsMessage
         let mut m = msg;
         m.header.pid = Some(pid.clone());
@@ -132,6 +292,11 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
                 Err(LoggingServiceError::EmptyPayloadReceived) // BadRequest
             }
         }?;
+        // binary payloads are digested as they were received
+        let payload_bytes = m
+            .payload_bytes()
+            .map_err(|e| LoggingServiceError::InvalidPayload(e.to_string()))?
+            .unwrap_or_default();
 
         // Check if process exists and if the user is authorized to access the process
         match self.get_process_and_check_authorized(&pid, user).await {
@@ -139,7 +304,7 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
                 // convenience: if process does not exist, we create it but only if no error occurred before
                 info!("Requested pid '{}' does not exist. Creating...", &pid);
                 // create a new process
-                let new_process = Process::new(pid.clone(), vec![user.clone()]);
+                let new_process = Process::new(pid.clone(), vec![user.to_string()]);
 
                 if let Err(e) = self.db.store_process(new_process).await {
                     error!("Error while creating process '{}' automatically for log message (could have been created in the meantime)", &pid);
@@ -168,44 +333,16 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
             }
             Ok(_) => {}
         }
+        self.check_not_shredded(&pid).await?;
 
         // transform message to document
         debug!("transforming message to document...");
         let doc: Document<String> = m.into();
 
-        debug!("Storing document...");
-        match self
-            .doc_api
-            .create_enc_document(ChClaims::new(user), doc.clone())
-            .await
-        {
-            Ok(doc_receipt) => {
-                debug!("Creating receipt...");
-                let transaction = DataTransaction {
-                    timestamp: doc_receipt.timestamp,
-                    process_id: doc_receipt.pid,
-                    document_id: doc_receipt.doc_id,
-                    payload,
-                    client_id: self
-                        .cert_util
-                        .ski_aki()
-                        .map_err(|e| LoggingServiceError::CertUtilError(e.to_string()))?
-                        .to_string(),
-                    clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
-                };
-                debug!("...done. Signing receipt...");
-                Ok(transaction
-                    .sign_jsonwebtoken(self.cert_util.as_ref())
-                    .map_err(|e| LoggingServiceError::DatabaseError {
-                        source: e.into(),
-                        description: "Issue during signing".to_string(),
-                    })?)
-            }
-            Err(e) => {
-                error!("Error while creating document: {:?}", e);
-                Err(LoggingServiceError::DocumentServiceError(e))
-            }
-        }
+        // digests for the receipt are created from the message as it is stored
+        let digest = ReceiptDigest::new(self.receipt_mode, &doc.content, &payload_bytes)?;
+
+        Ok((doc, payload, digest))
     }
 
     pub(crate) async fn create_process(
@@ -228,7 +365,7 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         if let Some(static_process_owner) = &self.static_process_owner {
             owners.push(static_process_owner.clone());
         }
-        
+
         // Extract owners from payload and extend the owners list with not yet existing ones
         if let Some(owner_list) = m.payload {
             trace!("OwnerList: '{:#?}'", owner_list);
@@ -237,7 +374,7 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
                     owners.push(o);
                 }
             }
-        };
+        }
 
         // check if the pid already exists
         match self.db.get_process(&pid).await {
@@ -277,14 +414,350 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         }
     }
 
-    pub(crate) async fn query_pid(
+    /// Changes the owners of the process `pid` and logs the change as document in the process
+    pub(crate) async fn change_owners(
+        &self,
+        ch_claims: ChClaims,
+        msg: IdsMessage<OwnerList>,
+        pid: String,
+        action: OwnerAction,
+    ) -> Result<Receipt, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to change the process
+        let process = self.get_process_and_check_authorized(&pid, user).await?;
+
+        let owners = msg.payload.map(|l| l.owners).unwrap_or_default();
+        if owners.is_empty() {
+            return Err(LoggingServiceError::InvalidOwnerChange(
+                "No owners given".to_string(),
+            ));
+        }
+
+        let change = OwnerChange::new(action, owners, user, &process.owners);
+        if change.is_empty() {
+            return Err(LoggingServiceError::InvalidOwnerChange(
+                "Owners are unchanged".to_string(),
+            ));
+        }
+        if change.resulting_owners.is_empty() {
+            return Err(LoggingServiceError::InvalidOwnerChange(
+                "Process must keep at least one owner".to_string(),
+            ));
+        }
+        if let Some(static_process_owner) = &self.static_process_owner {
+            if change.removed.contains(static_process_owner) {
+                return Err(LoggingServiceError::InvalidOwnerChange(
+                    "Static process owner cannot be removed".to_string(),
+                ));
+            }
+        }
+        // The quorum of an erasure is counted over the current owners, so the owners must not
+        // shrink while an erasure is pending
+        if !change.removed.is_empty() {
+            let consents = self.db.get_deletion_consents(&pid).await.map_err(|e| {
+                error!(
+                    "Error while getting deletion consents for pid '{}': {}",
+                    &pid, e
+                );
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Getting deletion consents failed".to_string(),
+                }
+            })?;
+            if !consents.is_empty() {
+                return Err(LoggingServiceError::InvalidOwnerChange(
+                    "Owners cannot be removed while an erasure is pending".to_string(),
+                ));
+            }
+        }
+        info!(
+            "Changing owners of pid '{}': adding {:?}, removing {:?}",
+            &pid, change.added, change.removed
+        );
+
+        let payload = serde_json::to_string(&change)?;
+        let mut header = msg.header;
+        header.pid = Some(pid.clone());
+        let doc: Document<String> = IdsMessage {
+            header,
+            payload: Some(payload.clone()),
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: msg.raw_header,
+        }
+        .into();
+        let digest = ReceiptDigest::new(self.receipt_mode, &doc.content, payload.as_bytes())?;
+
+        // The change is applied before it is logged, so the history only contains applied changes
+        self.db
+            .update_owners(&pid, &change.added, &change.removed)
+            .await
+            .map_err(|e| {
+                error!("Error while changing owners of pid '{}': {}", &pid, e);
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Changing owners failed".to_string(),
+                }
+            })?;
+
+        let created = self
+            .doc_api
+            .create_enc_document(ChClaims::new(user), doc)
+            .await;
+        let doc_receipt = match created {
+            Ok(doc_receipt) => doc_receipt,
+            Err(e) => {
+                error!("Error while creating document: {:?}", e);
+                // A change, which is not part of the history, is reverted
+                let reverted = self
+                    .db
+                    .update_owners(&pid, &change.removed, &change.added)
+                    .await;
+                if let Err(e) = reverted {
+                    error!("Error while reverting owners of pid '{}': {}", &pid, e);
+                }
+                return Err(LoggingServiceError::DocumentServiceError(e));
+            }
+        };
+
+        self.sign_receipt(doc_receipt, payload, digest)
+    }
+
+    /// Records the consent of the user to erase the process `pid` and erases the process, once
+    /// the quorum of owners consented
+    pub(crate) async fn delete_process(
         &self,
         ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<DeletionStatus, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to delete the process
+        let process = self.get_process_and_check_authorized(&pid, user).await?;
+
+        let (consents, required) = self.add_erasure_consent(&process, user).await?;
+        if consents.len() < required {
+            info!(
+                "Deletion of pid '{}' pending: {} of {} consents",
+                &pid,
+                consents.len(),
+                required
+            );
+            return Ok(DeletionStatus::Pending { consents, required });
+        }
+
+        let chain_state = self
+            .doc_api
+            .get_chain_state(&pid)
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)?;
+        let tombstone = Tombstone::new_signed(
+            pid.clone(),
+            process.owners,
+            consents,
+            chain_state,
+            self.cert_util.as_ref(),
+        )
+        .map_err(|e| LoggingServiceError::CertUtilError(e.to_string()))?;
+
+        info!("Erasing pid '{}'", &pid);
+        // The archive is erased first, so a failed erasure can be retried by the owners
+        self.doc_api
+            .delete_archive(&pid)
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)?;
+        match self.db.delete_process(&pid, &tombstone).await {
+            Ok(true) => {}
+            Ok(false) => return Err(LoggingServiceError::ProcessDoesNotExist(pid)),
+            Err(e) => {
+                error!("Error while erasing pid '{}': {}", &pid, e);
+                return Err(LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Erasing process failed".to_string(),
+                });
+            }
+        }
+
+        Ok(DeletionStatus::Deleted {
+            receipt: Receipt {
+                data: tombstone.signature,
+            },
+        })
+    }
+
+    /// Records the consent of the user to shred the process `pid` and destroys its data keys, once
+    /// the quorum of owners consented. The consents are shared with the erasure of the process.
+    pub(crate) async fn shred_process(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<DeletionStatus, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to shred the process
+        let process = self.get_process_and_check_authorized(&pid, user).await?;
+
+        // Shredding is final, so the owners get the existing certificate again
+        if let Some(certificate) = self.get_shred_certificate(&pid).await? {
+            return Ok(DeletionStatus::Shredded {
+                receipt: Receipt {
+                    data: certificate.signature,
+                },
+            });
+        }
+
+        // Fails early, if the documents of the process cannot be shredded at all
+        let key_versions = self
+            .doc_api
+            .get_shreddable_key_versions(&pid)
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)?;
+
+        let (consents, required) = self.add_erasure_consent(&process, user).await?;
+        if consents.len() < required {
+            info!(
+                "Shredding of pid '{}' pending: {} of {} consents",
+                &pid,
+                consents.len(),
+                required
+            );
+            return Ok(DeletionStatus::Pending { consents, required });
+        }
+
+        let chain_state = self
+            .doc_api
+            .get_chain_state(&pid)
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)?;
+        let certificate = ShredCertificate::new_signed(
+            pid.clone(),
+            (process.owners, consents),
+            key_versions,
+            chain_state,
+            self.cert_util.as_ref(),
+        )
+        .map_err(|e| LoggingServiceError::CertUtilError(e.to_string()))?;
+
+        info!("Shredding pid '{}'", &pid);
+        match self.db.shred_process(&pid, &certificate).await {
+            Ok(true) => {}
+            Ok(false) => return Err(LoggingServiceError::ProcessDoesNotExist(pid)),
+            Err(e) => {
+                error!("Error while shredding pid '{}': {}", &pid, e);
+                return Err(LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Shredding process failed".to_string(),
+                });
+            }
+        }
+
+        Ok(DeletionStatus::Shredded {
+            receipt: Receipt {
+                data: certificate.signature,
+            },
+        })
+    }
+
+    /// Returns the tombstone of the erased process `pid` to its former owners
+    pub(crate) async fn get_tombstone(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<Tombstone, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        match self.db.get_tombstone(&pid).await {
+            Ok(Some(t)) if t.owners.contains(user) => Ok(t),
+            Ok(Some(_)) => {
+                warn!("User is not authorized to read tombstone of pid '{}'", &pid);
+                Err(LoggingServiceError::UserNotAuthorized)
+            }
+            Ok(None) => Err(LoggingServiceError::ProcessDoesNotExist(pid)),
+            Err(e) => {
+                error!("Error while getting tombstone of pid '{}': {}", &pid, e);
+                Err(LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Getting tombstone failed".to_string(),
+                })
+            }
+        }
+    }
+
+    /// Full-text search over the messages of all processes owned by the user, the best matches
+    /// first
+    pub(crate) async fn search_messages(
+        &self,
+        ch_claims: ChClaims,
+        query: String,
         page: Option<u64>,
         size: Option<u64>,
+    ) -> Result<SearchResult, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+
+        self.doc_api
+            .search_documents(ch_claims, query, page, size)
+            .await
+            .map_err(|e| {
+                error!("Error while searching messages: {:?}", e);
+                LoggingServiceError::DocumentServiceError(e)
+            })
+    }
+
+    /// Lists the processes owned by the user, the most recently created first
+    pub(crate) async fn list_processes(
+        &self,
+        ch_claims: ChClaims,
+        page: Option<u64>,
+        size: Option<u64>,
+    ) -> Result<ProcessList, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        let sanitized_page = page.unwrap_or(1).max(1);
+        let sanitized_size = match size {
+            Some(s) => s.min(MAX_NUM_RESPONSE_ENTRIES),
+            None => DEFAULT_NUM_RESPONSE_ENTRIES,
+        };
+
+        self.db
+            .get_processes_for_owner(user, sanitized_page, sanitized_size)
+            .await
+            .map(|processes| ProcessList {
+                page: sanitized_page,
+                size: sanitized_size,
+                processes,
+            })
+            .map_err(|e| {
+                error!("Error while listing processes: {}", e);
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Listing processes failed".to_string(),
+                }
+            })
+    }
+
+    pub(crate) async fn query_pid(
+        &self,
+        ch_claims: ChClaims,
+        (page, size, cursor): (Option<u64>, Option<u64>, Option<String>),
         sort: Option<SortingOrder>,
         (date_to, date_from): (Option<String>, Option<String>),
         pid: String,
+        filter: DocumentFilter,
     ) -> Result<IdsQueryResult<String>, LoggingServiceError> {
         debug!("page: {:#?}, size:{:#?} and sort:{:#?}", page, size, sort);
 
@@ -293,6 +766,7 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
 
         // Check if process exists and if the user is authorized to access the process
         self.get_process_and_check_authorized(&pid, user).await?;
+        self.check_not_shredded(&pid).await?;
 
         let sanitized_page = page.unwrap_or(1);
         let sanitized_size = match size {
@@ -306,11 +780,11 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
             .doc_api
             .get_enc_documents_for_pid(
                 ChClaims::new(user),
-                Some(sanitized_page),
-                Some(sanitized_size),
+                (Some(sanitized_page), Some(sanitized_size), cursor),
                 Some(sanitized_sort),
                 (date_from, date_to),
                 pid.clone(),
+                filter,
             )
             .await
         {
@@ -320,8 +794,13 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
                     .iter()
                     .map(|d| IdsMessage::from(d.clone()))
                     .collect();
-                let result =
+                let mut result =
                     IdsQueryResult::new(r.date_from, r.date_to, r.page, r.size, r.order, messages);
+                result.expires_at = self.get_expiry(&pid, &r.documents).await?;
+                result.total = r.total;
+                result.total_pages = r.total_pages;
+                result.has_next = r.has_next;
+                result.next_cursor = r.next_cursor;
                 Ok(result)
             }
             Err(e) => {
@@ -341,12 +820,14 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         pid: String,
         id: String,
         _message: IdsMessage<()>,
+        with_raw_header: bool,
     ) -> Result<IdsQueryResult<String>, LoggingServiceError> {
         trace!("...user '{}'", &ch_claims.client_id);
         let user = &ch_claims.client_id;
 
         // Check if process exists and if the user is authorized to access the process
         self.get_process_and_check_authorized(&pid, user).await?;
+        self.check_not_shredded(&pid).await?;
 
         match self
             .doc_api
@@ -354,16 +835,25 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
             .await
         {
             Ok(doc) => {
+                let expires_at = self.get_expiry(&pid, std::slice::from_ref(&doc)).await?;
+
                 // transform document to IDS message
-                let queried_message = IdsMessage::from(doc);
-                Ok(IdsQueryResult::new(
+                let raw_header = doc.content.raw_header.clone();
+                let mut queried_message = IdsMessage::from(doc);
+                if with_raw_header {
+                    queried_message.raw_header =
+                        Some(Self::releasable_raw_header(raw_header, user)?);
+                }
+                let mut result = IdsQueryResult::new(
                     0,
                     i64::MAX,
                     None,
                     None,
                     "asc".to_string(),
                     vec![queried_message],
-                ))
+                );
+                result.expires_at = expires_at;
+                Ok(result)
             }
             Err(e) => {
                 error!("Error while retrieving message: {:?}", e);
@@ -372,6 +862,313 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         }
     }
 
+    /// Returns `raw_header` to the sender of the message at once and to other owners of the
+    /// process once the security token of the sender in it expired, so they cannot use it to
+    /// impersonate the sender
+    fn releasable_raw_header(
+        raw_header: Option<String>,
+        user: &str,
+    ) -> Result<String, LoggingServiceError> {
+        let raw_header = raw_header.ok_or_else(|| {
+            LoggingServiceError::RawHeaderUnavailable(
+                "The message was logged before raw headers were stored".to_string(),
+            )
+        })?;
+        if crate::model::claims::security_token_issued_to(&raw_header, user)
+            || crate::model::claims::security_token_expired(&raw_header, chrono::Utc::now())
+        {
+            Ok(raw_header)
+        } else {
+            Err(LoggingServiceError::RawHeaderUnavailable(
+                "The security token of the message has not expired yet".to_string(),
+            ))
+        }
+    }
+
+    /// Sets the retention policy of the process `pid`, without policy the global policy applies
+    pub(crate) async fn set_retention_policy(
+        &self,
+        ch_claims: ChClaims,
+        msg: IdsMessage<RetentionPolicy>,
+        pid: String,
+    ) -> Result<Retention, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to change the process
+        self.get_process_and_check_authorized(&pid, user).await?;
+
+        let policy = msg.payload;
+        if policy.as_ref().is_some_and(|p| p.days == 0) {
+            return Err(LoggingServiceError::InvalidRetentionPolicy(
+                "Documents must be kept for at least one day".to_string(),
+            ));
+        }
+
+        info!("Setting retention policy of pid '{}' to {:?}", &pid, policy);
+        self.db
+            .set_retention_policy(Some(&pid), policy.as_ref())
+            .await
+            .map_err(|e| {
+                error!(
+                    "Error while setting retention policy of pid '{}': {}",
+                    &pid, e
+                );
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Setting retention policy failed".to_string(),
+                }
+            })?;
+
+        self.get_retention(&pid).await
+    }
+
+    /// Closes the process `pid`, which starts the retention period of policies counting from the
+    /// closing of the process
+    pub(crate) async fn close_process(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<Retention, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to change the process
+        self.get_process_and_check_authorized(&pid, user).await?;
+
+        info!("Closing pid '{}'", &pid);
+        self.db
+            .close_process(&pid, chrono::Utc::now())
+            .await
+            .map_err(|e| {
+                error!("Error while closing pid '{}': {}", &pid, e);
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Closing process failed".to_string(),
+                }
+            })?;
+
+        self.get_retention(&pid).await
+    }
+
+    /// Generates a new data key for the process `pid`, which encrypts all further documents
+    pub(crate) async fn rotate_data_key(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<DataKeyInfo, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check for default process id
+        Self::check_for_default_pid(&pid)?;
+
+        // Check if process exists and if the user is authorized to change the process
+        self.get_process_and_check_authorized(&pid, user).await?;
+        self.check_not_shredded(&pid).await?;
+
+        info!("Rotating data key of pid '{}'", &pid);
+        self.doc_api
+            .rotate_data_key(&pid)
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)
+    }
+
+    /// Wraps all data keys with the current master key, after the master key was rotated
+    pub(crate) async fn rewrap_data_keys(&self) -> Result<u64, LoggingServiceError> {
+        self.doc_api
+            .rewrap_data_keys()
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)
+    }
+
+    /// Stores the global retention policy, which applies to all processes without own policy
+    pub(crate) async fn set_global_retention_policy(
+        &self,
+        policy: Option<&RetentionPolicy>,
+    ) -> Result<(), LoggingServiceError> {
+        self.db
+            .set_retention_policy(None, policy)
+            .await
+            .map_err(|e| LoggingServiceError::DatabaseError {
+                source: e.into(),
+                description: "Setting global retention policy failed".to_string(),
+            })
+    }
+
+    /// Moves documents logged in the months ending at least `older_than_days` ago to the archive
+    pub(crate) async fn archive_documents(
+        &self,
+        older_than_days: u32,
+    ) -> Result<u64, LoggingServiceError> {
+        self.doc_api
+            .archive_documents(older_than_days, self.cert_util.as_ref())
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)
+    }
+
+    /// Purges the payload of all documents, whose retention period is over
+    pub(crate) async fn expire_documents(&self) -> Result<u64, LoggingServiceError> {
+        self.doc_api
+            .expire_documents()
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)
+    }
+
+    /// Verifies the hash chain over all documents of the process `pid`
+    pub(crate) async fn verify_chain(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+    ) -> Result<ChainVerification, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check if process exists and if the user is authorized to access the process
+        self.get_process_and_check_authorized(&pid, user).await?;
+
+        self.doc_api
+            .verify_chain(ChClaims::new(user), pid)
+            .await
+            .map_err(|e| {
+                error!("Error while verifying hash chain: {:?}", e);
+                LoggingServiceError::DocumentServiceError(e)
+            })
+    }
+
+    /// Returns a proof that the document `id` is included in the published Merkle roots
+    pub(crate) async fn inclusion_proof(
+        &self,
+        ch_claims: ChClaims,
+        pid: String,
+        id: String,
+    ) -> Result<InclusionProof, LoggingServiceError> {
+        trace!("...user '{}'", &ch_claims.client_id);
+        let user = &ch_claims.client_id;
+
+        // Check if process exists and if the user is authorized to access the process
+        self.get_process_and_check_authorized(&pid, user).await?;
+
+        self.doc_api
+            .get_inclusion_proof(ChClaims::new(user), pid, id)
+            .await
+            .map_err(|e| {
+                error!("Error while creating inclusion proof: {:?}", e);
+                LoggingServiceError::DocumentServiceError(e)
+            })
+    }
+
+    /// Publishes signed Merkle roots over all documents, which are not yet covered by a root
+    pub(crate) async fn publish_merkle_roots(&self) -> Result<(), LoggingServiceError> {
+        self.doc_api
+            .publish_merkle_roots(self.cert_util.as_ref())
+            .await
+            .map_err(LoggingServiceError::DocumentServiceError)
+    }
+
+    /// Creates the signed receipt for a stored document
+    fn sign_receipt(
+        &self,
+        doc_receipt: DocumentReceipt,
+        payload: String,
+        digest: Option<ReceiptDigest>,
+    ) -> Result<Receipt, LoggingServiceError> {
+        debug!("Creating receipt...");
+        let transaction = DataTransaction {
+            timestamp: doc_receipt.timestamp,
+            process_id: doc_receipt.pid,
+            document_id: doc_receipt.doc_id,
+            payload: digest.is_none().then_some(payload),
+            digest,
+            client_id: self
+                .cert_util
+                .ski_aki()
+                .map_err(|e| LoggingServiceError::CertUtilError(e.to_string()))?
+                .to_string(),
+            clearing_house_version: env!("CARGO_PKG_VERSION").to_string(),
+            document_hash: doc_receipt.hash,
+        };
+        debug!("...done. Signing receipt...");
+        transaction
+            .sign_jsonwebtoken(self.cert_util.as_ref())
+            .map_err(|e| LoggingServiceError::DatabaseError {
+                source: e.into(),
+                description: "Issue during signing".to_string(),
+            })
+    }
+
+    /// Signs the receipt of the message `doc`, whose message id was `logged` before in the process.
+    /// Retries of a message get the receipt of the document logged first, messages with different
+    /// content are rejected.
+    fn sign_duplicate_receipt(
+        &self,
+        doc: &Document<String>,
+        logged: &Document<String>,
+    ) -> Result<Receipt, LoggingServiceError> {
+        let message_id = logged.content.header.id.clone().unwrap_or_default();
+        let Some(payload) = logged.content.payload.clone() else {
+            return Err(LoggingServiceError::DuplicateMessage(format!(
+                "Message '{message_id}' was logged before, its payload is not available anymore"
+            )));
+        };
+        if !logged.has_same_content(doc) {
+            return Err(LoggingServiceError::DuplicateMessage(format!(
+                "Message '{message_id}' was logged before with different content"
+            )));
+        }
+
+        info!("Message '{message_id}' was logged before, returning its receipt");
+        let payload_bytes = logged
+            .content
+            .payload_bytes()
+            .map_err(|e| LoggingServiceError::InvalidPayload(e.to_string()))?
+            .unwrap_or_default();
+        let digest = ReceiptDigest::new(self.receipt_mode, &logged.content, &payload_bytes)?;
+        let doc_receipt = DocumentReceipt::new(
+            logged.ts,
+            &logged.pid,
+            &logged.id.to_string(),
+            logged.hash.as_deref(),
+        );
+        self.sign_receipt(doc_receipt, payload, digest)
+    }
+
+    /// Returns the retention of the process `pid`
+    async fn get_retention(&self, pid: &str) -> Result<Retention, LoggingServiceError> {
+        self.db.get_retention(pid).await.map_err(|e| {
+            error!("Error while getting retention of pid '{}': {}", pid, e);
+            LoggingServiceError::DatabaseError {
+                source: e.into(),
+                description: "Getting retention failed".to_string(),
+            }
+        })
+    }
+
+    /// Returns the expiry of `docs` of the process `pid` by their message id
+    async fn get_expiry(
+        &self,
+        pid: &str,
+        docs: &[Document<String>],
+    ) -> Result<std::collections::BTreeMap<String, String>, LoggingServiceError> {
+        let retention = self.get_retention(pid).await?;
+
+        Ok(docs
+            .iter()
+            .filter_map(|d| {
+                let expires_at = d
+                    .expired_at
+                    .or_else(|| retention.expires_at(d.ts.to_utc()))?;
+                Some((d.content.header.id.clone()?, expires_at.to_rfc3339()))
+            })
+            .collect())
+    }
+
     /// Checks if the given pid is the default pid
     fn check_for_default_pid(pid: &str) -> Result<(), LoggingServiceError> {
         // Check for default process id
@@ -383,6 +1180,83 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         }
     }
 
+    /// Records the consent of `user` to erase or shred `process` and returns the consents of the
+    /// current owners together with the number of required consents. The static process owner
+    /// is owner of every process and does not take part in the quorum, unless it is the only
+    /// owner.
+    async fn add_erasure_consent(
+        &self,
+        process: &Process,
+        user: &str,
+    ) -> Result<(Vec<String>, usize), LoggingServiceError> {
+        let voters: Vec<&String> = match &self.static_process_owner {
+            Some(static_process_owner)
+                if process.owners.iter().any(|o| o != static_process_owner) =>
+            {
+                process
+                    .owners
+                    .iter()
+                    .filter(|o| *o != static_process_owner)
+                    .collect()
+            }
+            _ => process.owners.iter().collect(),
+        };
+
+        // Only consents of current owners count
+        let consents: Vec<String> = self
+            .db
+            .add_deletion_consent(&process.id, user)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Error while storing deletion consent for pid '{}': {}",
+                    &process.id, e
+                );
+                LoggingServiceError::DatabaseError {
+                    source: e.into(),
+                    description: "Storing deletion consent failed".to_string(),
+                }
+            })?
+            .into_iter()
+            .filter(|c| voters.contains(&c))
+            .collect();
+        let required = self
+            .deletion_quorum
+            .map_or(voters.len(), |q| q.clamp(1, voters.len()));
+
+        Ok((consents, required))
+    }
+
+    async fn get_shred_certificate(
+        &self,
+        pid: &str,
+    ) -> Result<Option<ShredCertificate>, LoggingServiceError> {
+        self.db.get_shred_certificate(pid).await.map_err(|e| {
+            error!(
+                "Error while getting shred certificate of pid '{}': {}",
+                pid, e
+            );
+            LoggingServiceError::DatabaseError {
+                source: e.into(),
+                description: "Getting shred certificate failed".to_string(),
+            }
+        })
+    }
+
+    /// Rejects access to the documents of the process `pid` after it was shredded
+    async fn check_not_shredded(&self, pid: &str) -> Result<(), LoggingServiceError> {
+        match self.get_shred_certificate(pid).await? {
+            Some(certificate) => {
+                warn!("Access to shredded pid '{}' rejected", pid);
+                Err(LoggingServiceError::ProcessShredded {
+                    pid: certificate.pid,
+                    shredded_at: certificate.shredded_at,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
     /// Checks if a process exists and the user is authorized to access the process
     async fn get_process_and_check_authorized(
         &self,
@@ -409,3 +1283,330 @@ impl<T: ProcessStore + Send + Sync, S: DocumentStore + Send + Sync> LoggingServi
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "memory")]
+    use super::*;
+    #[cfg(feature = "memory")]
+    use crate::db::memory_store::{MemoryDocumentStore, MemoryProcessStore};
+    #[cfg(feature = "memory")]
+    use crate::model::ids::message::IdsHeader;
+    #[cfg(feature = "memory")]
+    use crate::test_util::{memory_db, service};
+
+    /// Logging service over an empty in-memory database
+    #[cfg(feature = "memory")]
+    async fn logging_service(
+        static_process_owner: Option<&str>,
+        deletion_quorum: Option<usize>,
+    ) -> LoggingService<MemoryProcessStore, MemoryDocumentStore> {
+        logging_service_over(
+            &memory_db(&[]).await,
+            MemoryProcessStore::new,
+            static_process_owner,
+            deletion_quorum,
+        )
+    }
+
+    /// Logging service over `db` with the process store created by `process_store`
+    #[cfg(feature = "memory")]
+    fn logging_service_over<P: ProcessStore>(
+        db: &Arc<crate::db::memory_store::MemoryDb>,
+        process_store: impl FnOnce(Arc<crate::db::memory_store::MemoryDb>) -> P,
+        static_process_owner: Option<&str>,
+        deletion_quorum: Option<usize>,
+    ) -> LoggingService<P, MemoryDocumentStore> {
+        let cert_util = crate::test_util::cert_util();
+        LoggingService::new(
+            process_store(db.clone()),
+            Arc::new(service(db, None)),
+            Arc::new(cert_util),
+            "issuer".to_string(),
+            static_process_owner.map(ToString::to_string),
+            ReceiptMode::default(),
+            deletion_quorum,
+        )
+    }
+
+    #[cfg(feature = "memory")]
+    fn owner_list(owners: &[&str]) -> IdsMessage<OwnerList> {
+        IdsMessage {
+            header: IdsHeader::default(),
+            payload: Some(OwnerList {
+                owners: owners.iter().map(ToString::to_string).collect(),
+            }),
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        }
+    }
+
+    #[cfg(feature = "memory")]
+    async fn consent(
+        service: &LoggingService<MemoryProcessStore, MemoryDocumentStore>,
+        user: &str,
+    ) -> DeletionStatus {
+        service
+            .delete_process(ChClaims::new(user), "pid".to_string())
+            .await
+            .expect("Consenting")
+    }
+
+    #[cfg(feature = "memory")]
+    fn assert_pending(status: &DeletionStatus, expected: &[&str], expected_required: usize) {
+        match status {
+            DeletionStatus::Pending { consents, required } => {
+                assert_eq!(consents, expected);
+                assert_eq!(*required, expected_required);
+            }
+            s => panic!("Expected pending erasure, got {s:?}"),
+        }
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn erasure_quorum_excludes_static_owner() {
+        let service = logging_service(Some("ch"), None).await;
+        service
+            .create_process(ChClaims::new("a"), owner_list(&["b"]), "pid".to_string())
+            .await
+            .expect("Creating process");
+
+        assert_pending(&consent(&service, "a").await, &["a"], 2);
+        // The static process owner is owner of every process, its consent does not count
+        assert_pending(&consent(&service, "ch").await, &["a"], 2);
+        assert!(matches!(
+            consent(&service, "b").await,
+            DeletionStatus::Deleted { .. }
+        ));
+        let tombstone = service
+            .get_tombstone(ChClaims::new("a"), "pid".to_string())
+            .await
+            .expect("Reading tombstone");
+        assert_eq!(tombstone.consents, vec!["a", "b"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn erasure_quorum_is_clamped_to_owners() {
+        let service = logging_service(Some("ch"), Some(5)).await;
+        service
+            .create_process(ChClaims::new("a"), owner_list(&[]), "pid".to_string())
+            .await
+            .expect("Creating process");
+
+        // The quorum cannot exceed the owners besides the static process owner
+        assert!(matches!(
+            consent(&service, "a").await,
+            DeletionStatus::Deleted { .. }
+        ));
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn owner_removal_blocked_while_erasure_pending() {
+        let service = logging_service(None, None).await;
+        service
+            .create_process(
+                ChClaims::new("a"),
+                owner_list(&["b", "c"]),
+                "pid".to_string(),
+            )
+            .await
+            .expect("Creating process");
+        service
+            .change_owners(
+                ChClaims::new("a"),
+                owner_list(&["c"]),
+                "pid".to_string(),
+                OwnerAction::Remove,
+            )
+            .await
+            .expect("Removing owner without pending erasure");
+
+        assert_pending(&consent(&service, "a").await, &["a"], 2);
+        // Removing "b" would let "a" erase the process alone
+        assert!(matches!(
+            service
+                .change_owners(
+                    ChClaims::new("a"),
+                    owner_list(&["b"]),
+                    "pid".to_string(),
+                    OwnerAction::Remove,
+                )
+                .await,
+            Err(LoggingServiceError::InvalidOwnerChange(_))
+        ));
+        assert!(matches!(
+            service
+                .change_owners(
+                    ChClaims::new("a"),
+                    owner_list(&["d"]),
+                    "pid".to_string(),
+                    OwnerAction::Transfer,
+                )
+                .await,
+            Err(LoggingServiceError::InvalidOwnerChange(_))
+        ));
+
+        // Added owners raise the quorum
+        service
+            .change_owners(
+                ChClaims::new("a"),
+                owner_list(&["d"]),
+                "pid".to_string(),
+                OwnerAction::Add,
+            )
+            .await
+            .expect("Adding owner");
+        assert_pending(&consent(&service, "b").await, &["a", "b"], 3);
+        assert!(matches!(
+            consent(&service, "d").await,
+            DeletionStatus::Deleted { .. }
+        ));
+    }
+
+    /// Process store, which fails to change the owners of processes
+    #[cfg(feature = "memory")]
+    struct FailingOwnerUpdates(MemoryProcessStore);
+
+    #[cfg(feature = "memory")]
+    impl ProcessStore for FailingOwnerUpdates {
+        async fn get_processes(&self) -> anyhow::Result<Vec<Process>> {
+            self.0.get_processes().await
+        }
+
+        async fn delete_process(&self, pid: &str, tombstone: &Tombstone) -> anyhow::Result<bool> {
+            self.0.delete_process(pid, tombstone).await
+        }
+
+        async fn add_deletion_consent(
+            &self,
+            pid: &str,
+            owner: &str,
+        ) -> anyhow::Result<Vec<String>> {
+            self.0.add_deletion_consent(pid, owner).await
+        }
+
+        async fn get_deletion_consents(&self, pid: &str) -> anyhow::Result<Vec<String>> {
+            self.0.get_deletion_consents(pid).await
+        }
+
+        async fn get_tombstone(&self, pid: &str) -> anyhow::Result<Option<Tombstone>> {
+            self.0.get_tombstone(pid).await
+        }
+
+        async fn shred_process(
+            &self,
+            pid: &str,
+            certificate: &ShredCertificate,
+        ) -> anyhow::Result<bool> {
+            self.0.shred_process(pid, certificate).await
+        }
+
+        async fn get_shred_certificate(
+            &self,
+            pid: &str,
+        ) -> anyhow::Result<Option<ShredCertificate>> {
+            self.0.get_shred_certificate(pid).await
+        }
+
+        async fn exists_process(&self, pid: &str) -> anyhow::Result<bool> {
+            self.0.exists_process(pid).await
+        }
+
+        async fn get_process(&self, pid: &str) -> anyhow::Result<Option<Process>> {
+            self.0.get_process(pid).await
+        }
+
+        async fn store_process(&self, process: Process) -> anyhow::Result<()> {
+            self.0.store_process(process).await
+        }
+
+        async fn get_processes_for_owner(
+            &self,
+            owner: &str,
+            page: u64,
+            size: u64,
+        ) -> anyhow::Result<Vec<crate::model::process::ProcessInfo>> {
+            self.0.get_processes_for_owner(owner, page, size).await
+        }
+
+        async fn update_owners(
+            &self,
+            _pid: &str,
+            _added: &[String],
+            _removed: &[String],
+        ) -> anyhow::Result<()> {
+            anyhow::bail!("Owners cannot be changed")
+        }
+
+        async fn set_retention_policy(
+            &self,
+            pid: Option<&str>,
+            policy: Option<&RetentionPolicy>,
+        ) -> anyhow::Result<()> {
+            self.0.set_retention_policy(pid, policy).await
+        }
+
+        async fn get_retention(&self, pid: &str) -> anyhow::Result<Retention> {
+            self.0.get_retention(pid).await
+        }
+
+        async fn close_process(
+            &self,
+            pid: &str,
+            closed_at: chrono::DateTime<chrono::Utc>,
+        ) -> anyhow::Result<()> {
+            self.0.close_process(pid, closed_at).await
+        }
+
+        async fn ping(&self) -> anyhow::Result<()> {
+            self.0.ping().await
+        }
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn failed_owner_change_is_not_logged() {
+        let db = memory_db(&[]).await;
+        let service = logging_service_over(
+            &db,
+            |db| FailingOwnerUpdates(MemoryProcessStore::new(db)),
+            None,
+            None,
+        );
+        service
+            .create_process(ChClaims::new("a"), owner_list(&["b"]), "pid".to_string())
+            .await
+            .expect("Creating process");
+        let chain_head = || async {
+            MemoryDocumentStore::new(db.clone())
+                .get_chain_head("pid")
+                .await
+                .expect("Reading chain head")
+        };
+        let head = chain_head().await;
+
+        assert!(matches!(
+            service
+                .change_owners(
+                    ChClaims::new("a"),
+                    owner_list(&["c"]),
+                    "pid".to_string(),
+                    OwnerAction::Add,
+                )
+                .await,
+            Err(LoggingServiceError::DatabaseError { .. })
+        ));
+        // The change is neither applied nor logged
+        let process = MemoryProcessStore::new(db.clone())
+            .get_process("pid")
+            .await
+            .expect("Reading process")
+            .expect("Process exists");
+        assert_eq!(process.owners, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(chain_head().await, head);
+    }
+}
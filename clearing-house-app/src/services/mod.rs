@@ -6,7 +6,9 @@
 //!
 use crate::model::document::Document;
 
+pub(crate) mod dat_cache;
 pub(crate) mod document_service;
+pub(crate) mod health_service;
 pub(crate) mod logging_service;
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
@@ -14,6 +16,7 @@ pub struct DocumentReceipt {
     pub timestamp: i64,
     pub pid: String,
     pub doc_id: String,
+    pub hash: Option<String>,
 }
 
 impl DocumentReceipt {
@@ -21,15 +24,26 @@ impl DocumentReceipt {
         timestamp: chrono::DateTime<chrono::Local>,
         pid: &str,
         doc_id: &str,
+        hash: Option<&str>,
     ) -> DocumentReceipt {
         DocumentReceipt {
             timestamp: timestamp.timestamp(),
             pid: pid.to_string(),
             doc_id: doc_id.to_string(),
+            hash: hash.map(ToString::to_string),
         }
     }
 }
 
+/// Outcome of logging a document, whose message id (`@id`) might have been logged before
+#[derive(Clone, Debug)]
+pub enum Appended {
+    /// The document was appended to the hash chain of its process
+    Created(DocumentReceipt),
+    /// The message id was logged before in the process, contains the decrypted document
+    Duplicate(Box<Document<String>>),
+}
+
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub struct QueryResult {
     pub date_from: i64,
@@ -38,6 +52,13 @@ pub struct QueryResult {
     pub size: Option<i32>,
     pub order: String,
     pub documents: Vec<Document<String>>,
+    /// Number of documents in the date window
+    pub total: u64,
+    pub total_pages: u64,
+    /// Whether further documents follow this page
+    pub has_next: bool,
+    /// Opaque cursor continuing after the last document, `None` if there are no further documents
+    pub next_cursor: Option<String>,
 }
 
 impl QueryResult {
@@ -56,6 +77,65 @@ impl QueryResult {
             size,
             order,
             documents,
+            total: 0,
+            total_pages: 0,
+            has_next: false,
+            next_cursor: None,
         }
     }
 }
+
+/// Result of walking the hash chain of a process
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChainVerification {
+    /// All documents of the process form a single, unmodified chain
+    Intact { length: usize, head: Option<String> },
+    /// The chain is broken at `position` (0-based) by the document `doc_id`
+    Broken {
+        position: usize,
+        doc_id: String,
+        reason: ChainBreak,
+    },
+}
+
+/// Reason why the hash chain of a process is broken
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainBreak {
+    /// The stored hash does not match the content of the document
+    ContentModified,
+    /// The document does not link to its predecessor, e.g. because a document was deleted
+    MissingPredecessor,
+    /// More than one document links to the same predecessor
+    Fork,
+}
+
+/// Proof that a document is included in a published Merkle root
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+pub struct InclusionProof {
+    pub doc_id: String,
+    /// Hash of the document, which is the leaf of the process tree
+    pub leaf: String,
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub audit_path: Vec<String>,
+    /// Hex encoded root of the process tree
+    pub root: String,
+    /// Process root signed by the Clearing House
+    pub signed_root: String,
+    /// Proof that the process root is included in the global root
+    pub global: Option<GlobalInclusionProof>,
+}
+
+/// Proof that a process root is included in a published global Merkle root
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+pub struct GlobalInclusionProof {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub audit_path: Vec<String>,
+    /// Hex encoded global root
+    pub root: String,
+    /// Global root signed by the Clearing House
+    pub signed_root: String,
+}
@@ -0,0 +1,81 @@
+//! Fixtures shared by the unit tests
+
+use crate::model::document::Document;
+use crate::model::ids::message::{IdsHeader, IdsMessage};
+
+#[cfg(feature = "postgres")]
+#[path = "../tests/common/postgres.rs"]
+mod postgres;
+#[cfg(feature = "postgres")]
+pub(crate) use postgres::start_postgres;
+
+/// Base64 encoded master key for encryption at rest
+pub(crate) const TEST_MASTER_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+/// Another master key, e.g. for rotating `TEST_MASTER_KEY`
+pub(crate) const TEST_MASTER_KEY_2: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+/// Key ring of the master keys `keys`, separated by commas
+pub(crate) fn key_ring(keys: &str) -> crate::model::crypto::KeyRing {
+    crate::model::crypto::KeyRing::parse(keys).expect("Valid keys")
+}
+
+/// Certificate of the test connector
+#[cfg(feature = "memory")]
+pub(crate) fn cert_util() -> ids_daps_cert::CertUtil {
+    ids_daps_cert::CertUtil::load_certificate(
+        std::path::Path::new("keys/connector-certificate.p12"),
+        "Password1",
+    )
+    .expect("Loading certificate")
+}
+
+/// Message with a default header and `payload`
+pub(crate) fn message(payload: &str) -> IdsMessage<String> {
+    IdsMessage {
+        header: IdsHeader::default(),
+        payload: Some(payload.to_string()),
+        payload_type: None,
+        payload_encoding: None,
+        raw_header: None,
+    }
+}
+
+/// Unlinked document of the process `pid` with `payload`
+pub(crate) fn document(pid: &str, payload: &str) -> Document<String> {
+    Document::new(pid.to_string(), message(payload))
+}
+
+/// In-memory database containing the processes `pids` without owners
+#[cfg(feature = "memory")]
+pub(crate) async fn memory_db(pids: &[&str]) -> std::sync::Arc<crate::db::memory_store::MemoryDb> {
+    use crate::db::ProcessStore;
+
+    let db = std::sync::Arc::new(crate::db::memory_store::MemoryDb::default());
+    let process_store = crate::db::memory_store::MemoryProcessStore::new(db.clone());
+    for pid in pids {
+        process_store
+            .store_process(crate::model::process::Process::new(
+                (*pid).to_string(),
+                vec![],
+            ))
+            .await
+            .expect("Storing process");
+    }
+    db
+}
+
+/// Document service without archive over `db`, encrypting documents with `keys`
+#[cfg(feature = "memory")]
+pub(crate) fn service(
+    db: &std::sync::Arc<crate::db::memory_store::MemoryDb>,
+    keys: Option<crate::model::crypto::KeyRing>,
+) -> crate::services::document_service::DocumentService<
+    crate::db::memory_store::MemoryDocumentStore,
+    crate::db::object_store::LocalObjectStore,
+> {
+    crate::services::document_service::DocumentService::new(
+        crate::db::memory_store::MemoryDocumentStore::new(db.clone()),
+        None,
+        keys,
+    )
+}
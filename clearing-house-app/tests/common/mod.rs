@@ -1,23 +1,42 @@
+#![allow(dead_code)] // Not every test uses all helpers
+
+mod postgres;
+pub use postgres::start_postgres;
+
 pub fn build_multipart_body<T: serde::Serialize>(
     client: &reqwest::Client,
     method: http::Method,
     url: impl reqwest::IntoUrl,
-    msg: clearing_house_app::model::ids::message::IdsMessage<T>
+    msg: clearing_house_app::model::ids::message::IdsMessage<T>,
 ) -> http::Request<reqwest::Body> {
     let header = serde_json::to_vec_pretty(&msg.header).unwrap();
-    let header_part = reqwest::multipart::Part::bytes(header)
-        .mime_str("application/json")
-        .unwrap();
-
-    let mut form = reqwest::multipart::Form::new()
-        .part("header", header_part);
 
     // Handle optional payload
-    if let Some(payload) = msg.payload {
+    let payload = msg.payload.map(|payload| {
         let serialized_payload = serde_json::to_vec_pretty(&payload).unwrap();
         tracing::trace!("Payload: {:?}", &serialized_payload);
+        serialized_payload
+    });
 
-        let payload_part = reqwest::multipart::Part::bytes(serialized_payload)
+    build_raw_multipart_body(client, method, url, header, payload)
+}
+
+/// Builds a multipart request with the `header` and `payload` parts sent exactly as given
+pub fn build_raw_multipart_body(
+    client: &reqwest::Client,
+    method: http::Method,
+    url: impl reqwest::IntoUrl,
+    header: Vec<u8>,
+    payload: Option<Vec<u8>>,
+) -> http::Request<reqwest::Body> {
+    let header_part = reqwest::multipart::Part::bytes(header)
+        .mime_str("application/json")
+        .unwrap();
+
+    let mut form = reqwest::multipart::Form::new().part("header", header_part);
+
+    if let Some(payload) = payload {
+        let payload_part = reqwest::multipart::Part::bytes(payload)
             .mime_str("application/json")
             .unwrap();
 
@@ -25,27 +44,34 @@ pub fn build_multipart_body<T: serde::Serialize>(
     }
 
     // Build request
-    client.request(method, url).multipart(form)
+    client
+        .request(method, url)
+        .multipart(form)
         .build()
         .unwrap()
         .try_into()
         .unwrap()
 }
 
-pub async fn parse_multipart_payload<T: serde::de::DeserializeOwned + std::fmt::Debug>(response: http::Response<axum::body::Body>) -> clearing_house_app::model::ids::message::IdsMessage<T> {
+pub async fn parse_multipart_payload<T: serde::de::DeserializeOwned + std::fmt::Debug>(
+    response: http::Response<axum::body::Body>,
+) -> clearing_house_app::model::ids::message::IdsMessage<T> {
     use std::io::Read;
-    
-    let boundary = response.headers().get(reqwest::header::CONTENT_TYPE)
+
+    let boundary = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
         .and_then(|ct| ct.to_str().ok())
         .and_then(|ct| ct.split("boundary=").last())
         .expect("Failed to parse boundary")
         .to_string();
-    
+
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
 
-    let mut multipart: multipart::server::Multipart<&[u8]> = multipart::server::Multipart::with_body(body.as_ref(), boundary);
+    let mut multipart: multipart::server::Multipart<&[u8]> =
+        multipart::server::Multipart::with_body(body.as_ref(), boundary);
     let mut header: Option<clearing_house_app::model::ids::message::IdsHeader> = None;
     let mut payload: Option<T> = None;
 
@@ -70,22 +96,37 @@ pub async fn parse_multipart_payload<T: serde::de::DeserializeOwned + std::fmt::
         header: header.expect("Header is required"),
         payload,
         payload_type: None,
+        payload_encoding: None,
+        raw_header: None,
     }
 }
 
-
-pub async fn create_security_token(daps_client: &ids_daps_client::ReqwestDapsClient) -> Result<clearing_house_app::model::ids::SecurityToken, ids_daps_client::DapsError> {
+pub async fn create_security_token(
+    daps_client: &ids_daps_client::ReqwestDapsClient,
+) -> Result<clearing_house_app::model::ids::SecurityToken, ids_daps_client::DapsError> {
     let token_response = daps_client.request_dat().await?;
 
     Ok(clearing_house_app::model::ids::SecurityToken {
         type_message: clearing_house_app::model::ids::MessageType::DAPSToken,
-        id: Some(format!("https://w3id.org/idsa/autogen/dynamicAttributeToken/{}", clearing_house_app::util::new_uuid())),
-        token_format: Some(clearing_house_app::model::ids::InfoModelComplexId::new("https://w3id.org/idsa/code/JWT".to_string()).into()),
+        id: Some(format!(
+            "https://w3id.org/idsa/autogen/dynamicAttributeToken/{}",
+            clearing_house_app::util::new_uuid()
+        )),
+        token_format: Some(
+            clearing_house_app::model::ids::InfoModelComplexId::new(
+                "https://w3id.org/idsa/code/JWT".to_string(),
+            )
+            .into(),
+        ),
         token_value: token_response,
     })
 }
 
-pub async fn start_daps() -> (testcontainers::ContainerAsync<testcontainers::GenericImage>, String, String) {
+pub async fn start_daps() -> (
+    testcontainers::ContainerAsync<testcontainers::GenericImage>,
+    String,
+    String,
+) {
     use testcontainers::runners::AsyncRunner;
 
     // Starting the test DAPS
@@ -112,25 +153,20 @@ pub async fn start_daps() -> (testcontainers::ContainerAsync<testcontainers::Gen
     (container, certs_url, token_url)
 }
 
-pub async fn start_postgres() -> (testcontainers::ContainerAsync<testcontainers_modules::postgres::Postgres>, String) {
-    use testcontainers::runners::AsyncRunner;
+/// Starts the database for the tests and returns its `database_url`. Uses the in-memory backend
+/// with the `memory` feature, an in-memory SQLite database with the `sqlite` feature, otherwise a
+/// Postgres container, which must be kept alive.
+pub async fn start_database() -> (
+    Option<testcontainers::ContainerAsync<testcontainers_modules::postgres::Postgres>>,
+    String,
+) {
+    if cfg!(feature = "memory") {
+        return (None, "memory://".to_string());
+    }
+    if cfg!(feature = "sqlite") {
+        return (None, "sqlite::memory:".to_string());
+    }
 
-    let postgres_instance = testcontainers_modules::postgres::Postgres::default()
-        .start()
-        .await
-        .expect("Failed to start Postgres container");
-
-    let connection_string = format!(
-        "postgres://postgres:postgres@{}:{}/postgres",
-        postgres_instance
-            .get_host()
-            .await
-            .expect("Failed to get host"),
-        postgres_instance
-            .get_host_port_ipv4(5432)
-            .await
-            .expect("Failed to get port")
-    );
-
-    (postgres_instance, connection_string)
+    let (postgres_instance, connection_string) = start_postgres().await;
+    (Some(postgres_instance), connection_string)
 }
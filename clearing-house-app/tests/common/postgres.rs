@@ -0,0 +1,30 @@
+//! Postgres container for the integration tests, also used by the unit tests of the Postgres
+//! stores
+
+/// Starts a Postgres container and returns it together with its `database_url`. The container
+/// is stopped, when it is dropped.
+pub async fn start_postgres() -> (
+    testcontainers::ContainerAsync<testcontainers_modules::postgres::Postgres>,
+    String,
+) {
+    use testcontainers::runners::AsyncRunner;
+
+    let postgres_instance = testcontainers_modules::postgres::Postgres::default()
+        .start()
+        .await
+        .expect("Failed to start Postgres container");
+
+    let connection_string = format!(
+        "postgres://postgres:postgres@{}:{}/postgres",
+        postgres_instance
+            .get_host()
+            .await
+            .expect("Failed to get host"),
+        postgres_instance
+            .get_host_port_ipv4(5432)
+            .await
+            .expect("Failed to get port")
+    );
+
+    (postgres_instance, connection_string)
+}
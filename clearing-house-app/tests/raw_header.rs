@@ -0,0 +1,191 @@
+mod common;
+
+use axum::http::StatusCode;
+use clearing_house_app::model::ids::message::{IdsHeader, IdsMessage};
+use clearing_house_app::model::ids::{IdsQueryResult, InfoModelId, MessageType};
+use clearing_house_app::model::process::{DataTransaction, OwnerList, Receipt};
+use clearing_house_app::util::new_uuid;
+use tower::ServiceExt;
+
+fn header(
+    type_message: MessageType,
+    security_token: clearing_house_app::model::ids::SecurityToken,
+) -> IdsHeader {
+    IdsHeader {
+        context: Some(std::collections::HashMap::from([
+            ("ids".to_string(), "https://w3id.org/idsa/core/".to_string()),
+            (
+                "idsc".to_string(),
+                "https://w3id.org/idsa/code/".to_string(),
+            ),
+        ])),
+        type_message,
+        id: Some(new_uuid()),
+        model_version: "test".to_string(),
+        security_token: Some(security_token),
+        issuer_connector: InfoModelId::new("test-connector".to_string()),
+        sender_agent: InfoModelId::new("https://w3id.org/idsa/core/ClearingHouse".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Sets the expiry of the JWT `token` an hour into the past. The test DAPS does not issue expired
+/// tokens, so the signature of the returned token does not match anymore.
+fn expire_token(token: &str) -> String {
+    use base64::Engine;
+
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let parts = token.split('.').collect::<Vec<_>>();
+    assert_eq!(parts.len(), 3, "DAPS token is a JWT");
+
+    let mut claims: serde_json::Value =
+        serde_json::from_slice(&engine.decode(parts[1]).unwrap()).unwrap();
+    claims["exp"] = serde_json::json!(chrono::Utc::now().timestamp() - 3600);
+    let claims = engine.encode(serde_json::to_vec(&claims).unwrap());
+
+    format!("{}.{claims}.{}", parts[0], parts[2])
+}
+
+#[tokio::test]
+async fn query_raw_header() {
+    let cert_util = ids_daps_cert::CertUtil::load_certificate(
+        std::path::Path::new("keys/connector-certificate.p12"),
+        "Password1",
+    )
+    .expect("The cert_util should be already ready");
+
+    // Starting the test DAPS and creating the DAPS client for executing requests against the Clearing House Server
+    let (_daps_container, certs_url, token_url) = common::start_daps().await;
+    let daps_client = ids_daps_client::ReqwestDapsClient::from_cert_util(
+        &cert_util,
+        "idsc:IDS_CONNECTORS_ALL",
+        &certs_url,
+        &token_url,
+        300,
+    );
+
+    let client_id = cert_util.ski_aki().unwrap().to_string();
+
+    // Start database
+    let (_database_container, connection_string) = common::start_database().await;
+
+    #[allow(unsafe_code)] // Deprecated safe from rust edition 2024
+    unsafe {
+        std::env::set_var("CH_APP_DAPS_CERTS_URL", certs_url);
+        std::env::set_var("CH_APP_DAPS_TOKEN_URL", token_url);
+        std::env::set_var("CH_APP_CLEAR_DB", "false");
+        std::env::set_var("CH_APP_DATABASE_URL", connection_string);
+    }
+
+    let app = clearing_house_app::app().await.unwrap();
+    let client = reqwest::Client::new();
+
+    // Create a process
+    let pid = new_uuid();
+    let token = common::create_security_token(&daps_client)
+        .await
+        .expect("DAPS Token inserted");
+    let create_req = common::build_multipart_body(
+        &client,
+        http::Method::POST,
+        format!("http://0.0.0.0:8080/process/{pid}"),
+        IdsMessage {
+            header: header(MessageType::RequestMessage, token),
+            payload: Some(OwnerList {
+                owners: vec![client_id],
+            }),
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        },
+    );
+    let response = app.clone().oneshot(create_req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // ---------------------------------------------------------------------------------------------
+
+    // Log a message whose header has a field unknown to `IdsHeader` and its own formatting
+    let token = common::create_security_token(&daps_client)
+        .await
+        .expect("DAPS Token inserted");
+    let mut log_header = serde_json::to_value(header(MessageType::LogMessage, token)).unwrap();
+    log_header["custom:property"] = serde_json::json!({ "nested": [1, 2, 3] });
+    let raw_header = format!("{}\n", serde_json::to_string_pretty(&log_header).unwrap());
+
+    let log_req = common::build_raw_multipart_body(
+        &client,
+        http::Method::POST,
+        format!("http://0.0.0.0:8080/messages/log/{pid}"),
+        raw_header.clone().into_bytes(),
+        Some(br#"{"foo": "Hello World"}"#.to_vec()),
+    );
+    let log_response = app.clone().oneshot(log_req).await.unwrap();
+    assert_eq!(log_response.status(), StatusCode::CREATED);
+
+    let log_resp: IdsMessage<Receipt> = common::parse_multipart_payload(log_response).await;
+    let receipt = log_resp.payload.expect("Receipt is there");
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::PS512);
+    validation.insecure_disable_signature_validation();
+    validation.set_required_spec_claims::<&str>(&[]);
+    let transaction = jsonwebtoken::decode::<DataTransaction>(
+        receipt.data.as_str(),
+        &jsonwebtoken::DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .expect("Decoding receipt")
+    .claims;
+    let id = transaction.document_id;
+
+    // ---------------------------------------------------------------------------------------------
+
+    // The sender reads the header back exactly as it was sent
+    let token = common::create_security_token(&daps_client)
+        .await
+        .expect("DAPS Token inserted");
+    let query_req = common::build_multipart_body(
+        &client,
+        http::Method::POST,
+        format!("http://0.0.0.0:8080/messages/query/{pid}/{id}?raw_header=true"),
+        IdsMessage::<()> {
+            header: header(MessageType::QueryMessage, token.clone()),
+            payload: None,
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        },
+    );
+    let query_response = app.clone().oneshot(query_req).await.unwrap();
+    assert_eq!(query_response.status(), StatusCode::OK);
+
+    let query_resp: IdsMessage<IdsQueryResult<String>> =
+        common::parse_multipart_payload(query_response).await;
+    let documents = query_resp
+        .payload
+        .expect("IDS Query Result is there")
+        .documents;
+    assert_eq!(documents.len(), 1);
+    assert_eq!(
+        documents[0].raw_header.as_deref().map(str::as_bytes),
+        Some(raw_header.as_bytes())
+    );
+
+    // ---------------------------------------------------------------------------------------------
+
+    // An expired token is refused
+    let mut expired_token = token;
+    expired_token.token_value = expire_token(&expired_token.token_value);
+    let query_req = common::build_multipart_body(
+        &client,
+        http::Method::POST,
+        format!("http://0.0.0.0:8080/messages/query/{pid}/{id}?raw_header=true"),
+        IdsMessage::<()> {
+            header: header(MessageType::QueryMessage, expired_token),
+            payload: None,
+            payload_type: None,
+            payload_encoding: None,
+            raw_header: None,
+        },
+    );
+    let query_response = app.clone().oneshot(query_req).await.unwrap();
+    assert_eq!(query_response.status(), StatusCode::BAD_REQUEST);
+}